@@ -0,0 +1,28 @@
+//! Remembering the vulnerabilities package audits found, see `node_audit`.
+
+use std::sync::Mutex;
+
+use rust_i18n::t;
+
+use crate::terminal::print_separator;
+
+/// How many vulnerabilities each audited package manager reported, in the order they ran.
+static VULNERABILITIES: Mutex<Vec<(String, u64)>> = Mutex::new(Vec::new());
+
+/// Remember that the audit of `name` found `count` vulnerabilities.
+pub fn record(name: &str, count: u64) {
+    VULNERABILITIES.lock().unwrap().push((name.to_owned(), count));
+}
+
+/// Print how many vulnerabilities each audit found, if any audit ran.
+pub fn print_summary() {
+    let vulnerabilities = VULNERABILITIES.lock().unwrap();
+    if vulnerabilities.is_empty() {
+        return;
+    }
+
+    print_separator(t!("Vulnerabilities"));
+    for (name, count) in vulnerabilities.iter() {
+        println!("{name}: {count}");
+    }
+}
@@ -1,14 +1,20 @@
 //! Utilities for running commands and providing user-friendly error messages.
 
 use std::fmt::Display;
+use std::io;
 use std::process::Child;
-use std::process::{Command, ExitStatus, Output};
+use std::process::{Command, ExitStatus, Output, Stdio};
 
 use color_eyre::eyre;
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 
 use crate::error::TopgradeError;
+use crate::executor::{
+    apply_step_env, check_interrupted, output_prefix, prepare_background_child, prepare_child, pump_output,
+    silence_warning, TrackedChild,
+};
+use crate::terminal::{is_capturing, write_captured};
 
 use tracing::debug;
 
@@ -157,14 +163,26 @@ impl CommandExt for Command {
     type Child = Child;
 
     fn output_checked_with(&mut self, succeeded: impl Fn(&Output) -> Result<(), ()>) -> eyre::Result<Output> {
+        apply_step_env(self);
         let command = log(self);
 
         // This is where we implement `output_checked`, which is what we prefer to use instead of
-        // `output`, so we allow `Command::output` here.
+        // `output`, so we allow `Command::spawn` here. The stdio setup mirrors the defaults of
+        // `Command::output`; we spawn the child ourselves so that it can be killed if the step
+        // times out.
+        let foreground = prepare_child(self).with_context(|| format!("Failed to execute `{command}`"))?;
         #[allow(clippy::disallowed_methods)]
         let output = self
-            .output()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .and_then(|child| {
+                let _tracked = TrackedChild::new(child.id(), foreground);
+                child.wait_with_output()
+            })
             .with_context(|| format!("Failed to execute `{command}`"))?;
+        check_interrupted(foreground, output.status);
 
         if succeeded(&output).is_ok() {
             Ok(output)
@@ -192,13 +210,47 @@ impl CommandExt for Command {
     }
 
     fn status_checked_with(&mut self, succeeded: impl Fn(ExitStatus) -> Result<(), ()>) -> eyre::Result<()> {
+        apply_step_env(self);
         let command = log(self);
         let message = format!("Failed to execute `{command}`");
 
         // This is where we implement `status_checked`, which is what we prefer to use instead of
-        // `status`, so we allow `Command::status` here.
+        // `status`, so we allow `Command::spawn` here. Spawning and waiting is what
+        // `Command::status` does, but this way the child can be killed if the step times out.
+        //
+        // When the output of the current step is buffered, so is the output of the command.
+        let foreground = prepare_child(self).with_context(|| message.clone())?;
         #[allow(clippy::disallowed_methods)]
-        let status = self.status().with_context(|| message.clone())?;
+        let status = if is_capturing() {
+            self.stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .and_then(|child| {
+                    let _tracked = TrackedChild::new(child.id(), foreground);
+                    child.wait_with_output()
+                })
+                .map(|output| {
+                    write_captured(&output.stdout);
+                    write_captured(&output.stderr);
+                    output.status
+                })
+        } else if silence_warning().is_some() || output_prefix().is_some() {
+            self.stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .and_then(|mut child| {
+                    let _tracked = TrackedChild::new(child.id(), foreground);
+                    pump_output(&mut child, &command, silence_warning(), output_prefix().as_deref());
+                    child.wait()
+                })
+        } else {
+            self.spawn().and_then(|mut child| {
+                let _tracked = TrackedChild::new(child.id(), foreground);
+                child.wait()
+            })
+        }
+        .with_context(|| message.clone())?;
+        check_interrupted(foreground, status);
 
         if succeeded(status).is_ok() {
             Ok(())
@@ -212,6 +264,7 @@ impl CommandExt for Command {
     }
 
     fn spawn_checked(&mut self) -> eyre::Result<Self::Child> {
+        apply_step_env(self);
         let command = log(self);
         let message = format!("Failed to execute `{command}`");
 
@@ -224,6 +277,20 @@ impl CommandExt for Command {
     }
 }
 
+/// Like [`tokio::process::Command::output`] for `command`, but the child is tracked like the
+/// ones started through [`CommandExt`], so that it gets killed if the step times out.
+pub async fn async_output(mut command: Command) -> io::Result<Output> {
+    apply_step_env(&mut command);
+    log(&command);
+    prepare_background_child(&mut command)?;
+    let child = tokio::process::Command::from(command)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let _tracked = child.id().map(|pid| TrackedChild::new(pid, false));
+    child.wait_with_output().await
+}
+
 fn get_program_and_args(cmd: &Command) -> (String, String) {
     // We're not doing anything weird with commands that are invalid UTF-8 so this is fine.
     let program = cmd.get_program().to_string_lossy().into_owned();
@@ -1,29 +1,35 @@
 #![allow(dead_code)]
 
+use std::borrow::Cow;
 use std::fs::{write, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use std::{env, fmt, fs};
 
+use chrono::NaiveTime;
 use clap::{Parser, ValueEnum};
 use clap_complete::Shell;
-use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Context};
 use etcetera::base_strategy::BaseStrategy;
+use glob::{MatchOptions, Pattern};
 use indexmap::IndexMap;
 use merge::Merge;
 use regex::Regex;
 use regex_split::RegexSplit;
 use rust_i18n::t;
-use serde::Deserialize;
-use strum::{EnumIter, EnumString, IntoEnumIterator, VariantNames};
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, EnumIter, EnumString, IntoEnumIterator, VariantNames};
 use which_crate::which;
 
 use super::utils::editor;
 use crate::command::CommandExt;
+use crate::manifests::stable_hash;
 use crate::sudo::SudoKind;
-use crate::utils::string_prepend_str;
+use crate::terminal::print_warning;
+use crate::utils::{hostname, string_prepend_str};
 use tracing::{debug, error};
 
 // TODO: Add i18n to this. Tracking issue: https://github.com/topgrade-rs/topgrade/issues/859
@@ -44,9 +50,167 @@ macro_rules! str_value {
     };
 }
 
-pub type Commands = IndexMap<String, String>;
+pub type Commands = IndexMap<String, CustomCommand>;
 
-#[derive(ValueEnum, EnumString, VariantNames, Debug, Clone, PartialEq, Eq, Deserialize, EnumIter, Copy)]
+/// A remote machine to run Topgrade on, given either as a plain `[user@]host` or as a table
+/// with SSH options for that host.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum RemoteTopgrade {
+    Plain(String),
+    Table(RemoteTopgradeTable),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteTopgradeTable {
+    host: String,
+    proxy_jump: Option<String>,
+    port: Option<u16>,
+    identity_file: Option<String>,
+    ssh_arguments: Option<String>,
+}
+
+impl RemoteTopgrade {
+    /// The `[user@]host` to connect to.
+    pub fn host(&self) -> &str {
+        match self {
+            RemoteTopgrade::Plain(host) => host,
+            RemoteTopgrade::Table(table) => &table.host,
+        }
+    }
+
+    /// The SSH options specific to this host.
+    pub fn ssh_options(&self) -> Vec<String> {
+        let RemoteTopgrade::Table(table) = self else {
+            return Vec::new();
+        };
+
+        let mut options = Vec::new();
+        if let Some(proxy_jump) = &table.proxy_jump {
+            options.extend([String::from("-J"), proxy_jump.clone()]);
+        }
+        if let Some(port) = table.port {
+            options.extend([String::from("-p"), port.to_string()]);
+        }
+        if let Some(identity_file) = &table.identity_file {
+            options.extend([String::from("-i"), shellexpand::tilde(identity_file).into_owned()]);
+        }
+        if let Some(ssh_arguments) = &table.ssh_arguments {
+            options.extend(ssh_arguments.split_whitespace().map(String::from));
+        }
+
+        options
+    }
+}
+
+/// A custom command, given either as a plain string or as a table with more settings.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CustomCommand {
+    Plain(String),
+    Table(CustomCommandTable),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CustomCommandTable {
+    command: String,
+    cwd: Option<String>,
+    shell: Option<String>,
+    requires: Option<String>,
+    run_if: Option<String>,
+}
+
+impl CustomCommand {
+    /// The command line to run.
+    pub fn command(&self) -> &str {
+        match self {
+            CustomCommand::Plain(command) => command,
+            CustomCommand::Table(table) => &table.command,
+        }
+    }
+
+    /// The directory to run the command in, before `~` and environment variable expansion.
+    pub fn cwd(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Plain(_) => None,
+            CustomCommand::Table(table) => table.cwd.as_deref(),
+        }
+    }
+
+    /// The shell to run the command with instead of the default one.
+    pub fn shell(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Plain(_) => None,
+            CustomCommand::Table(table) => table.shell.as_deref(),
+        }
+    }
+
+    /// A binary that must be in `PATH` for the command to run.
+    pub fn requires(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Plain(_) => None,
+            CustomCommand::Table(table) => table.requires.as_deref(),
+        }
+    }
+
+    /// A command that must succeed for the command to run.
+    pub fn run_if(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Plain(_) => None,
+            CustomCommand::Table(table) => table.run_if.as_deref(),
+        }
+    }
+}
+
+/// A package manager Topgrade has no step for, given as a `[[custom_package_manager]]` entry.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CustomPackageManager {
+    name: String,
+    detect: String,
+    update: String,
+    shell: Option<String>,
+}
+
+impl CustomPackageManager {
+    /// The name of the step.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A command that succeeds when the package manager is installed.
+    pub fn detect(&self) -> &str {
+        &self.detect
+    }
+
+    /// The command that updates the packages.
+    pub fn update(&self) -> &str {
+        &self.update
+    }
+
+    /// The shell to run the commands with instead of the default one.
+    pub fn shell(&self) -> Option<&str> {
+        self.shell.as_deref()
+    }
+}
+
+#[derive(
+    ValueEnum,
+    EnumString,
+    VariantNames,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Serialize,
+    EnumIter,
+    AsRefStr,
+    Copy,
+)]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
@@ -61,6 +225,7 @@ pub enum Step {
     AutoCpufreq,
     Bin,
     Bob,
+    BrewBundle,
     BrewCask,
     BrewFormula,
     Bun,
@@ -77,11 +242,13 @@ pub enum Step {
     ConfigUpdate,
     Containers,
     CustomCommands,
+    CustomPackageManagers,
     DebGet,
     Deno,
     Distrobox,
     DkpPacman,
     Dotnet,
+    Eget,
     Elan,
     Emacs,
     Firmware,
@@ -125,6 +292,7 @@ pub enum Step {
     Lensfun,
     Lure,
     Macports,
+    Maintenance,
     Mamba,
     Mas,
     Maza,
@@ -134,8 +302,10 @@ pub enum Step {
     Mise,
     Myrepos,
     Nix,
+    NixFlake,
     NixHelper,
     Node,
+    Nodenv,
     Opam,
     Pacdef,
     Pacstall,
@@ -155,7 +325,9 @@ pub enum Step {
     Powershell,
     Protonup,
     Pyenv,
+    PythonVenvs,
     Raco,
+    Rbenv,
     Rcm,
     Remotes,
     Restarts,
@@ -174,6 +346,7 @@ pub enum Step {
     Stack,
     Stew,
     System,
+    Terraform,
     Tldr,
     Tlmgr,
     Tmux,
@@ -197,24 +370,26 @@ pub enum Step {
     Zvm,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Include {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     paths: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Containers {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     ignored_containers: Option<Vec<String>>,
     runtime: Option<ContainerRuntime>,
+    cleanup: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Git {
+    #[serde(alias = "parallel")]
     max_concurrency: Option<usize>,
 
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
@@ -223,10 +398,17 @@ pub struct Git {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     repos: Option<Vec<String>>,
 
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    repos_exclude: Option<Vec<String>>,
+
     pull_predefined: Option<bool>,
+
+    precommit_autoupdate: Option<bool>,
+
+    verify_signatures: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Vagrant {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
@@ -236,7 +418,7 @@ pub struct Vagrant {
     always_suspend: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, Default, Debug, Copy, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum UpdatesAutoReboot {
     Yes,
@@ -245,7 +427,65 @@ pub enum UpdatesAutoReboot {
     Ask,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+/// What to do at the end of a run when a step found that a reboot is needed.
+#[derive(Deserialize, Serialize, Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RebootMode {
+    Auto,
+    Prompt,
+    #[default]
+    Never,
+}
+
+/// How the Neovim step upgrades plugins, see `neovim_plugin_manager`.
+#[derive(Deserialize, Serialize, Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NeovimPluginManager {
+    /// Use the plugin manager found in the Neovim directories, or else the upgrade script.
+    #[default]
+    Auto,
+    Lazy,
+    Packer,
+    VimPlug,
+    /// The upgrade script shared with Vim, which runs every plugin manager it finds.
+    Script,
+}
+
+/// The winget source to upgrade packages from.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum WingetSource {
+    Winget,
+    Msstore,
+}
+
+impl WingetSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WingetSource::Winget => "winget",
+            WingetSource::Msstore => "msstore",
+        }
+    }
+}
+
+/// Which updates `softwareupdate` should install.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SoftwareUpdates {
+    #[default]
+    All,
+    Recommended,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct MacOS {
+    softwareupdate_updates: Option<SoftwareUpdates>,
+    softwareupdate_include_config_data: Option<bool>,
+    softwareupdate_restart: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Windows {
     accept_all_updates: Option<bool>,
@@ -256,9 +496,12 @@ pub struct Windows {
     wsl_update_use_web_download: Option<bool>,
     winget_silent_install: Option<bool>,
     winget_use_sudo: Option<bool>,
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    winget_exclude: Option<Vec<String>>,
+    winget_source: Option<WingetSource>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Python {
     enable_pip_review: Option<bool>,
@@ -266,9 +509,27 @@ pub struct Python {
     enable_pipupgrade: Option<bool>,
     pipupgrade_arguments: Option<String>,
     poetry_force_self_update: Option<bool>,
+    pipx_include_injected: Option<bool>,
+    conda_environments: Option<CondaEnvironments>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+/// The conda environments to update, see `conda_environments`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CondaEnvironments {
+    /// Every environment listed by `conda env list`.
+    All(AllEnvironments),
+    Named(Vec<String>),
+}
+
+/// The `"all"` keyword of [`CondaEnvironments`].
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AllEnvironments {
+    All,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Distrobox {
@@ -278,49 +539,70 @@ pub struct Distrobox {
     containers: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Yarn {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct NPM {
     use_sudo: Option<bool>,
+    update_local: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Rustup {
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    toolchains: Option<Vec<String>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    components: Option<Vec<String>>,
+
+    self_update: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Deno {
     version: Option<String>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Firmware {
     upgrade: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Maintenance {
+    man_db: Option<bool>,
+    locate: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Flatpak {
     use_sudo: Option<bool>,
+    remove_unused: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct Pixi {
     include_release_notes: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Brew {
     greedy_cask: Option<bool>,
@@ -328,9 +610,11 @@ pub struct Brew {
     greedy_auto_updates: Option<bool>,
     autoremove: Option<bool>,
     fetch_head: Option<bool>,
+    bundle_file: Option<String>,
+    bundle_cleanup: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum ArchPackageManager {
     Autodetect,
@@ -344,11 +628,23 @@ pub enum ArchPackageManager {
     Yay,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// A JSON object with the message and the number of steps in each state.
+    #[default]
+    Generic,
+    Slack,
+    Discord,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ContainerRuntime {
     Docker,
     Podman,
+    /// Docker if it is installed, Podman otherwise
+    Auto,
 }
 
 impl fmt::Display for ContainerRuntime {
@@ -356,11 +652,12 @@ impl fmt::Display for ContainerRuntime {
         match self {
             ContainerRuntime::Docker => write!(f, "docker"),
             ContainerRuntime::Podman => write!(f, "podman"),
+            ContainerRuntime::Auto => write!(f, "auto"),
         }
     }
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Linux {
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
@@ -395,9 +692,17 @@ pub struct Linux {
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     nix_env_arguments: Option<String>,
 
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    nix_flake_dirs: Option<Vec<String>>,
+
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     apt_arguments: Option<String>,
 
+    apt_autoremove: Option<bool>,
+    apt_clean: Option<bool>,
+    apt_include_phased: Option<bool>,
+    apt_noninteractive: Option<bool>,
+    zypper_noninteractive: Option<bool>,
     enable_tlmgr: Option<bool>,
     redhat_distro_sync: Option<bool>,
     suse_dup: Option<bool>,
@@ -414,50 +719,86 @@ pub struct Linux {
     home_manager_arguments: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Composer {
     self_update: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Vim {
     force_plug_update: Option<bool>,
+    neovim_plugin_manager: Option<NeovimPluginManager>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Misc {
     pre_sudo: Option<bool>,
 
+    sudo_keepalive: Option<bool>,
+
     sudo_command: Option<SudoKind>,
 
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
-    disable: Option<Vec<Step>>,
+    no_sudo_steps: Option<Vec<Step>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    quiet_steps: Option<Vec<Step>>,
+
+    ctrl_c_skips_step: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    disable: Option<Vec<String>>,
 
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     ignore_failures: Option<Vec<Step>>,
 
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
-    remote_topgrades: Option<Vec<String>>,
+    remote_topgrades: Option<Vec<RemoteTopgrade>>,
 
     remote_topgrade_path: Option<String>,
 
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    containers_to_topgrade: Option<Vec<String>>,
+
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     ssh_arguments: Option<String>,
 
+    ssh_control_master: Option<bool>,
+
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     tmux_arguments: Option<String>,
 
+    tmux_force_plugin_update: Option<bool>,
+
     set_title: Option<bool>,
 
     display_time: Option<bool>,
 
+    summary_group_by_status: Option<bool>,
+
+    separator_char: Option<char>,
+
+    separator_width: Option<usize>,
+
+    separator_color: Option<String>,
+
+    no_color: Option<bool>,
+
     assume_yes: Option<bool>,
 
     no_retry: Option<bool>,
 
+    retries: Option<u32>,
+
+    retry_delay: Option<u64>,
+
+    max_failures: Option<usize>,
+
+    healthcheck_abort: Option<bool>,
+
     run_in_tmux: Option<bool>,
 
     tmux_session_mode: Option<TmuxSessionMode>,
@@ -468,17 +809,75 @@ pub struct Misc {
 
     skip_notify: Option<bool>,
 
+    reboot: Option<RebootMode>,
+
+    allowed_hours: Option<TimeRange>,
+
     bashit_branch: Option<String>,
 
+    omz_update_custom: Option<bool>,
+
+    cargo_use_binstall: Option<bool>,
+
+    go_update_toolchain: Option<bool>,
+
+    ruby_update_bundle: Option<bool>,
+
+    rebuild_completions: Option<bool>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    terraform_dirs: Option<Vec<String>>,
+
+    terraform_binary: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    python_venvs: Option<Vec<String>>,
+
+    version_manager_latest_patch: Option<bool>,
+
+    version_manager_set_global: Option<bool>,
+
+    node_audit: Option<bool>,
+
+    node_audit_fail: Option<bool>,
+
+    skip_unchanged: Option<bool>,
+
+    mise_update_plugins: Option<bool>,
+
+    asdf_update_plugins: Option<bool>,
+
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
-    only: Option<Vec<Step>>,
+    only: Option<Vec<String>>,
+
+    step_order: Option<Vec<String>>,
+
+    run_last: Option<Vec<String>>,
+
+    jitter: Option<bool>,
+
+    jitter_max_delay: Option<u64>,
 
     no_self_update: Option<bool>,
 
+    self_update_channel: Option<String>,
+
     log_filters: Option<Vec<String>>,
+
+    timeout: Option<u64>,
+
+    step_silence_warning: Option<u64>,
+
+    prefix_step_output: Option<bool>,
+
+    parallelism: Option<usize>,
+
+    log_file: Option<String>,
+
+    log_file_max: Option<usize>,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ValueEnum)]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum TmuxSessionMode {
@@ -486,24 +885,57 @@ pub enum TmuxSessionMode {
     AttachAlways,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+#[clap(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Print a human readable summary
+    #[default]
+    Human,
+    /// Print a JSON report to stdout and everything else to stderr
+    Json,
+}
+
 pub struct TmuxConfig {
     pub args: Vec<String>,
     pub session_mode: TmuxSessionMode,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+/// How to secure the connection to the SMTP server.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTls {
+    /// Upgrade a plain connection with STARTTLS, failing if the server doesn't support it.
+    #[default]
+    Starttls,
+    /// Connect with TLS right away.
+    Tls,
+    /// Never encrypt the connection.
+    None,
+}
+
+/// Where and how to send the summary of a run by email, see `[notifications.email]`.
+pub struct EmailConfig<'a> {
+    pub url: String,
+    pub tls: EmailTls,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    pub from: &'a str,
+    pub to: &'a [String],
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Lensfun {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct JuliaConfig {
     startup_file: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Zigup {
     target_versions: Option<Vec<String>>,
@@ -512,13 +944,129 @@ pub struct Zigup {
     cleanup: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Helm {
+    upgrade_releases: Option<bool>,
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    namespaces: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize, Default, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Ntfy {
+    url: Option<String>,
+    #[serde(serialize_with = "redact", skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Default, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Webhook {
+    #[serde(serialize_with = "redact", skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    format: Option<WebhookFormat>,
+    template: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Default, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Telegram {
+    #[serde(serialize_with = "redact", skip_serializing_if = "Option::is_none")]
+    bot_token: Option<String>,
+    chat_id: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Default, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    smtp_host: Option<String>,
+    smtp_port: Option<u16>,
+    tls: Option<EmailTls>,
+    username: Option<String>,
+    #[serde(serialize_with = "redact", skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    from: Option<String>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    to: Option<Vec<String>>,
+}
+
+// The secrets must not end up in debug logs either.
+impl fmt::Debug for Ntfy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ntfy")
+            .field("url", &self.url)
+            .field("token", &redacted(&self.token))
+            .finish()
+    }
+}
+
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("url", &redacted(&self.url))
+            .field("format", &self.format)
+            .field("template", &self.template)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Telegram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Telegram")
+            .field("bot_token", &redacted(&self.bot_token))
+            .field("chat_id", &self.chat_id)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Email {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Email")
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+pub struct Notifications {
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    webhook: Option<Webhook>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    telegram: Option<Telegram>,
+
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    email: Option<Email>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct VscodeConfig {
     profile: Option<String>,
+    update_app: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
+#[serde(deny_unknown_fields)]
+/// Steps to disable or enable on a given host, on top of the global `disable` list
+pub struct HostnameOverride {
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    disable: Option<Vec<Step>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    enable: Option<Vec<Step>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 /// Configuration file
 pub struct ConfigFile {
@@ -537,6 +1085,33 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::commands_merge_opt)]
     commands: Option<Commands>,
 
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    custom_package_manager: Option<Vec<CustomPackageManager>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    pre_step_commands: Option<IndexMap<Step, CustomCommand>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    post_step_commands: Option<IndexMap<Step, CustomCommand>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    step_healthcheck: Option<IndexMap<Step, CustomCommand>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    step_timeouts: Option<IndexMap<Step, u64>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    step_env: Option<IndexMap<Step, IndexMap<String, String>>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    hostname_overrides: Option<IndexMap<String, HostnameOverride>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    profiles: Option<IndexMap<String, ConfigFile>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::index_map_merge_opt)]
+    step_groups: Option<IndexMap<String, Vec<String>>>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     python: Option<Python>,
 
@@ -558,12 +1133,18 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     windows: Option<Windows>,
 
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    macos: Option<MacOS>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     npm: Option<NPM>,
 
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     yarn: Option<Yarn>,
 
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    rustup: Option<Rustup>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     deno: Option<Deno>,
 
@@ -573,6 +1154,9 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     firmware: Option<Firmware>,
 
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    maintenance: Option<Maintenance>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     vagrant: Option<Vagrant>,
 
@@ -594,31 +1178,142 @@ pub struct ConfigFile {
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     zigup: Option<Zigup>,
 
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    helm: Option<Helm>,
+
     #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
     vscode: Option<VscodeConfig>,
-}
 
-fn config_directory() -> PathBuf {
-    #[cfg(unix)]
-    return crate::XDG_DIRS.config_dir();
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    ntfy: Option<Ntfy>,
 
-    #[cfg(windows)]
-    return crate::WINDOWS_DIRS.config_dir();
+    #[merge(strategy = crate::utils::merge_strategies::inner_merge_opt)]
+    notifications: Option<Notifications>,
 }
 
-/// The only purpose of this struct is to deserialize only the `include` field of the config file.
-#[derive(Deserialize, Default, Debug)]
-struct ConfigFileIncludeOnly {
-    include: Option<Include>,
+/// A range of local times like `02:00-05:00`, see `allowed_hours`.
+///
+/// The range crosses midnight when it ends before it starts, e.g. `22:00-02:00`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeRange {
+    start: NaiveTime,
+    end: NaiveTime,
 }
 
-impl ConfigFile {
-    /// Returns the main config file and any additional config files
-    /// 0 = main config file
-    /// 1 = additional config files coming from topgrade.d
-    fn ensure() -> Result<(PathBuf, Vec<PathBuf>)> {
-        let mut res = (PathBuf::new(), Vec::new());
-
+impl TimeRange {
+    /// Whether `time` falls within the range, including its start but not its end.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time `{time}`, expected HH:MM"))
+        };
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| format!("invalid range `{value}`, expected HH:MM-HH:MM"))?;
+
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl From<TimeRange> for String {
+    fn from(range: TimeRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// Parse a duration like `90m`, `24h` or `1d12h`. Units are `s`, `m`, `h`, `d` and `w`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let mut total = 0u64;
+    let mut number = String::new();
+
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(format!("invalid unit `{c}`, expected one of s, m, h, d, w")),
+        };
+        let amount: u64 = number.parse().map_err(|_| format!("missing number before `{c}`"))?;
+        total = amount
+            .checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or("duration is too long")?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(format!("missing unit after `{number}`, e.g. `{number}h`"));
+    }
+    if total == 0 {
+        return Err(String::from("expected a duration such as `24h` or `3d`"));
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+fn config_directory() -> PathBuf {
+    #[cfg(unix)]
+    return crate::XDG_DIRS.config_dir();
+
+    #[cfg(windows)]
+    return crate::WINDOWS_DIRS.config_dir();
+}
+
+/// What secrets such as webhook tokens are shown as.
+fn redacted(value: &Option<String>) -> Option<&'static str> {
+    value.as_ref().map(|_| "<redacted>")
+}
+
+/// Hide secrets such as webhook tokens from `--dump-config`.
+fn redact<S: serde::Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match redacted(value) {
+        Some(redacted) => serializer.serialize_str(redacted),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// The only purpose of this struct is to deserialize only the `include` field of the config file.
+#[derive(Deserialize, Default, Debug)]
+struct ConfigFileIncludeOnly {
+    include: Option<Include>,
+}
+
+impl ConfigFile {
+    /// Returns the main config file and any additional config files
+    /// 0 = main config file
+    /// 1 = additional config files coming from topgrade.d
+    fn ensure() -> Result<(PathBuf, Vec<PathBuf>)> {
+        let mut res = (PathBuf::new(), Vec::new());
+
         let config_directory = config_directory();
 
         let possible_config_paths = [
@@ -681,99 +1376,195 @@ impl ConfigFile {
         Ok(res)
     }
 
-    /// Read the configuration file.
+    /// Read the configuration files.
     ///
-    /// If the configuration file does not exist, the function returns the default ConfigFile.
-    fn read(config_path: Option<PathBuf>) -> Result<ConfigFile> {
+    /// Without any `config_paths`, the default configuration file is read, along with the files
+    /// in topgrade.d. Otherwise, the files are merged in order, so that later ones override
+    /// earlier ones. If the configuration file does not exist, the function returns the
+    /// default ConfigFile.
+    fn read(config_paths: &[PathBuf]) -> Result<ConfigFile> {
+        let (config_paths, dir_include) = if config_paths.is_empty() {
+            let (path, dir_include) = Self::ensure()?;
+            (vec![path], dir_include)
+        } else {
+            (config_paths.to_vec(), Vec::new())
+        };
+
         let mut result = Self::default();
+        let mut stack = Vec::new();
 
-        let config_path = if let Some(path) = config_path {
-            path
-        } else {
-            let (path, dir_include) = Self::ensure()?;
+        // Merging keeps the values already set, so the last file is merged first
+        for config_path in config_paths.iter().rev() {
+            // The default path means there is only topgrade.d, see Self::ensure()
+            if *config_path == PathBuf::default() {
+                continue;
+            }
+
+            let mut contents = fs::read_to_string(config_path).inspect_err(|_| {
+                error!("Unable to read {}", config_path.display());
+            })?;
+
+            Self::ensure_misc_is_present(&mut contents, config_path);
+
+            debug!("Loading configuration file {}", config_path.display());
+            result.merge(Self::parse_with_includes(config_path, &contents, &mut stack)?);
+        }
+
+        // Files in topgrade.d are included before the main config file, so it overrides them
+        for include in dir_include.iter().rev() {
+            result.merge(Self::read_include(include, &mut stack)?);
+        }
 
-            /*
-            The Function was called without a config_path, we need
-            to read the include directory before returning the main config path
-            */
-            for include in dir_include {
-                let include_contents = fs::read_to_string(&include).inspect_err(|_| {
-                    error!("Unable to read {}", include.display());
-                })?;
-                let include_contents_parsed = toml::from_str(include_contents.as_str()).inspect_err(|_| {
-                    error!("Failed to deserialize {}", include.display());
-                })?;
-
-                result.merge(include_contents_parsed);
+        result.expand_paths();
+
+        debug!("Loaded configuration: {:?}", result);
+        Ok(result)
+    }
+
+    /// Expand `~` in the paths of the configuration.
+    fn expand_paths(&mut self) {
+        let git_paths = self
+            .git
+            .as_mut()
+            .into_iter()
+            .flat_map(|git| git.repos.iter_mut().chain(git.repos_exclude.iter_mut()));
+        let flake_paths = self
+            .linux
+            .as_mut()
+            .into_iter()
+            .flat_map(|linux| linux.nix_flake_dirs.iter_mut());
+        let misc_paths = self
+            .misc
+            .as_mut()
+            .into_iter()
+            .flat_map(|misc| misc.terraform_dirs.iter_mut().chain(misc.python_venvs.iter_mut()));
+        for paths in git_paths.chain(flake_paths).chain(misc_paths) {
+            for path in paths.iter_mut() {
+                let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
+                debug!(
+                    "{}",
+                    t!("Path {path} expanded to {expanded}", path = path, expanded = expanded)
+                );
+                *path = expanded;
             }
+        }
+    }
 
-            path
+    /// Apply the `[profiles.<name>]` section on top of the rest of the configuration.
+    fn with_profile(mut self, name: &str) -> Result<Self> {
+        let Some(mut profile) = self.profiles.as_mut().and_then(|profiles| profiles.shift_remove(name)) else {
+            let profiles: Vec<&str> = self
+                .profiles
+                .iter()
+                .flat_map(|profiles| profiles.keys())
+                .map(String::as_str)
+                .collect();
+            return Err(if profiles.is_empty() {
+                eyre!(t!(
+                    "Unknown profile {profile}, no profiles are configured",
+                    profile = name
+                ))
+            } else {
+                eyre!(t!(
+                    "Unknown profile {profile}, available profiles: {profiles}",
+                    profile = name,
+                    profiles = profiles.join(", ")
+                ))
+            });
         };
 
-        if config_path == PathBuf::default() {
-            // Here we expect topgrade.d and consequently result is not empty.
-            // If empty, Self:: ensure() would have created the default config.
-            return Ok(result);
+        debug!("Applying profile {name}");
+        profile.expand_paths();
+        // Merging keeps the values already set, so the profile wins over the rest
+        profile.merge(self);
+        Ok(profile)
+    }
+
+    /// Check the `[[custom_package_manager]]` entries, which serde can't check on its own.
+    fn validate_custom_package_managers(&self) -> Result<()> {
+        let managers = self.custom_package_manager.as_deref().unwrap_or_default();
+        for (i, manager) in managers.iter().enumerate() {
+            for (field, value) in [
+                ("name", &manager.name),
+                ("detect", &manager.detect),
+                ("update", &manager.update),
+            ] {
+                if value.trim().is_empty() {
+                    return Err(eyre!(t!(
+                        "The {field} of custom_package_manager {index} is empty",
+                        field = field,
+                        index = i + 1
+                    )));
+                }
+            }
+            if managers[..i].iter().any(|other| other.name == manager.name) {
+                return Err(eyre!(t!(
+                    "There are several custom_package_manager named {name}",
+                    name = manager.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a file from an `[include]` section or from topgrade.d.
+    ///
+    /// `stack` holds the files being read, from the main config file down to the one including
+    /// `path`, so that circular includes are reported instead of recursing forever.
+    fn read_include(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Self> {
+        if !path.exists() {
+            return Err(eyre!("Included config file {} does not exist", path.display()));
+        }
+        if stack.contains(&path.canonicalize()?) {
+            return Err(eyre!("Circular include of config file {}", path.display()));
         }
 
-        let mut contents_non_split = fs::read_to_string(&config_path).inspect_err(|_| {
-            error!("Unable to read {}", config_path.display());
-        })?;
+        let contents = fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        Self::parse_with_includes(path, &contents, stack)
+            .with_context(|| format!("Failed to deserialize {}", path.display()))
+    }
 
-        Self::ensure_misc_is_present(&mut contents_non_split, &config_path);
+    /// Parse the `contents` of the config file at `path`, along with the files it includes.
+    ///
+    /// Relative include paths are resolved from the directory of `path`. The file overrides
+    /// what it includes, and later includes override earlier ones.
+    fn parse_with_includes(path: &Path, contents: &str, stack: &mut Vec<PathBuf>) -> Result<Self> {
+        let mut result = Self::default();
+        let mut includes = Vec::new();
 
         // To parse [include] sections in the order as they are written,
         // we split the file and parse each part as a separate file
         let regex_match_include = Regex::new(r"^\s*\[include]").expect("Failed to compile regex");
-        let contents_split = regex_match_include.split_inclusive_left(contents_non_split.as_str());
+        let contents_split = regex_match_include.split_inclusive_left(contents);
 
         for contents in contents_split {
             let config_file_include_only: ConfigFileIncludeOnly = toml::from_str(contents).inspect_err(|_| {
-                error!("Failed to deserialize an include section of {}", config_path.display());
+                error!("Failed to deserialize an include section of {}", path.display());
             })?;
 
-            if let Some(includes) = &config_file_include_only.include {
-                // Parses the [include] section present in the slice
-                if let Some(ref paths) = includes.paths {
-                    for include in paths.iter().rev() {
-                        let include_path = shellexpand::tilde::<&str>(&include.as_ref()).into_owned();
-                        let include_path = PathBuf::from(include_path);
-                        let include_contents = match fs::read_to_string(&include_path) {
-                            Ok(c) => c,
-                            Err(e) => {
-                                error!("Unable to read {}: {e}", include_path.display(),);
-                                continue;
-                            }
-                        };
-                        match toml::from_str::<Self>(&include_contents) {
-                            Ok(include_parsed) => result.merge(include_parsed),
-                            Err(e) => {
-                                error!("Failed to deserialize {}: {e}", include_path.display(),);
-                                continue;
-                            }
-                        };
-                    }
-                }
+            if let Some(paths) = config_file_include_only.include.and_then(|include| include.paths) {
+                includes.extend(paths);
             }
 
             match toml::from_str::<Self>(contents) {
                 Ok(contents) => result.merge(contents),
-                Err(e) => error!("Failed to deserialize {}: {e}", config_path.display(),),
+                Err(e) => error!("Failed to deserialize {}: {e}", path.display(),),
             }
         }
 
-        if let Some(paths) = result.git.as_mut().and_then(|git| git.repos.as_mut()) {
-            for path in paths.iter_mut() {
-                let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
-                debug!(
-                    "{}",
-                    t!("Path {path} expanded to {expanded}", path = path, expanded = expanded)
-                );
-                *path = expanded;
-            }
+        let directory = path.parent().unwrap_or(Path::new(""));
+        stack.push(path.canonicalize().unwrap_or_else(|_| path.to_owned()));
+
+        // Merging keeps the values already set, so the last include is merged first
+        for include in includes.iter().rev() {
+            let include_path = directory.join(shellexpand::tilde::<&str>(&include.as_ref()).as_ref());
+            result.merge(
+                Self::read_include(&include_path, stack)
+                    .with_context(|| format!("Failed to include {} in {}", include_path.display(), path.display()))?,
+            );
         }
 
-        debug!("Loaded configuration: {:?}", result);
+        stack.pop();
         Ok(result)
     }
 
@@ -819,6 +1610,14 @@ pub struct CommandLineArgs {
     #[arg(long = "config-reference")]
     show_config_reference: bool,
 
+    /// Print the configuration in effect, once merged with the files it includes, and exit
+    #[arg(long = "dump-config")]
+    dump_config: bool,
+
+    /// Print every step, whether it would run and why not, and exit
+    #[arg(long = "list-steps")]
+    list_steps: bool,
+
     /// Run inside tmux
     #[arg(short = 't', long = "tmux")]
     run_in_tmux: bool,
@@ -831,17 +1630,79 @@ pub struct CommandLineArgs {
     #[arg(short = 'n', long = "dry-run")]
     dry_run: bool,
 
+    /// Print a shell script running the commands a dry run would run to stdout
+    #[arg(long = "dry-run-script", conflicts_with = "output_format")]
+    dry_run_script: bool,
+
+    /// Dry run, and save the steps and the commands they would run to PATH, to run them
+    /// with `--plan-in`. Commands whose failure a step ignores make it fail when replayed
+    #[arg(long = "plan-out", value_name = "PATH")]
+    plan_out: Option<PathBuf>,
+
+    /// Write when each step ran to PATH, in the Chrome trace event format, to open in
+    /// chrome://tracing or Perfetto
+    #[arg(long = "trace-out", value_name = "PATH")]
+    trace_out: Option<PathBuf>,
+
+    /// Run the steps and commands saved by `--plan-out`, instead of finding out what to run
+    #[arg(long = "plan-in", value_name = "PATH", conflicts_with_all = ["plan_out", "list_steps"])]
+    plan_in: Option<PathBuf>,
+
+    /// Print what would be done, and list the packages that would be upgraded by the steps
+    /// that can tell
+    #[arg(long = "explain")]
+    explain: bool,
+
+    /// Preview each step with a dry run, then ask before running it for real
+    #[arg(long = "confirm")]
+    confirm: bool,
+
+    /// Never wait for user input, taking the safe default for every prompt
+    ///
+    /// Enabled automatically when stdout is not a terminal. Failed steps are not retried,
+    /// yes/no questions are answered with no (unless `--yes` is given), the breaking changes of a new major release are
+    /// printed without asking for confirmation (and shown again on the next interactive run),
+    /// and `--keep` and `--interactive-select` are ignored.
+    #[arg(long = "no-interactive")]
+    no_interactive: bool,
+
+    /// Choose which steps to run from a checklist before starting
+    #[arg(long = "interactive-select")]
+    interactive_select: bool,
+
     /// Do not ask to retry failed steps
     #[arg(long = "no-retry")]
     no_retry: bool,
 
-    /// Do not perform upgrades for the given steps
-    #[arg(long = "disable", value_name = "STEP", value_enum, num_args = 1..)]
-    disable: Vec<Step>,
+    /// Make Ctrl+C skip the current step instead of asking whether to retry it, pressing it
+    /// twice aborts the run. Overrides `ctrl_c_skips_step`
+    #[arg(long = "keep-going")]
+    keep_going: bool,
+
+    /// Run steps even if their manifest is unchanged and `skip_unchanged` is set, and forget
+    /// the steps `--resume` would skip
+    #[arg(long = "force")]
+    force: bool,
+
+    /// Skip the steps that succeeded in the last run, if it failed with the same configuration
+    #[arg(long = "resume")]
+    resume: bool,
+
+    /// Skip the steps that come before STEP
+    #[arg(long = "from-step", value_name = "STEP", value_enum)]
+    from_step: Option<Step>,
 
-    /// Perform only the specified steps
-    #[arg(long = "only", value_name = "STEP", value_enum, num_args = 1..)]
-    only: Vec<Step>,
+    /// Skip self-update, remote topgrades, git pulls and other steps that download a lot
+    #[arg(long = "offline")]
+    offline: bool,
+
+    /// Do not perform upgrades for the given steps, groups of steps from `[step_groups]` or step name patterns like `jetbrains_*`
+    #[arg(long = "disable", value_name = "STEP", num_args = 1..)]
+    disable: Vec<String>,
+
+    /// Perform only the specified steps or groups of steps, minus the ones given to `--disable`
+    #[arg(long = "only", value_name = "STEP", num_args = 1..)]
+    only: Vec<String>,
 
     /// Run only specific custom commands
     #[arg(long = "custom-commands", value_name = "NAME", num_args = 1..)]
@@ -864,6 +1725,10 @@ pub struct CommandLineArgs {
     skip_notify: bool,
 
     /// Say yes to package manager's prompt
+    ///
+    /// Given without any STEP, also answer yes to Topgrade's own yes/no questions, such as
+    /// the confirmation of the breaking changes of a new major release or the reboot prompt.
+    /// This takes precedence over `--no-interactive`, which still turns off the other prompts
     #[arg(
         short = 'y',
         long = "yes",
@@ -877,9 +1742,14 @@ pub struct CommandLineArgs {
     #[arg(long = "disable-predefined-git-repos")]
     disable_predefined_git_repos: bool,
 
-    /// Alternative configuration file
+    /// Alternative configuration file. Can be given several times, later files override
+    /// earlier ones
     #[arg(long = "config", value_name = "PATH")]
-    config: Option<PathBuf>,
+    config: Vec<PathBuf>,
+
+    /// Apply the `[profiles.NAME]` section of the configuration on top of the rest of it
+    #[arg(long = "profile", value_name = "NAME")]
+    profile: Option<String>,
 
     /// A regular expression for restricting remote host execution
     #[arg(long = "remote-host-limit", value_name = "REGEX")]
@@ -906,6 +1776,34 @@ pub struct CommandLineArgs {
     /// Don't update Topgrade
     #[arg(long = "no-self-update")]
     pub no_self_update: bool,
+
+    /// Run the steps in their usual order without waiting first, even if `jitter` is set
+    #[arg(long = "no-jitter")]
+    no_jitter: bool,
+
+    /// Wait for another Topgrade that is already running to finish, instead of exiting
+    #[arg(long = "wait", conflicts_with = "no_lock")]
+    wait: bool,
+
+    /// Run even if another Topgrade is already running
+    #[arg(long = "no-lock")]
+    no_lock: bool,
+
+    /// Run up to N independent steps at the same time
+    #[arg(short = 'j', long = "jobs", value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Exit right away if the last successful run was less than DURATION ago, e.g. `24h` or `3d`
+    #[arg(long = "if-older-than", value_name = "DURATION", value_parser = parse_duration)]
+    if_older_than: Option<Duration>,
+
+    /// Write a JUnit XML report of the run to PATH, one test case per step
+    #[arg(long = "report-junit", value_name = "PATH")]
+    report_junit: Option<PathBuf>,
+
+    /// Format of the summary printed at the end of the run
+    #[arg(long = "output-format", value_name = "FORMAT", value_enum, default_value_t)]
+    output_format: OutputFormat,
 }
 
 impl CommandLineArgs {
@@ -960,9 +1858,16 @@ impl Config {
     ///
     /// The function parses the command line arguments and reads the configuration file.
     pub fn load(opt: CommandLineArgs) -> Result<Self> {
+        if let Some(path) = opt.config.iter().find(|path| !path.is_file()) {
+            return Err(eyre!(t!(
+                "Configuration file {path} does not exist",
+                path = path.display()
+            )));
+        }
+
         let config_directory = config_directory();
         let config_file = if config_directory.is_dir() {
-            ConfigFile::read(opt.config.clone()).unwrap_or_else(|e| {
+            ConfigFile::read(&opt.config).unwrap_or_else(|e| {
                 // Inform the user about errors when loading the configuration,
                 // but fallback to the default config to at least attempt to do something
                 error!("failed to load configuration: {e}");
@@ -973,7 +1878,14 @@ impl Config {
             ConfigFile::default()
         };
 
-        let allowed_steps = Self::allowed_steps(&opt, &config_file);
+        let config_file = match &opt.profile {
+            Some(profile) => config_file.with_profile(profile)?,
+            None => config_file,
+        };
+        config_file.validate_custom_package_managers()?;
+
+        let hostname = hostname().ok();
+        let allowed_steps = Self::allowed_steps(&opt, &config_file, hostname.as_deref())?;
 
         Ok(Self {
             opt,
@@ -982,6 +1894,33 @@ impl Config {
         })
     }
 
+    /// Whether to print the configuration and exit, see `--dump-config`.
+    pub fn dump_config(&self) -> bool {
+        self.opt.dump_config
+    }
+
+    /// The configuration file as merged from all the files it includes, preceded by the steps
+    /// that are going to run, as TOML. Secrets are redacted.
+    pub fn dump(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Dump<'a> {
+            enabled_steps: &'a [Step],
+            #[serde(flatten)]
+            config_file: &'a ConfigFile,
+        }
+
+        Ok(toml::to_string(&Dump {
+            enabled_steps: &self.allowed_steps,
+            config_file: &self.config_file,
+        })?)
+    }
+
+    /// A hash of the configuration files, to tell whether the configuration changed since the
+    /// last run.
+    pub fn file_hash(&self) -> Result<u64> {
+        Ok(stable_hash(toml::to_string(&self.config_file)?.as_bytes()))
+    }
+
     /// Launch an editor to edit the configuration
     pub fn edit() -> Result<()> {
         ConfigFile::edit()
@@ -997,16 +1936,59 @@ impl Config {
         &self.config_file.post_commands
     }
 
+    /// The command to run before the given step.
+    pub fn pre_step_command(&self, step: Step) -> Option<&CustomCommand> {
+        self.config_file
+            .pre_step_commands
+            .as_ref()
+            .and_then(|commands| commands.get(&step))
+    }
+
+    /// The command to run after the given step.
+    pub fn post_step_command(&self, step: Step) -> Option<&CustomCommand> {
+        self.config_file
+            .post_step_commands
+            .as_ref()
+            .and_then(|commands| commands.get(&step))
+    }
+
+    /// The command that must succeed after the given step for it to count as successful.
+    pub fn step_healthcheck(&self, step: Step) -> Option<&CustomCommand> {
+        self.config_file
+            .step_healthcheck
+            .as_ref()
+            .and_then(|commands| commands.get(&step))
+    }
+
+    /// Whether a failed healthcheck skips the remaining steps.
+    pub fn healthcheck_abort(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.healthcheck_abort)
+            .unwrap_or(false)
+    }
+
     /// The list of custom steps.
     pub fn commands(&self) -> &Option<Commands> {
         &self.config_file.commands
     }
 
+    /// The package managers given as `[[custom_package_manager]]` entries.
+    pub fn custom_package_managers(&self) -> &[CustomPackageManager] {
+        self.config_file.custom_package_manager.as_deref().unwrap_or_default()
+    }
+
     /// The list of additional git repositories to pull.
     pub fn git_repos(&self) -> Option<&Vec<String>> {
         self.config_file.git.as_ref().and_then(|git| git.repos.as_ref())
     }
 
+    /// Glob patterns of git repositories not to pull.
+    pub fn git_repos_exclude(&self) -> Option<&Vec<String>> {
+        self.config_file.git.as_ref().and_then(|git| git.repos_exclude.as_ref())
+    }
+
     /// The list of docker/podman containers to ignore.
     pub fn containers_ignored_tags(&self) -> Option<&Vec<String>> {
         self.config_file
@@ -1021,7 +2003,16 @@ impl Config {
             .containers
             .as_ref()
             .and_then(|containers| containers.runtime)
-            .unwrap_or(ContainerRuntime::Docker) // defaults to a popular choice
+            .unwrap_or(ContainerRuntime::Auto)
+    }
+
+    /// Whether to prune build cache, stopped containers and dangling volumes after updating.
+    pub fn containers_cleanup(&self) -> bool {
+        self.config_file
+            .containers
+            .as_ref()
+            .and_then(|containers| containers.cleanup)
+            .unwrap_or(false)
     }
 
     /// Tell whether the specified step should run.
@@ -1032,30 +2023,191 @@ impl Config {
         self.allowed_steps.contains(&step)
     }
 
-    fn allowed_steps(opt: &CommandLineArgs, config_file: &ConfigFile) -> Vec<Step> {
-        let mut enabled_steps: Vec<Step> = Vec::new();
-        enabled_steps.extend(&opt.only);
+    /// Why `step` doesn't run although it is available: either `only` left it out, or it was
+    /// disabled.
+    pub fn disabled_reason(&self, step: Step) -> Cow<'static, str> {
+        let mut only = Self::expand_steps(&self.opt.only, &self.config_file).unwrap_or_default();
+        if let Some(misc_only) = self.config_file.misc.as_ref().and_then(|misc| misc.only.as_ref()) {
+            only.extend(Self::expand_steps(misc_only, &self.config_file).unwrap_or_default());
+        }
+
+        if !only.is_empty() && !only.contains(&step) {
+            t!("Not selected by `only`")
+        } else {
+            t!("Disabled by `disable`")
+        }
+    }
+
+    /// Whether to list the steps instead of running them, see `--list-steps`.
+    pub fn list_steps(&self) -> bool {
+        self.opt.list_steps
+    }
+
+    /// Only run `steps` from now on, see `--interactive-select`.
+    pub fn select_steps(&mut self, steps: Vec<Step>) {
+        self.allowed_steps = steps;
+    }
+
+    /// A configuration read from `contents` that allows every step, for the tests of other modules.
+    #[cfg(test)]
+    pub fn from_toml(contents: &str) -> Self {
+        Self {
+            opt: CommandLineArgs::parse_from::<_, String>([]),
+            config_file: toml::from_str(contents).expect("Invalid test configuration"),
+            allowed_steps: Step::iter().collect(),
+        }
+    }
+
+    /// Whether steps that download a lot should be skipped, see `--offline`.
+    pub fn offline(&self) -> bool {
+        self.opt.offline
+    }
+
+    /// Whether `--no-interactive` was given.
+    pub fn no_interactive(&self) -> bool {
+        self.opt.no_interactive
+    }
+
+    /// Whether the steps to run should be picked from a checklist first.
+    pub fn interactive_select(&self) -> bool {
+        self.opt.interactive_select
+    }
+
+    /// The steps to run, from `--only`, `--disable` and the configuration file.
+    ///
+    /// The `[hostname_overrides]` entry matching `hostname` disables more steps, or enables
+    /// steps the global configuration disabled. `--disable` always wins.
+    fn allowed_steps(opt: &CommandLineArgs, config_file: &ConfigFile, hostname: Option<&str>) -> Result<Vec<Step>> {
+        let host_override = hostname.and_then(|hostname| {
+            config_file
+                .hostname_overrides
+                .as_ref()
+                .and_then(|overrides| overrides.get(hostname))
+        });
+        let host_enabled: &[Step] = host_override
+            .and_then(|host_override| host_override.enable.as_deref())
+            .unwrap_or_default();
+
+        let only = Self::expand_steps(&opt.only, config_file)?;
+        let mut enabled_steps: Vec<Step> = only.clone();
 
         if let Some(misc) = config_file.misc.as_ref() {
             if let Some(only) = misc.only.as_ref() {
-                enabled_steps.extend(only);
+                enabled_steps.extend(Self::expand_steps(only, config_file)?);
             }
         }
 
         if enabled_steps.is_empty() {
             enabled_steps.extend(Step::iter());
+        } else if opt.only.is_empty() {
+            enabled_steps.extend(host_enabled);
         }
 
         let mut disabled_steps: Vec<Step> = Vec::new();
-        disabled_steps.extend(&opt.disable);
         if let Some(misc) = config_file.misc.as_ref() {
             if let Some(disabled) = misc.disable.as_ref() {
-                disabled_steps.extend(disabled);
+                disabled_steps.extend(Self::expand_steps(disabled, config_file)?);
+            }
+        }
+        if let Some(disabled) = host_override.and_then(|host_override| host_override.disable.as_ref()) {
+            disabled_steps.extend(disabled);
+        }
+        disabled_steps.retain(|step| !host_enabled.contains(step));
+
+        // Steps given to `--only` run even if the configuration disables them, but not if
+        // `--disable` does.
+        let cli_disabled = Self::expand_steps(&opt.disable, config_file)?;
+        enabled_steps.retain(|e| (!disabled_steps.contains(e) || only.contains(e)) && !cli_disabled.contains(e));
+        Ok(enabled_steps)
+    }
+
+    /// Turn step names and names of groups from `[step_groups]` into the steps they stand for.
+    fn expand_steps(names: &[String], config_file: &ConfigFile) -> Result<Vec<Step>> {
+        let mut steps = Vec::new();
+        Self::expand_step_groups(names, config_file.step_groups.as_ref(), &mut Vec::new(), &mut steps)?;
+        Ok(steps)
+    }
+
+    /// Groups may contain other groups, `stack` holds the ones being expanded to report cycles.
+    fn expand_step_groups<'a>(
+        names: &'a [String],
+        groups: Option<&'a IndexMap<String, Vec<String>>>,
+        stack: &mut Vec<&'a str>,
+        steps: &mut Vec<Step>,
+    ) -> Result<()> {
+        for name in names {
+            if let Ok(step) = name.parse::<Step>() {
+                steps.push(step);
+                continue;
+            }
+            if name.contains(['*', '?', '[']) {
+                Self::expand_step_pattern(name, steps);
+                continue;
+            }
+
+            let Some(members) = groups.and_then(|groups| groups.get(name)) else {
+                return Err(eyre!("Unknown step or step group {name}"));
+            };
+            if stack.contains(&name.as_str()) {
+                return Err(eyre!("Step group {name} includes itself"));
             }
+
+            stack.push(name);
+            Self::expand_step_groups(members, groups, stack, steps)?;
+            stack.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Add the steps whose names match the glob `pattern`, ignoring case.
+    fn expand_step_pattern(pattern: &str, steps: &mut Vec<Step>) {
+        let pattern = match Pattern::new(pattern) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                print_warning(t!(
+                    "Invalid step pattern {pattern}: {error}",
+                    pattern = pattern,
+                    error = e
+                ));
+                return;
+            }
+        };
+
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        let before = steps.len();
+        steps.extend(Step::iter().filter(|step| pattern.matches_with(step.as_ref(), options)));
+        if steps.len() == before {
+            print_warning(t!("Step pattern {pattern} matches no step", pattern = pattern.as_str()));
         }
+    }
+
+    /// Where to write the JUnit XML report, see `--report-junit`.
+    pub fn report_junit(&self) -> Option<&Path> {
+        self.opt.report_junit.as_deref()
+    }
+
+    /// Minimum time since the last successful run for this run to happen, see `--if-older-than`.
+    pub fn if_older_than(&self) -> Option<Duration> {
+        self.opt.if_older_than
+    }
 
-        enabled_steps.retain(|e| !disabled_steps.contains(e) || opt.only.contains(e));
-        enabled_steps
+    /// Whether to wait for another running Topgrade to finish, see `--wait`.
+    pub fn wait(&self) -> bool {
+        self.opt.wait
+    }
+
+    /// Whether to run without taking the lock that keeps two runs apart, see `--no-lock`.
+    pub fn no_lock(&self) -> bool {
+        self.opt.no_lock
+    }
+
+    /// Tell whether `--only` was used to run an explicit subset of steps.
+    pub fn only_given(&self) -> bool {
+        !self.opt.only.is_empty()
     }
 
     /// Tell whether we should run a self-update.
@@ -1069,6 +2221,14 @@ impl Config {
                 .unwrap_or(false)
     }
 
+    /// The releases to self-update to, `stable` or `prerelease`.
+    pub fn self_update_channel(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.self_update_channel.as_deref())
+    }
+
     /// Tell whether we should run in tmux.
     pub fn run_in_tmux(&self) -> bool {
         self.opt.run_in_tmux
@@ -1102,7 +2262,42 @@ impl Config {
 
     /// Tell whether we are dry-running.
     pub fn dry_run(&self) -> bool {
-        self.opt.dry_run
+        self.opt.dry_run || self.opt.explain || self.opt.dry_run_script || self.opt.plan_out.is_some()
+    }
+
+    /// Where to save when each step ran, see `--trace-out`.
+    pub fn trace_out(&self) -> Option<&Path> {
+        self.opt.trace_out.as_deref()
+    }
+
+    /// Where to save the plan of the run, see `--plan-out`.
+    pub fn plan_out(&self) -> Option<&Path> {
+        self.opt.plan_out.as_deref()
+    }
+
+    /// The plan to run instead of finding out what to run, see `--plan-in`.
+    pub fn plan_in(&self) -> Option<&Path> {
+        self.opt.plan_in.as_deref()
+    }
+
+    /// Tell whether dry runs should print a shell script, see `--dry-run-script`.
+    pub fn dry_run_script(&self) -> bool {
+        self.opt.dry_run_script
+    }
+
+    /// Tell whether we should list the packages that would be upgraded.
+    pub fn explain(&self) -> bool {
+        self.opt.explain
+    }
+
+    /// The local times at which disruptive steps may run, see `allowed_hours`.
+    pub fn allowed_hours(&self) -> Option<TimeRange> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.allowed_hours)
+    }
+
+    /// Whether to preview each step and ask before running it, see `--confirm`.
+    pub fn confirm(&self) -> bool {
+        self.opt.confirm && !self.dry_run()
     }
 
     /// Tell whether we should not attempt to retry anything.
@@ -1116,14 +2311,85 @@ impl Config {
                 .unwrap_or(false)
     }
 
+    /// How many times to automatically retry a failed step.
+    pub fn retries(&self) -> u32 {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.retries)
+            .unwrap_or(0)
+    }
+
+    /// How long to wait before the first automatic retry. Doubles with every retry.
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(
+            self.config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.retry_delay)
+                .unwrap_or(5),
+        )
+    }
+
+    /// Skip the remaining steps once this many steps have failed. `0` means never.
+    pub fn max_failures(&self) -> usize {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.max_failures)
+            .unwrap_or(0)
+    }
+
+    /// Steps to run first, in this order. Names that aren't steps are kept so that they can be
+    /// reported.
+    pub fn step_order(&self) -> Option<&Vec<String>> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.step_order.as_ref())
+    }
+
+    /// Steps to run at the end, in this order. Names that aren't steps are kept so that they can
+    /// be reported.
+    pub fn run_last(&self) -> Option<&Vec<String>> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.run_last.as_ref())
+    }
+
+    /// Whether to shuffle the steps and wait a random delay before running them.
+    pub fn jitter(&self) -> bool {
+        !self.opt.no_jitter
+            && self
+                .config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.jitter)
+                .unwrap_or(false)
+    }
+
+    /// The longest delay to wait before running the steps when `jitter` is set.
+    pub fn jitter_max_delay(&self) -> Duration {
+        Duration::from_secs(
+            self.config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.jitter_max_delay)
+                .unwrap_or(60),
+        )
+    }
+
     /// List of remote hosts to run Topgrade in
-    pub fn remote_topgrades(&self) -> Option<&Vec<String>> {
+    pub fn remote_topgrades(&self) -> Option<&Vec<RemoteTopgrade>> {
         self.config_file
             .misc
             .as_ref()
             .and_then(|misc| misc.remote_topgrades.as_ref())
     }
 
+    /// Distrobox and toolbx containers to run Topgrade in
+    pub fn containers_to_topgrade(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.containers_to_topgrade.as_ref())
+    }
+
     /// Path to Topgrade executable used for all remote hosts
     pub fn remote_topgrade_path(&self) -> &str {
         self.config_file
@@ -1141,11 +2407,38 @@ impl Config {
             .and_then(|misc| misc.ssh_arguments.as_ref())
     }
 
+    /// Whether remotes on the same host should share one `ssh` connection
+    pub fn ssh_control_master(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.ssh_control_master)
+            .unwrap_or(false)
+    }
+
     /// Extra Git arguments
     pub fn git_arguments(&self) -> Option<&String> {
         self.config_file.git.as_ref().and_then(|git| git.arguments.as_ref())
     }
 
+    /// Whether to run `pre-commit autoupdate` in the pulled repositories that use pre-commit.
+    pub fn git_precommit_autoupdate(&self) -> bool {
+        self.config_file
+            .git
+            .as_ref()
+            .and_then(|git| git.precommit_autoupdate)
+            .unwrap_or(false)
+    }
+
+    /// Whether to refuse pulling commits whose signature can't be verified.
+    pub fn git_verify_signatures(&self) -> bool {
+        self.config_file
+            .git
+            .as_ref()
+            .and_then(|git| git.verify_signatures)
+            .unwrap_or(false)
+    }
+
     pub fn tmux_config(&self) -> Result<TmuxConfig> {
         let args = self.tmux_arguments()?;
         Ok(TmuxConfig {
@@ -1173,6 +2466,15 @@ impl Config {
             .with_context(|| format!("Failed to parse `tmux_arguments`: `{args}`"))
     }
 
+    /// Whether to start a throwaway tmux server to update the tmux plugins when none is running
+    pub fn tmux_force_plugin_update(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.tmux_force_plugin_update)
+            .unwrap_or(false)
+    }
+
     /// Prompt for a key before exiting
     pub fn keep_at_end(&self) -> bool {
         self.opt.keep_at_end || env::var("TOPGRADE_KEEP_END").is_ok()
@@ -1187,6 +2489,15 @@ impl Config {
         self.opt.skip_notify
     }
 
+    /// What to do when a step found that a reboot is needed.
+    pub fn reboot(&self) -> RebootMode {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.reboot)
+            .unwrap_or_default()
+    }
+
     /// Whether to set the terminal title
     pub fn set_title(&self) -> bool {
         self.config_file
@@ -1213,6 +2524,153 @@ impl Config {
         false
     }
 
+    /// Whether Topgrade's own yes/no questions are answered with yes, which `--yes` without
+    /// any step asks for.
+    pub fn yes_to_prompts(&self) -> bool {
+        self.opt.yes.as_ref().is_some_and(Vec::is_empty)
+    }
+
+    /// Whether the cargo step should use cargo-binstall when it is installed.
+    pub fn cargo_use_binstall(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.cargo_use_binstall)
+            .unwrap_or(false)
+    }
+
+    /// Whether to update the Go toolchain installed with `golang.org/dl`.
+    pub fn go_update_toolchain(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.go_update_toolchain)
+            .unwrap_or(false)
+    }
+
+    /// Whether to audit the global Node packages after upgrading them.
+    pub fn node_audit(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.node_audit)
+            .unwrap_or(false)
+    }
+
+    /// Whether vulnerabilities found by `node_audit` fail the step.
+    pub fn node_audit_fail(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.node_audit_fail)
+            .unwrap_or(false)
+    }
+
+    /// Whether to rebuild the completion dumps of zsh after the shell steps.
+    pub fn rebuild_completions(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.rebuild_completions)
+            .unwrap_or(false)
+    }
+
+    /// Directories with Terraform configurations whose providers and modules to upgrade.
+    pub fn terraform_dirs(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.terraform_dirs.as_ref())
+    }
+
+    /// The Terraform executable to use instead of `terraform` or `tofu`.
+    pub fn terraform_binary(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.terraform_binary.as_deref())
+    }
+
+    /// Virtual environments, or glob patterns matching them, whose packages to upgrade.
+    pub fn python_venvs(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.python_venvs.as_ref())
+    }
+
+    /// Whether pyenv, rbenv and nodenv should install the latest patch release of each
+    /// installed minor version.
+    pub fn version_manager_latest_patch(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.version_manager_latest_patch)
+            .unwrap_or(false)
+    }
+
+    /// Whether the patch releases `version_manager_latest_patch` installs replace the global
+    /// versions they are newer than.
+    pub fn version_manager_set_global(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.version_manager_set_global)
+            .unwrap_or(false)
+    }
+
+    /// Whether to run `bundle update` when the current directory has a `Gemfile`.
+    pub fn ruby_update_bundle(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.ruby_update_bundle)
+            .unwrap_or(false)
+    }
+
+    /// Whether the mise step should run `mise plugins update` before upgrading the tools.
+    pub fn mise_update_plugins(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.mise_update_plugins)
+            .unwrap_or(true)
+    }
+
+    /// Whether the asdf step should update its plugins.
+    pub fn asdf_update_plugins(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.asdf_update_plugins)
+            .unwrap_or(true)
+    }
+
+    /// Whether steps should remember the hash of their manifests and be skipped while it stays
+    /// the same.
+    pub fn skip_unchanged(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.skip_unchanged)
+            .unwrap_or(false)
+    }
+
+    /// Whether `--force` was given to run steps `skip_unchanged` would skip.
+    pub fn force(&self) -> bool {
+        self.opt.force
+    }
+
+    /// Whether to skip the steps that succeeded in the last run, see `--resume`.
+    pub fn resume(&self) -> bool {
+        self.opt.resume
+    }
+
+    /// The step to start the run at, see `--from-step`.
+    pub fn start_step(&self) -> Option<Step> {
+        self.opt.from_step
+    }
+
     /// Bash-it branch
     pub fn bashit_branch(&self) -> &str {
         self.config_file
@@ -1222,6 +2680,15 @@ impl Config {
             .unwrap_or("stable")
     }
 
+    /// Whether to pull the git repositories in the oh-my-zsh custom directory
+    pub fn omz_update_custom(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.omz_update_custom)
+            .unwrap_or(false)
+    }
+
     /// Whether to accept all Windows updates
     pub fn accept_all_windows_updates(&self) -> bool {
         self.config_file
@@ -1276,6 +2743,65 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// IDs of the winget packages that should not be upgraded
+    pub fn winget_exclude(&self) -> &[String] {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.winget_exclude.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The winget source to upgrade packages from, all sources if unset
+    pub fn winget_source(&self) -> Option<WingetSource> {
+        self.config_file.windows.as_ref().and_then(|w| w.winget_source)
+    }
+
+    /// Which updates the macOS system update installs
+    pub fn softwareupdate_updates(&self) -> SoftwareUpdates {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.softwareupdate_updates)
+            .unwrap_or_default()
+    }
+
+    /// Whether the macOS system update also installs configuration data, such as XProtect
+    pub fn softwareupdate_include_config_data(&self) -> bool {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.softwareupdate_include_config_data)
+            .unwrap_or(false)
+    }
+
+    /// Whether the macOS system update may restart the computer when an update requires it
+    pub fn softwareupdate_restart(&self) -> bool {
+        self.config_file
+            .macos
+            .as_ref()
+            .and_then(|macos| macos.softwareupdate_restart)
+            .unwrap_or(false)
+    }
+
+    /// Whether the maintenance step should rebuild the `man` page index
+    pub fn maintenance_man_db(&self) -> bool {
+        self.config_file
+            .maintenance
+            .as_ref()
+            .and_then(|maintenance| maintenance.man_db)
+            .unwrap_or(false)
+    }
+
+    /// Whether the maintenance step should update the `locate` database
+    pub fn maintenance_locate(&self) -> bool {
+        self.config_file
+            .maintenance
+            .as_ref()
+            .and_then(|maintenance| maintenance.locate)
+            .unwrap_or(false)
+    }
+
     /// Whether Brew cask should be greedy
     pub fn brew_cask_greedy(&self) -> bool {
         self.config_file
@@ -1312,12 +2838,26 @@ impl Config {
             .unwrap_or(false)
     }
 
-    /// Whether Brew should upgrade formulae built from the HEAD branch
-    pub fn brew_fetch_head(&self) -> bool {
+    /// Whether Brew should upgrade formulae built from the HEAD branch
+    pub fn brew_fetch_head(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.fetch_head)
+            .unwrap_or(false)
+    }
+
+    /// The Brewfile the BrewBundle step installs.
+    pub fn brew_bundle_file(&self) -> Option<&str> {
+        self.config_file.brew.as_ref().and_then(|c| c.bundle_file.as_deref())
+    }
+
+    /// Whether the BrewBundle step should uninstall what isn't listed in the Brewfile
+    pub fn brew_bundle_cleanup(&self) -> bool {
         self.config_file
             .brew
             .as_ref()
-            .and_then(|c| c.fetch_head)
+            .and_then(|c| c.bundle_cleanup)
             .unwrap_or(false)
     }
 
@@ -1339,6 +2879,15 @@ impl Config {
             .unwrap_or_default()
     }
 
+    /// How the Neovim step upgrades plugins.
+    pub fn neovim_plugin_manager(&self) -> NeovimPluginManager {
+        self.config_file
+            .vim
+            .as_ref()
+            .and_then(|vim| vim.neovim_plugin_manager)
+            .unwrap_or_default()
+    }
+
     /// Whether to send a desktop notification at the beginning of every step
     pub fn notify_each_step(&self) -> bool {
         self.config_file
@@ -1469,6 +3018,14 @@ impl Config {
             .and_then(|linux| linux.nix_env_arguments.as_deref())
     }
 
+    /// Directories holding a `flake.nix` whose inputs the Nix flake step updates.
+    pub fn nix_flake_dirs(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.nix_flake_dirs.as_ref())
+    }
+
     /// Extra Home Manager arguments
     pub fn home_manager(&self) -> Option<&Vec<String>> {
         self.config_file
@@ -1526,6 +3083,51 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Run `apt-get autoremove` after upgrading, even without `cleanup`
+    pub fn apt_autoremove(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_autoremove)
+            .unwrap_or(false)
+    }
+
+    /// Run `apt-get clean` after upgrading, even without `cleanup`
+    pub fn apt_clean(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_clean)
+            .unwrap_or(false)
+    }
+
+    /// Install phased updates right away instead of waiting for their rollout
+    pub fn apt_include_phased(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_include_phased)
+            .unwrap_or(false)
+    }
+
+    /// Whether apt should take the default answer to every question when assuming yes.
+    pub fn apt_noninteractive(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.apt_noninteractive)
+            .unwrap_or(false)
+    }
+
+    /// Whether zypper should take the default answer to every question when assuming yes.
+    pub fn zypper_noninteractive(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.zypper_noninteractive)
+            .unwrap_or(false)
+    }
+
     /// Use distro-sync in Red Hat based distributions
     pub fn redhat_distro_sync(&self) -> bool {
         self.config_file
@@ -1562,6 +3164,36 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether `step` must not use sudo, see `no_sudo_steps`.
+    pub fn no_sudo(&self, step: Step) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.no_sudo_steps.as_ref())
+            .is_some_and(|steps| steps.contains(&step))
+    }
+
+    /// Whether the output of `step` should only be shown if it fails, see `quiet_steps`.
+    pub fn quiet(&self, step: Step) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.quiet_steps.as_ref())
+            .is_some_and(|steps| steps.contains(&step))
+    }
+
+    /// Whether Ctrl+C should skip the current step instead of asking what to do, see
+    /// `--keep-going` and `ctrl_c_skips_step`.
+    pub fn ctrl_c_skips_step(&self) -> bool {
+        self.opt.keep_going
+            || self
+                .config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.ctrl_c_skips_step)
+                .unwrap_or(false)
+    }
+
     /// Determine if we should ignore failures for this step
     pub fn ignore_failure(&self, step: Step) -> bool {
         self.config_file
@@ -1610,6 +3242,32 @@ impl Config {
         self.opt.show_skipped
     }
 
+    /// Where to keep a log of the run, `{date}` gets replaced by the time the run started.
+    #[cfg(unix)]
+    pub fn log_file(&self) -> Option<&str> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.log_file.as_deref())
+    }
+
+    /// How many log files to keep.
+    #[cfg(unix)]
+    pub fn log_file_max(&self) -> Option<usize> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.log_file_max)
+    }
+
+    /// The maximum number of steps to run at the same time.
+    pub fn jobs(&self) -> usize {
+        self.opt
+            .jobs
+            .or_else(|| self.config_file.misc.as_ref().and_then(|misc| misc.parallelism))
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// The format of the summary printed at the end of the run.
+    pub fn output_format(&self) -> OutputFormat {
+        self.opt.output_format
+    }
+
     pub fn open_remotes_in_new_terminal(&self) -> bool {
         self.config_file
             .windows
@@ -1640,6 +3298,25 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// If `true`, the cached `sudo` credentials should be refreshed in the background until
+    /// the run ends.
+    pub fn sudo_keepalive(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.sudo_keepalive)
+            .unwrap_or(false)
+    }
+
+    /// Whether to also update the project in the current directory
+    pub fn npm_update_local(&self) -> bool {
+        self.config_file
+            .npm
+            .as_ref()
+            .and_then(|npm| npm.update_local)
+            .unwrap_or(false)
+    }
+
     #[cfg(target_os = "linux")]
     pub fn npm_use_sudo(&self) -> bool {
         self.config_file
@@ -1657,6 +3334,35 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// The toolchains to update instead of all the installed ones.
+    pub fn rustup_toolchains(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .rustup
+            .as_ref()
+            .and_then(|rustup| rustup.toolchains.as_ref())
+    }
+
+    /// The components to add to the toolchains in `rustup_toolchains`.
+    pub fn rustup_components(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .rustup
+            .as_ref()
+            .and_then(|rustup| rustup.components.as_ref())
+    }
+
+    /// Whether `rustup` should update itself, `None` to leave it to `rustup update`.
+    pub fn rustup_self_update(&self) -> Option<bool> {
+        self.config_file.rustup.as_ref().and_then(|rustup| rustup.self_update)
+    }
+
+    /// The conda environments to update instead of only `base`.
+    pub fn conda_environments(&self) -> Option<&CondaEnvironments> {
+        self.config_file
+            .python
+            .as_ref()
+            .and_then(|python| python.conda_environments.as_ref())
+    }
+
     pub fn deno_version(&self) -> Option<&str> {
         self.config_file.deno.as_ref().and_then(|deno| deno.version.as_deref())
     }
@@ -1679,6 +3385,16 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether to uninstall unused runtimes after updating, even without `cleanup`.
+    #[cfg(target_os = "linux")]
+    pub fn flatpak_remove_unused(&self) -> bool {
+        self.config_file
+            .flatpak
+            .as_ref()
+            .and_then(|flatpak| flatpak.remove_unused)
+            .unwrap_or(false)
+    }
+
     #[cfg(target_os = "linux")]
     str_value!(linux, emerge_sync_flags);
 
@@ -1715,6 +3431,15 @@ impl Config {
             .and_then(|s| s.pipupgrade_arguments.as_deref())
             .unwrap_or("")
     }
+    /// Whether `pipx upgrade-all` also upgrades the packages injected into apps.
+    pub fn pipx_include_injected(&self) -> bool {
+        self.config_file
+            .python
+            .as_ref()
+            .and_then(|python| python.pipx_include_injected)
+            .unwrap_or(true)
+    }
+
     pub fn enable_pip_review(&self) -> bool {
         self.config_file
             .python
@@ -1737,6 +3462,15 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether the summary should list the steps grouped by their result.
+    pub fn summary_group_by_status(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.summary_group_by_status)
+            .unwrap_or(false)
+    }
+
     pub fn display_time(&self) -> bool {
         self.config_file
             .misc
@@ -1745,6 +3479,81 @@ impl Config {
             .unwrap_or(true)
     }
 
+    /// The character step titles are drawn with.
+    pub fn separator_char(&self) -> Option<char> {
+        self.config_file.misc.as_ref().and_then(|misc| misc.separator_char)
+    }
+
+    /// The width of step titles, at most the width of the terminal. 0 means the whole width.
+    pub fn separator_width(&self) -> usize {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.separator_width)
+            .unwrap_or(80)
+    }
+
+    /// The color of step titles, such as `cyan`.
+    pub fn separator_color(&self) -> Option<&str> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.separator_color.as_deref())
+    }
+
+    /// Whether to print without colors or styles, also the case when `NO_COLOR` is set.
+    pub fn no_color(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.no_color)
+            .unwrap_or(false)
+            || env::var_os("NO_COLOR").is_some_and(|no_color| !no_color.is_empty())
+    }
+
+    /// Extra environment variables for the commands run by the given step.
+    pub fn step_env(&self, step: Step) -> Vec<(String, String)> {
+        self.config_file
+            .step_env
+            .as_ref()
+            .and_then(|step_env| step_env.get(&step))
+            .map(|env| env.iter().map(|(key, value)| (key.clone(), value.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    /// How long a command may print nothing before a notice tells that it is still running.
+    pub fn step_silence_warning(&self) -> Option<Duration> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.step_silence_warning)
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Whether to prefix every line commands print with the name of their step.
+    pub fn prefix_step_output(&self) -> bool {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.prefix_step_output)
+            .unwrap_or(false)
+    }
+
+    /// How long the given step may run before it gets killed, if it has a time limit.
+    ///
+    /// Per-step values in `[step_timeouts]` take precedence over the global `timeout`;
+    /// a value of 0 disables the limit.
+    pub fn step_timeout(&self, step: Step) -> Option<Duration> {
+        self.config_file
+            .step_timeouts
+            .as_ref()
+            .and_then(|timeouts| timeouts.get(&step).copied())
+            .or_else(|| self.config_file.misc.as_ref().and_then(|misc| misc.timeout))
+            .filter(|seconds| *seconds > 0)
+            .map(Duration::from_secs)
+    }
+
     pub fn should_run_custom_command(&self, name: &str) -> bool {
         if self.opt.custom_commands.is_empty() {
             return true;
@@ -1769,6 +3578,23 @@ impl Config {
             .unwrap_or(true)
     }
 
+    /// Whether to upgrade the Helm releases whose chart has a newer version
+    pub fn helm_upgrade_releases(&self) -> bool {
+        self.config_file
+            .helm
+            .as_ref()
+            .and_then(|helm| helm.upgrade_releases)
+            .unwrap_or(false)
+    }
+
+    /// The namespaces to upgrade Helm releases in, all of them if `None`
+    pub fn helm_namespaces(&self) -> Option<&[String]> {
+        self.config_file
+            .helm
+            .as_ref()
+            .and_then(|helm| helm.namespaces.as_deref())
+    }
+
     pub fn zigup_target_versions(&self) -> Vec<String> {
         self.config_file
             .zigup
@@ -1777,6 +3603,80 @@ impl Config {
             .unwrap_or(vec!["master".to_owned()])
     }
 
+    /// The URL of the ntfy topic to notify at the end of a run.
+    pub fn ntfy_url(&self) -> Option<&str> {
+        self.config_file.ntfy.as_ref().and_then(|ntfy| ntfy.url.as_deref())
+    }
+
+    /// The access token used to publish to the ntfy topic.
+    pub fn ntfy_token(&self) -> Option<&str> {
+        self.config_file.ntfy.as_ref().and_then(|ntfy| ntfy.token.as_deref())
+    }
+
+    fn webhook(&self) -> Option<&Webhook> {
+        self.config_file
+            .notifications
+            .as_ref()
+            .and_then(|notifications| notifications.webhook.as_ref())
+    }
+
+    /// The URL to post a summary of the run to at the end of a run.
+    pub fn webhook_url(&self) -> Option<&str> {
+        self.webhook().and_then(|webhook| webhook.url.as_deref())
+    }
+
+    /// The shape of the payload posted to the webhook.
+    pub fn webhook_format(&self) -> WebhookFormat {
+        self.webhook().and_then(|webhook| webhook.format).unwrap_or_default()
+    }
+
+    /// The text of the webhook message, with placeholders for the summary of the run.
+    pub fn webhook_template(&self) -> Option<&str> {
+        self.webhook().and_then(|webhook| webhook.template.as_deref())
+    }
+
+    fn telegram(&self) -> Option<&Telegram> {
+        self.config_file
+            .notifications
+            .as_ref()
+            .and_then(|notifications| notifications.telegram.as_ref())
+    }
+
+    /// The token of the Telegram bot sending a summary of the run at the end of a run.
+    pub fn telegram_bot_token(&self) -> Option<&str> {
+        self.telegram().and_then(|telegram| telegram.bot_token.as_deref())
+    }
+
+    /// The chat the Telegram bot sends the summary to.
+    pub fn telegram_chat_id(&self) -> Option<&str> {
+        self.telegram().and_then(|telegram| telegram.chat_id.as_deref())
+    }
+
+    /// Where to send the summary of the run by email, if `smtp_host`, `from` and `to` are set.
+    pub fn email(&self) -> Option<EmailConfig<'_>> {
+        let email = self
+            .config_file
+            .notifications
+            .as_ref()
+            .and_then(|notifications| notifications.email.as_ref())?;
+        let host = email.smtp_host.as_deref()?;
+        let tls = email.tls.unwrap_or_default();
+        let (scheme, default_port) = match tls {
+            EmailTls::Starttls => ("smtp", 587),
+            EmailTls::Tls => ("smtps", 465),
+            EmailTls::None => ("smtp", 25),
+        };
+
+        Some(EmailConfig {
+            url: format!("{scheme}://{host}:{}", email.smtp_port.unwrap_or(default_port)),
+            tls,
+            username: email.username.as_deref(),
+            password: email.password.as_deref(),
+            from: email.from.as_deref()?,
+            to: email.to.as_deref().filter(|to| !to.is_empty())?,
+        })
+    }
+
     pub fn zigup_install_dir(&self) -> Option<&str> {
         self.config_file
             .zigup
@@ -1799,6 +3699,15 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Whether to also update the VSCode/VSCodium CLI, when it can update itself.
+    pub fn vscode_update_app(&self) -> bool {
+        self.config_file
+            .vscode
+            .as_ref()
+            .and_then(|vscode| vscode.update_app)
+            .unwrap_or(false)
+    }
+
     pub fn vscode_profile(&self) -> Option<&str> {
         let vscode_cfg = self.config_file.vscode.as_ref()?;
         let profile = vscode_cfg.profile.as_ref()?;
@@ -1833,6 +3742,30 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            [ntfy]
+            token = "ntfy-secret"
+
+            [notifications.webhook]
+            url = "https://example.com/webhook-secret"
+
+            [notifications.telegram]
+            bot_token = "telegram-secret"
+
+            [notifications.email]
+            password = "email-secret"
+            "#,
+        )
+        .unwrap();
+
+        let debug = format!("{config:?}");
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("secret"));
+    }
+
     #[test]
     fn test_should_execute_remote_different_hostname() {
         assert!(config().should_execute_remote(Ok("hostname".to_string()), "remote_hostname"));
@@ -1871,4 +3804,331 @@ mod test {
         config.opt = CommandLineArgs::parse_from(["topgrade", "--remote-host-limit", "other_hostname"]);
         assert!(!config.should_execute_remote(Ok("hostname".to_string()), "user@remote_hostname"));
     }
+
+    #[test]
+    fn test_step_env() {
+        let mut config = config();
+        config.config_file = toml::from_str("[step_env]\ncargo = { CARGO_NET_GIT_FETCH_WITH_CLI = \"true\" }").unwrap();
+        assert_eq!(
+            config.step_env(Step::Cargo),
+            vec![("CARGO_NET_GIT_FETCH_WITH_CLI".to_string(), "true".to_string())]
+        );
+        assert!(config.step_env(Step::Pipx).is_empty());
+    }
+
+    #[test]
+    fn test_custom_command_forms() {
+        let config: ConfigFile = toml::from_str(
+            "[commands]\nplain = \"make\"\ntable = { command = \"make install\", cwd = \"~/src\", shell = \"bash\" }",
+        )
+        .unwrap();
+        let commands = config.commands.unwrap();
+
+        assert_eq!(commands["plain"].command(), "make");
+        assert_eq!(commands["plain"].cwd(), None);
+        assert_eq!(commands["table"].command(), "make install");
+        assert_eq!(commands["table"].cwd(), Some("~/src"));
+        assert_eq!(commands["table"].shell(), Some("bash"));
+    }
+
+    #[test]
+    fn test_remote_topgrade_forms() {
+        let config: ConfigFile = toml::from_str(
+            "[misc]\nremote_topgrades = [\"pi\", { host = \"admin@db1\", proxy_jump = \"bastion\", port = 2222 }]",
+        )
+        .unwrap();
+        let remotes = config.misc.unwrap().remote_topgrades.unwrap();
+
+        assert_eq!(remotes[0].host(), "pi");
+        assert!(remotes[0].ssh_options().is_empty());
+        assert_eq!(remotes[1].host(), "admin@db1");
+        assert_eq!(remotes[1].ssh_options(), vec!["-J", "bastion", "-p", "2222"]);
+    }
+
+    #[test]
+    fn test_custom_package_manager_validation() {
+        let parse = |contents: &str| toml::from_str::<ConfigFile>(contents);
+        let entry = "[[custom_package_manager]]\nname = \"eget\"\ndetect = \"command -v eget\"\nupdate = \"eget -a\"\n";
+
+        assert!(parse(entry).unwrap().validate_custom_package_managers().is_ok());
+        assert!(parse("[[custom_package_manager]]\nname = \"eget\"\ndetect = \"command -v eget\"").is_err());
+        assert!(parse(&entry.replace("eget -a", ""))
+            .unwrap()
+            .validate_custom_package_managers()
+            .is_err());
+        assert!(parse(&format!("{entry}{entry}"))
+            .unwrap()
+            .validate_custom_package_managers()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(24 * 60 * 60)));
+        assert_eq!(parse_duration("3d"), Ok(Duration::from_secs(3 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(90 * 60)));
+        assert!(parse_duration("24").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("0h").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn test_time_range() {
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+
+        let night = TimeRange::try_from(String::from("02:00-05:00")).unwrap();
+        assert!(night.contains(time(2, 0)));
+        assert!(night.contains(time(4, 59)));
+        assert!(!night.contains(time(5, 0)));
+        assert!(!night.contains(time(23, 0)));
+        assert_eq!(night.to_string(), "02:00-05:00");
+
+        let midnight = TimeRange::try_from(String::from("22:30 - 01:00")).unwrap();
+        assert!(midnight.contains(time(23, 0)));
+        assert!(midnight.contains(time(0, 30)));
+        assert!(!midnight.contains(time(1, 0)));
+        assert!(!midnight.contains(time(12, 0)));
+
+        assert!(TimeRange::try_from(String::from("02:00")).is_err());
+        assert!(TimeRange::try_from(String::from("2am-5am")).is_err());
+        assert!(TimeRange::try_from(String::from("25:00-05:00")).is_err());
+    }
+
+    #[test]
+    fn test_conda_environments() {
+        let parse = |toml: &str| toml::from_str::<Python>(toml).map(|python| python.conda_environments);
+
+        assert!(matches!(
+            parse(r#"conda_environments = "all""#),
+            Ok(Some(CondaEnvironments::All(_)))
+        ));
+        assert!(matches!(
+            parse(r#"conda_environments = ["base", "ml"]"#),
+            Ok(Some(CondaEnvironments::Named(names))) if names == ["base", "ml"]
+        ));
+        assert!(parse(r#"conda_environments = "ml""#).is_err());
+    }
+
+    #[test]
+    fn test_only_minus_disabled() {
+        let opt = CommandLineArgs::parse_from(["topgrade", "--only", "cargo", "pipx", "--disable", "pipx"]);
+        let allowed_steps = Config::allowed_steps(&opt, &ConfigFile::default(), None).unwrap();
+        assert_eq!(allowed_steps, vec![Step::Cargo]);
+    }
+
+    #[test]
+    fn test_only_overrides_config_disable() {
+        let config = toml::from_str("[misc]\ndisable = [\"cargo\", \"pipx\"]").unwrap();
+        let opt = CommandLineArgs::parse_from(["topgrade", "--only", "cargo", "system"]);
+        let allowed_steps = Config::allowed_steps(&opt, &config, None).unwrap();
+        assert_eq!(allowed_steps, vec![Step::Cargo, Step::System]);
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--only", "cargo", "--disable", "cargo"]);
+        let allowed_steps = Config::allowed_steps(&opt, &config, None).unwrap();
+        assert!(allowed_steps.is_empty());
+    }
+
+    #[test]
+    fn test_include_precedence() {
+        let directory = env::temp_dir().join(format!("topgrade-test-include-{}", std::process::id()));
+        fs::create_dir_all(directory.join("common")).unwrap();
+        fs::write(
+            directory.join("common/base.toml"),
+            "[misc]\nassume_yes = true\ncleanup = true\nno_retry = true\n",
+        )
+        .unwrap();
+        fs::write(directory.join("common/extra.toml"), "[misc]\ncleanup = false\n").unwrap();
+        fs::write(
+            directory.join("topgrade.toml"),
+            "[include]\npaths = [\"common/base.toml\", \"common/extra.toml\"]\n\n[misc]\nassume_yes = false\n",
+        )
+        .unwrap();
+
+        let config = ConfigFile::read(&[directory.join("topgrade.toml")]).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let misc = config.misc.unwrap();
+        // The main file overrides its includes, which override each other in order
+        assert_eq!(misc.assume_yes, Some(false));
+        assert_eq!(misc.cleanup, Some(false));
+        assert_eq!(misc.no_retry, Some(true));
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let directory = env::temp_dir().join(format!("topgrade-test-include-errors-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("missing.toml"), "[include]\npaths = [\"typo.toml\"]\n").unwrap();
+        fs::write(directory.join("a.toml"), "[include]\npaths = [\"b.toml\"]\n").unwrap();
+        fs::write(directory.join("b.toml"), "[include]\npaths = [\"a.toml\"]\n").unwrap();
+
+        let missing = ConfigFile::read(&[directory.join("missing.toml")]);
+        let circular = ConfigFile::read(&[directory.join("a.toml")]);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let missing = format!("{:#}", missing.unwrap_err());
+        assert!(missing.contains("typo.toml does not exist"), "{missing}");
+        assert!(missing.contains("missing.toml"), "{missing}");
+        assert!(format!("{:#}", circular.unwrap_err()).contains("Circular include"));
+    }
+
+    #[test]
+    fn test_multiple_config_files() {
+        let directory = env::temp_dir().join(format!("topgrade-test-multiple-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("base.toml"),
+            "[misc]\nassume_yes = true\ncleanup = true\n",
+        )
+        .unwrap();
+        fs::write(directory.join("overlay.toml"), "[misc]\ncleanup = false\n").unwrap();
+
+        let config = ConfigFile::read(&[directory.join("base.toml"), directory.join("overlay.toml")]).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let misc = config.misc.unwrap();
+        assert_eq!(misc.assume_yes, Some(true));
+        assert_eq!(misc.cleanup, Some(false));
+    }
+
+    #[test]
+    fn test_dump_redacts_secrets() {
+        let config = Config {
+            opt: CommandLineArgs::parse_from(["topgrade"]),
+            config_file: toml::from_str(
+                r#"
+                [misc]
+                cleanup = true
+
+                [notifications.webhook]
+                url = "https://hooks.slack.com/services/T000/B000/secret"
+                "#,
+            )
+            .unwrap(),
+            allowed_steps: vec![Step::Cargo],
+        };
+
+        let dump = config.dump().unwrap();
+        assert!(dump.contains("enabled_steps = [\"cargo\"]"));
+        assert!(dump.contains("cleanup = true"));
+        assert!(dump.contains("url = \"<redacted>\""));
+        assert!(!dump.contains("secret"));
+    }
+
+    #[test]
+    fn test_step_groups() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            [misc]
+            disable = ["languages"]
+
+            [step_groups]
+            python = ["pipx", "pip3"]
+            languages = ["cargo", "python"]
+            loop = ["cargo", "loop"]
+            "#,
+        )
+        .unwrap();
+
+        let opt = CommandLineArgs::parse_from(["topgrade"]);
+        let allowed_steps = Config::allowed_steps(&opt, &config, None).unwrap();
+        assert!(!allowed_steps.contains(&Step::Cargo));
+        assert!(!allowed_steps.contains(&Step::Pip3));
+        assert!(allowed_steps.contains(&Step::System));
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--only", "python", "system"]);
+        let allowed_steps = Config::allowed_steps(&opt, &ConfigFile::default(), None);
+        assert!(allowed_steps.is_err());
+        let allowed_steps = Config::allowed_steps(
+            &opt,
+            &toml::from_str("[step_groups]\npython = [\"pipx\"]").unwrap(),
+            None,
+        );
+        assert_eq!(allowed_steps.unwrap(), vec![Step::Pipx, Step::System]);
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--disable", "Jetbrains*", "[invalid"]);
+        let allowed_steps = Config::allowed_steps(&opt, &ConfigFile::default(), None).unwrap();
+        assert!(!allowed_steps.contains(&Step::JetbrainsAqua));
+        assert!(!allowed_steps.contains(&Step::JetbrainsToolbox));
+        assert!(allowed_steps.contains(&Step::System));
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--only", "jetbrains_p*"]);
+        let allowed_steps = Config::allowed_steps(&opt, &ConfigFile::default(), None).unwrap();
+        assert!(!allowed_steps.is_empty());
+        assert!(allowed_steps
+            .iter()
+            .all(|step| step.as_ref().starts_with("jetbrains_p")));
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--only", "loop"]);
+        assert!(Config::allowed_steps(&opt, &config, None).is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config = || -> ConfigFile {
+            toml::from_str(
+                r#"
+                [misc]
+                assume_yes = true
+                cleanup = true
+
+                [step_timeouts]
+                system = 600
+                cargo = 60
+
+                [profiles.work.misc]
+                cleanup = false
+
+                [profiles.work.step_timeouts]
+                system = 0
+
+                [profiles.home]
+                "#,
+            )
+            .unwrap()
+        };
+
+        let work = config().with_profile("work").unwrap();
+        let misc = work.misc.unwrap();
+        assert_eq!(misc.assume_yes, Some(true));
+        assert_eq!(misc.cleanup, Some(false));
+        let timeouts = work.step_timeouts.unwrap();
+        assert_eq!(timeouts.get(&Step::System), Some(&0));
+        assert_eq!(timeouts.get(&Step::Cargo), Some(&60));
+
+        let error = config().with_profile("play").unwrap_err().to_string();
+        assert!(error.contains("work, home"), "{error}");
+    }
+
+    #[test]
+    fn test_hostname_overrides() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            [misc]
+            disable = ["cargo", "pipx"]
+
+            [hostname_overrides.laptop]
+            disable = ["system"]
+            enable = ["pipx"]
+            "#,
+        )
+        .unwrap();
+        let opt = CommandLineArgs::parse_from(["topgrade"]);
+
+        let laptop = Config::allowed_steps(&opt, &config, Some("laptop")).unwrap();
+        assert!(!laptop.contains(&Step::Cargo));
+        assert!(laptop.contains(&Step::Pipx));
+        assert!(!laptop.contains(&Step::System));
+
+        let server = Config::allowed_steps(&opt, &config, Some("server")).unwrap();
+        assert!(!server.contains(&Step::Cargo));
+        assert!(!server.contains(&Step::Pipx));
+        assert!(server.contains(&Step::System));
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--disable", "pipx"]);
+        let laptop = Config::allowed_steps(&opt, &config, Some("laptop")).unwrap();
+        assert!(!laptop.contains(&Step::Pipx));
+    }
 }
@@ -1,8 +1,18 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// A global variable telling whether the application has been interrupted.
 static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
+/// When the application was last interrupted, in milliseconds since the Unix epoch.
+static LAST_INTERRUPT: AtomicU64 = AtomicU64::new(0);
+
+/// Whether the application has been interrupted twice within `ABORT_WINDOW_MS`.
+static ABORTED: AtomicBool = AtomicBool::new(false);
+
+/// How close together two interrupts must be to abort the run, see `aborted`.
+const ABORT_WINDOW_MS: u64 = 2000;
+
 /// Tells whether the program has been interrupted
 pub fn interrupted() -> bool {
     INTERRUPTED.load(Ordering::SeqCst)
@@ -14,6 +24,18 @@ pub fn unset_interrupted() {
     INTERRUPTED.store(false, Ordering::SeqCst);
 }
 
+/// Tells whether the program has been interrupted twice in a row, which aborts the run when
+/// `ctrl_c_skips_step` is set.
+pub fn aborted() -> bool {
+    ABORTED.load(Ordering::SeqCst)
+}
+
 pub fn set_interrupted() {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64);
+    if now.saturating_sub(LAST_INTERRUPT.swap(now, Ordering::SeqCst)) < ABORT_WINDOW_MS {
+        ABORTED.store(true, Ordering::SeqCst);
+    }
     INTERRUPTED.store(true, Ordering::SeqCst);
 }
@@ -2,9 +2,11 @@
 use crate::ctrlc::interrupted::set_interrupted;
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 
-/// Handle SIGINT. Set the interruption flag.
+/// Handle SIGINT. Set the interruption flag, and pass the signal on to the commands that
+/// don't get it from the terminal themselves.
 extern "C" fn handle_sigint(_: i32) {
     set_interrupted();
+    crate::executor::forward_interrupt();
 }
 
 /// Set the necessary signal handlers.
@@ -1,4 +1,6 @@
-use std::{fmt::Display, process::ExitStatus};
+use std::{fmt::Display, io, process::ExitStatus};
+
+use color_eyre::eyre::Report;
 
 use rust_i18n::t;
 use thiserror::Error;
@@ -68,6 +70,33 @@ impl Display for StepFailed {
     }
 }
 
+/// The configuration could not be loaded, e.g. it refers to an unknown step.
+#[derive(Error, Debug)]
+pub struct ConfigError;
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", t!("Invalid configuration"))
+    }
+}
+
+/// Another Topgrade holds the lock that keeps two runs apart, see `lock::acquire`.
+#[derive(Error, Debug)]
+pub struct AlreadyRunning(pub String);
+
+impl Display for AlreadyRunning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            t!(
+                "Topgrade is already running (PID {pid}), pass --wait to wait for it to finish",
+                pid = self.0
+            )
+        )
+    }
+}
+
 #[derive(Error, Debug)]
 pub struct DryRun();
 
@@ -86,6 +115,21 @@ impl Display for SkipStep {
     }
 }
 
+/// A step was skipped because the tool it updates isn't installed, see `utils::require`.
+#[derive(Error, Debug)]
+pub struct NotInstalled(pub String);
+
+impl Display for NotInstalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Whether `error` tells that a step, or a custom command, skipped itself.
+pub fn is_skipped(error: &Report) -> bool {
+    error.is::<SkipStep>() || error.is::<NotInstalled>()
+}
+
 #[cfg(all(windows, feature = "self-update"))]
 #[derive(Error, Debug)]
 pub struct Upgraded(pub ExitStatus);
@@ -96,3 +140,67 @@ impl Display for Upgraded {
         write!(f, "{}", t!("Topgrade Upgraded"))
     }
 }
+
+/// Topgrade upgraded itself, but failed to run the new version in its place.
+#[cfg(feature = "self-update")]
+#[derive(Error, Debug)]
+pub struct RespawnFailed;
+
+#[cfg(feature = "self-update")]
+impl Display for RespawnFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", t!("Failed to run the upgraded Topgrade"))
+    }
+}
+
+/// The exit codes of Topgrade, so that scripts can tell failures apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// One or more steps failed.
+    StepFailed = 1,
+    /// Any other error.
+    Error = 2,
+    /// The configuration could not be loaded.
+    ConfigError = 3,
+    /// Topgrade upgraded itself and the new version could not take over the run.
+    ///
+    /// On Windows, the new version runs as a child process and its exit code is used instead.
+    SelfUpdated = 4,
+    /// Another Topgrade was already running.
+    AlreadyRunning = 5,
+    /// The run was interrupted, e.g. by quitting from a retry prompt.
+    Interrupted = 130,
+}
+
+impl ExitCode {
+    /// The exit code for a run that ended with `error`.
+    pub fn from_error(error: &Report) -> Self {
+        if error.downcast_ref::<StepFailed>().is_some() {
+            ExitCode::StepFailed
+        } else if error.downcast_ref::<ConfigError>().is_some() {
+            ExitCode::ConfigError
+        } else if error
+            .downcast_ref::<io::Error>()
+            .is_some_and(|io_error| io_error.kind() == io::ErrorKind::Interrupted)
+        {
+            ExitCode::Interrupted
+        } else if error.downcast_ref::<AlreadyRunning>().is_some() {
+            ExitCode::AlreadyRunning
+        } else if Self::is_self_update(error) {
+            ExitCode::SelfUpdated
+        } else {
+            ExitCode::Error
+        }
+    }
+
+    #[cfg(feature = "self-update")]
+    fn is_self_update(error: &Report) -> bool {
+        error.downcast_ref::<RespawnFailed>().is_some()
+    }
+
+    #[cfg(not(feature = "self-update"))]
+    fn is_self_update(_error: &Report) -> bool {
+        false
+    }
+}
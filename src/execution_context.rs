@@ -1,13 +1,25 @@
 #![allow(dead_code)]
+use crate::config::Step;
+use crate::error::SkipStep;
 use crate::executor::RunType;
 use crate::sudo::Sudo;
 use crate::utils::{get_require_sudo_string, require_option};
 use crate::{config::Config, executor::Executor};
 use color_eyre::eyre::Result;
+use rust_i18n::t;
+use std::cell::Cell;
 use std::env::var;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 
+thread_local! {
+    /// The step running on this thread, if any. Steps may run in parallel, see `--jobs`.
+    static CURRENT_STEP: Cell<Option<Step>> = const { Cell::new(None) };
+    /// How many packages the step running on this thread updated, if it told.
+    static UPDATED_PACKAGES: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
 pub struct ExecutionContext<'a> {
     run_type: RunType,
     sudo: Option<Sudo>,
@@ -18,6 +30,12 @@ pub struct ExecutionContext<'a> {
     tmux_session: Mutex<Option<String>>,
     /// True if topgrade is running under ssh.
     under_ssh: bool,
+    /// Whether steps that download a lot should skip themselves, see `--offline`.
+    offline: bool,
+    /// Whether a step found that a reboot is needed to finish updating, see `reboot` in the config.
+    reboot_required: AtomicBool,
+    /// Whether the current step is being previewed, see `--confirm`.
+    simulating: AtomicBool,
 }
 
 impl<'a> ExecutionContext<'a> {
@@ -29,20 +47,66 @@ impl<'a> ExecutionContext<'a> {
             config,
             tmux_session: Mutex::new(None),
             under_ssh,
+            offline: config.offline(),
+            reboot_required: AtomicBool::new(false),
+            simulating: AtomicBool::new(false),
         }
     }
 
+    /// Execute `command` with sudo, or directly in steps listed in `no_sudo_steps`.
     pub fn execute_elevated(&self, command: &Path, interactive: bool) -> Result<Executor> {
+        if self.no_sudo() {
+            return Ok(self.run_type().execute(command));
+        }
+
         let sudo = require_option(self.sudo.as_ref(), get_require_sudo_string())?;
         Ok(sudo.execute_elevated(self, command, interactive))
     }
 
+    /// How commands should run. While a step is previewed, this is `RunType::Explain`.
     pub fn run_type(&self) -> RunType {
-        self.run_type
+        if self.simulating.load(Ordering::Relaxed) {
+            RunType::Explain
+        } else {
+            self.run_type
+        }
+    }
+
+    /// Make steps dry run until this is called again with `false`, see `--confirm`.
+    pub fn set_simulating(&self, simulating: bool) {
+        self.simulating.store(simulating, Ordering::Relaxed);
     }
 
+    /// The sudo command, which is `None` in steps listed in `no_sudo_steps`.
     pub fn sudo(&self) -> &Option<Sudo> {
-        &self.sudo
+        if self.no_sudo() {
+            &None
+        } else {
+            &self.sudo
+        }
+    }
+
+    /// Record which step runs on the current thread.
+    pub fn set_current_step(&self, step: Option<Step>) {
+        CURRENT_STEP.set(step);
+        if step.is_some() {
+            UPDATED_PACKAGES.set(None);
+        }
+    }
+
+    /// Record that the current step updated `count` packages, for the summary.
+    pub fn record_updated_packages(&self, count: u64) {
+        UPDATED_PACKAGES.set(Some(UPDATED_PACKAGES.get().unwrap_or(0) + count));
+    }
+
+    /// How many packages the step that just ran on this thread updated, if it told.
+    pub fn take_updated_packages(&self) -> Option<u64> {
+        UPDATED_PACKAGES.take()
+    }
+
+    /// Whether the current step must not use sudo.
+    fn no_sudo(&self) -> bool {
+        CURRENT_STEP.get().is_some_and(|step| self.config.no_sudo(step))
     }
 
     pub fn config(&self) -> &Config {
@@ -53,6 +117,24 @@ impl<'a> ExecutionContext<'a> {
         self.under_ssh
     }
 
+    /// Skip the current step in offline mode, for steps that download a lot.
+    pub fn require_online(&self) -> Result<()> {
+        if self.offline {
+            return Err(SkipStep(t!("Offline mode").to_string()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Record that a reboot is needed to finish updating.
+    pub fn require_reboot(&self) {
+        self.reboot_required.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reboot_required(&self) -> bool {
+        self.reboot_required.load(Ordering::Relaxed)
+    }
+
     pub fn set_tmux_session(&self, session_name: String) {
         self.tmux_session.lock().unwrap().replace(session_name);
     }
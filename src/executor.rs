@@ -1,7 +1,16 @@
 //! Utilities for command execution
+use std::cell::{Cell, RefCell};
 use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::iter;
 use std::path::Path;
 use std::process::{Child, Command, ExitStatus, Output};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use color_eyre::eyre::Result;
 use rust_i18n::t;
@@ -9,6 +18,419 @@ use tracing::debug;
 
 use crate::command::CommandExt;
 use crate::error::DryRun;
+use crate::plan::{self, PlannedCommand};
+use crate::terminal::print_info;
+
+/// Child processes Topgrade is currently waiting on, and the steps that ran past their deadline.
+struct Children {
+    /// PIDs of the running children, along with the ID of the step that started them.
+    running: Vec<(usize, u32)>,
+    /// Steps that ran past their deadline. Children they start get killed right away, so a timed
+    /// out step can't keep spawning new commands.
+    timed_out: Vec<usize>,
+}
+
+static CHILDREN: Mutex<Children> = Mutex::new(Children {
+    running: Vec::new(),
+    timed_out: Vec::new(),
+});
+
+thread_local! {
+    /// ID of the step running on this thread. Steps run on the main thread use `0`.
+    static CURRENT_STEP: Cell<usize> = const { Cell::new(0) };
+}
+
+thread_local! {
+    /// Environment variables from `[step_env]` for the step running on this thread.
+    static STEP_ENV: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+thread_local! {
+    /// Tag printed in front of every output line of the commands of the step running on this
+    /// thread, see `prefix_step_output`.
+    static OUTPUT_PREFIX: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Set the environment variables every command started by the step running on this thread
+/// gets, on top of Topgrade's own environment.
+pub fn set_step_env(vars: Vec<(String, String)>) {
+    STEP_ENV.with(|env| *env.borrow_mut() = vars);
+}
+
+/// Apply the environment variables set by `set_step_env` to `command`.
+pub fn apply_step_env(command: &mut Command) {
+    STEP_ENV.with(|env| {
+        command.envs(env.borrow().iter().map(|(key, value)| (key, value)));
+    });
+}
+
+/// Prefix every output line of the commands started by the step running on this thread with
+/// `prefix`, or stop doing so.
+pub fn set_output_prefix(prefix: Option<String>) {
+    OUTPUT_PREFIX.with(|cell| *cell.borrow_mut() = prefix);
+}
+
+/// See `set_output_prefix`.
+pub fn output_prefix() -> Option<String> {
+    OUTPUT_PREFIX.with(|cell| cell.borrow().clone())
+}
+
+/// How long a command may print nothing before `pump_output` tells that it is still running, in
+/// milliseconds. `0` means never, see `step_silence_warning`.
+static SILENCE_WARNING_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Set how long a command may print nothing before a notice tells that it is still running.
+pub fn set_silence_warning(interval: Option<Duration>) {
+    let millis = interval.map_or(0, |interval| interval.as_millis() as u64);
+    SILENCE_WARNING_MS.store(millis, Ordering::SeqCst);
+}
+
+/// See `set_silence_warning`.
+pub fn silence_warning() -> Option<Duration> {
+    match SILENCE_WARNING_MS.load(Ordering::SeqCst) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+/// Forward the piped output of `child` to our own stdout and stderr until it closes them,
+/// starting every line with `prefix` if given, and printing a notice whenever `command` goes
+/// `interval` without printing anything.
+pub fn pump_output(child: &mut Child, command: &str, interval: Option<Duration>, prefix: Option<&str>) {
+    let started = Instant::now();
+    let last_output = Mutex::new(started);
+    let (done, finished) = mpsc::channel::<()>();
+
+    let last_output = &last_output;
+    thread::scope(|scope| {
+        if let Some(interval) = interval {
+            scope.spawn(move || loop {
+                let silent = last_output.lock().unwrap().elapsed();
+                match finished.recv_timeout(interval.saturating_sub(silent)) {
+                    Err(RecvTimeoutError::Timeout) if last_output.lock().unwrap().elapsed() >= interval => {
+                        print_info(t!(
+                            "`{command}` is still running ({seconds} seconds so far)",
+                            command = command,
+                            seconds = started.elapsed().as_secs()
+                        ));
+                        *last_output.lock().unwrap() = Instant::now();
+                    }
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            });
+        }
+
+        let mut forwarders = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            forwarders.push(scope.spawn(|| forward(stdout, io::stdout(), last_output, prefix)));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forwarders.push(scope.spawn(|| forward(stderr, io::stderr(), last_output, prefix)));
+        }
+        for forwarder in forwarders {
+            forwarder.join().ok();
+        }
+        drop(done);
+    });
+}
+
+/// Copy `from` to `to` as it comes, starting every line with `prefix` if given, and recording
+/// when it last did in `last_output`.
+fn forward(mut from: impl Read, mut to: impl Write, last_output: &Mutex<Instant>, prefix: Option<&str>) {
+    let mut buffer = [0; 8192];
+    let mut line_start = true;
+    while let Ok(read @ 1..) = from.read(&mut buffer) {
+        *last_output.lock().unwrap() = Instant::now();
+        match prefix {
+            Some(prefix) => {
+                for line in buffer[..read].split_inclusive(|byte| *byte == b'\n') {
+                    if line_start {
+                        write!(to, "{prefix} ").ok();
+                    }
+                    to.write_all(line).ok();
+                    line_start = line.ends_with(b"\n");
+                }
+            }
+            None => {
+                to.write_all(&buffer[..read]).ok();
+            }
+        }
+        to.flush().ok();
+    }
+}
+
+/// Where dry runs write the commands they would run, see `--dry-run-script`.
+static SCRIPT: Mutex<Option<File>> = Mutex::new(None);
+
+/// Make dry runs write the commands they would run to `output` as a shell script, instead of
+/// printing them.
+pub fn start_script(mut output: File) -> io::Result<()> {
+    writeln!(output, "#!/bin/sh")?;
+    *SCRIPT.lock().unwrap() = Some(output);
+    Ok(())
+}
+
+/// Write a comment to the script started by `start_script`, if any.
+pub fn script_comment(comment: &str) {
+    if let Some(script) = SCRIPT.lock().unwrap().as_mut() {
+        if let Err(e) = writeln!(script, "\n# {comment}") {
+            debug!("Failed to write to the script: {e}");
+        }
+    }
+}
+
+/// Set the ID of the step running on this thread, used to find its children.
+pub fn set_current_step(step: usize) {
+    CURRENT_STEP.with(|current| current.set(step));
+}
+
+/// Fail if the step running on this thread timed out, so that it stops instead of starting
+/// commands that would be killed right away.
+pub fn check_timed_out() -> io::Result<()> {
+    let step = CURRENT_STEP.with(Cell::get);
+    if CHILDREN.lock().unwrap().timed_out.contains(&step) {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            t!("The step timed out").to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Get `command` ready to be started by the step running on this thread.
+///
+/// On Unix, the command gets a process group of its own, so that `kill_running_children`
+/// also kills the processes it starts. Steps running on the main thread give it the terminal
+/// while it runs, like a shell does, so that it can still read from it. Returns whether it
+/// gets the terminal, which `TrackedChild::new` needs to know.
+pub fn prepare_child(command: &mut Command) -> io::Result<bool> {
+    prepare_background_child(command)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // Steps running on other threads run alongside each other, only one could have it.
+        let foreground = CURRENT_STEP.with(Cell::get) == 0 && terminal::owns_terminal();
+        if foreground {
+            // SAFETY: this only makes system calls, which is fine between fork and exec.
+            unsafe {
+                command.pre_exec(|| {
+                    terminal::give_terminal(nix::unistd::getpid());
+                    Ok(())
+                });
+            }
+        }
+        Ok(foreground)
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = command;
+        Ok(false)
+    }
+}
+
+/// Like `prepare_child`, for commands that never get the terminal.
+pub fn prepare_background_child(command: &mut Command) -> io::Result<()> {
+    check_timed_out()?;
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    Ok(())
+}
+
+/// Record that a command the user may have interrupted with Ctrl+C exited with `status`.
+///
+/// A command given the terminal gets Ctrl+C instead of Topgrade, so Topgrade only finds out
+/// from how it exited.
+pub fn check_interrupted(foreground: bool, status: ExitStatus) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if foreground && (status.signal() == Some(nix::libc::SIGINT) || status.code() == Some(130)) {
+            crate::ctrlc::set_interrupted();
+        }
+    }
+
+    #[cfg(windows)]
+    let _ = (foreground, status);
+}
+
+/// Registers a running child process for as long as this guard is alive, so that it can be
+/// killed by `kill_running_children`.
+pub struct TrackedChild {
+    pid: u32,
+    /// Whether the child was given the terminal, which is taken back once it exits.
+    foreground: bool,
+}
+
+impl TrackedChild {
+    /// Track the child `pid`, started from a command prepared by `prepare_child`.
+    pub fn new(pid: u32, foreground: bool) -> Self {
+        #[cfg(unix)]
+        if foreground {
+            // The child takes the terminal itself too, whichever of us gets there first.
+            terminal::give_terminal(nix::unistd::Pid::from_raw(pid as i32));
+        }
+
+        let step = CURRENT_STEP.with(Cell::get);
+        let mut children = CHILDREN.lock().unwrap();
+        if children.timed_out.contains(&step) {
+            kill_child(pid);
+        }
+        children.running.push((step, pid));
+        #[cfg(unix)]
+        if !foreground {
+            terminal::forward_interrupts_to(pid);
+        }
+
+        Self { pid, foreground }
+    }
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        CHILDREN.lock().unwrap().running.retain(|(_, pid)| *pid != self.pid);
+        #[cfg(unix)]
+        {
+            terminal::stop_forwarding_interrupts_to(self.pid);
+            if self.foreground {
+                terminal::give_terminal(nix::unistd::getpgrp());
+            }
+        }
+    }
+}
+
+/// Handing the terminal over to the commands Topgrade runs, and passing Ctrl+C on to the ones
+/// it doesn't hand it to.
+#[cfg(unix)]
+mod terminal {
+    use std::io::{self, IsTerminal};
+    use std::os::fd::BorrowedFd;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use nix::sys::signal::{killpg, pthread_sigmask, SigSet, SigmaskHow, Signal};
+    use nix::unistd::{getpgrp, tcgetpgrp, tcsetpgrp, Pid};
+
+    /// How many children in the background Ctrl+C is passed on to at most.
+    const MAX_FORWARDED: usize = 64;
+
+    /// The process groups of the running children that don't have the terminal, which don't
+    /// get Ctrl+C from it. Atomics, since the SIGINT handler reads them.
+    static FORWARDED: [AtomicU32; MAX_FORWARDED] = [const { AtomicU32::new(0) }; MAX_FORWARDED];
+
+    fn stdin() -> BorrowedFd<'static> {
+        // SAFETY: the standard input stays open for as long as Topgrade runs.
+        unsafe { BorrowedFd::borrow_raw(0) }
+    }
+
+    /// Whether Topgrade runs in the foreground of a terminal.
+    pub fn owns_terminal() -> bool {
+        io::stdin().is_terminal() && tcgetpgrp(stdin()).is_ok_and(|group| group == getpgrp())
+    }
+
+    /// Make `group` the foreground process group of the terminal.
+    ///
+    /// Only the foreground process group may do so without being stopped by SIGTTOU, unless
+    /// it blocks the signal. This runs between fork and exec too, so it only makes system calls.
+    pub fn give_terminal(group: Pid) {
+        let mut blocked = SigSet::empty();
+        blocked.add(Signal::SIGTTOU);
+        let mut previous = SigSet::empty();
+        if pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&blocked), Some(&mut previous)).is_ok() {
+            tcsetpgrp(stdin(), group).ok();
+            pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&previous), None).ok();
+        }
+    }
+
+    /// Pass Ctrl+C on to the process group `group`, see `forward_interrupt`.
+    pub fn forward_interrupts_to(group: u32) {
+        if !FORWARDED.iter().any(|slot| {
+            slot.compare_exchange(0, group, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        }) {
+            tracing::debug!("Too many children running, Ctrl+C won't reach {group}");
+        }
+    }
+
+    pub fn stop_forwarding_interrupts_to(group: u32) {
+        for slot in &FORWARDED {
+            slot.compare_exchange(group, 0, Ordering::SeqCst, Ordering::SeqCst).ok();
+        }
+    }
+
+    /// Send SIGINT to the children that don't have the terminal. Called from the SIGINT handler.
+    pub fn forward_interrupt() {
+        for slot in &FORWARDED {
+            let group = slot.load(Ordering::SeqCst);
+            if group != 0 {
+                killpg(Pid::from_raw(group as i32), Signal::SIGINT).ok();
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use terminal::forward_interrupt;
+
+/// Kill every running child process of `step` and keep killing new ones until `reset_timed_out`
+/// is called.
+pub fn kill_running_children(step: usize) {
+    let mut children = CHILDREN.lock().unwrap();
+    children.timed_out.push(step);
+    for (_, pid) in children.running.iter().filter(|(s, _)| *s == step) {
+        kill_child(*pid);
+    }
+}
+
+/// Clears the flag set by `kill_running_children`.
+pub fn reset_timed_out(step: usize) {
+    CHILDREN.lock().unwrap().timed_out.retain(|s| *s != step);
+}
+
+/// How long the processes of a timed out step get to exit after SIGTERM, before SIGKILL.
+#[cfg(unix)]
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Kill the process group of the child `pid`, see `prepare_child`.
+///
+/// SIGTERM goes first: `sudo` can't be killed by us, but passes it on to the command it runs.
+/// Whatever is left gets SIGKILL after `KILL_GRACE_PERIOD`.
+#[cfg(unix)]
+fn kill_child(pid: u32) {
+    use nix::sys::signal::{killpg, Signal};
+    use nix::unistd::Pid;
+
+    debug!("Killing the process group of child process {pid}");
+    let group = Pid::from_raw(pid as i32);
+    if let Err(e) = killpg(group, Signal::SIGTERM) {
+        debug!("Failed to kill the process group of child process {pid}: {e}");
+        return;
+    }
+    thread::spawn(move || {
+        thread::sleep(KILL_GRACE_PERIOD);
+        // Fails with ESRCH when every process exited already.
+        killpg(group, Signal::SIGKILL).ok();
+    });
+}
+
+#[cfg(windows)]
+fn kill_child(pid: u32) {
+    debug!("Killing child process {pid}");
+    // `/T` takes down the whole process tree started by the child.
+    // `output_checked` would register `taskkill` itself as a running child while we are
+    // holding the lock, so we fall back to `Command::output` here.
+    #[allow(clippy::disallowed_methods)]
+    if let Err(e) = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output()
+    {
+        debug!("Failed to kill child process {pid}: {e}");
+    }
+}
 
 /// An enum telling whether Topgrade should perform dry runs or actually perform the steps.
 #[derive(Clone, Copy, Debug)]
@@ -18,12 +440,19 @@ pub enum RunType {
 
     /// Executing commands will perform actual execution.
     Wet,
+
+    /// Like `Dry`, but steps that support it also query and print the packages they would
+    /// upgrade.
+    Explain,
 }
 
 impl RunType {
-    /// Create a new instance from a boolean telling whether to dry run.
-    pub fn new(dry_run: bool) -> Self {
-        if dry_run {
+    /// Create a new instance from booleans telling whether to dry run, and whether to explain
+    /// what would be upgraded while doing so.
+    pub fn new(dry_run: bool, explain: bool) -> Self {
+        if explain {
+            RunType::Explain
+        } else if dry_run {
             RunType::Dry
         } else {
             RunType::Wet
@@ -33,21 +462,36 @@ impl RunType {
     /// Create an instance of `Executor` that should run `program`.
     pub fn execute<S: AsRef<OsStr>>(self, program: S) -> Executor {
         match self {
-            RunType::Dry => Executor::Dry(DryCommand {
+            RunType::Dry | RunType::Explain => Executor::Dry(DryCommand {
                 program: program.as_ref().into(),
+                env: STEP_ENV.with(|env| {
+                    env.borrow()
+                        .iter()
+                        .map(|(key, value)| (key.into(), Some(value.into())))
+                        .collect()
+                }),
                 ..Default::default()
             }),
-            RunType::Wet => Executor::Wet(Command::new(program)),
+            RunType::Wet => {
+                let mut command = Command::new(program);
+                apply_step_env(&mut command);
+                Executor::Wet(command)
+            }
         }
     }
 
     /// Tells whether we're performing a dry run.
     pub fn dry(self) -> bool {
         match self {
-            RunType::Dry => true,
+            RunType::Dry | RunType::Explain => true,
             RunType::Wet => false,
         }
     }
+
+    /// Tells whether steps should list the packages they would upgrade, see `--explain`.
+    pub fn explain(self) -> bool {
+        matches!(self, RunType::Explain)
+    }
 }
 
 /// An enum providing a similar interface to `std::process::Command`.
@@ -124,7 +568,7 @@ impl Executor {
             Executor::Wet(c) => {
                 c.env_remove(key);
             }
-            Executor::Dry(_) => (),
+            Executor::Dry(c) => c.env.push((key.as_ref().into(), None)),
         }
 
         self
@@ -141,7 +585,7 @@ impl Executor {
             Executor::Wet(c) => {
                 c.env(key, val);
             }
-            Executor::Dry(_) => (),
+            Executor::Dry(c) => c.env.push((key.as_ref().into(), Some(val.as_ref().into()))),
         }
 
         self
@@ -184,7 +628,6 @@ impl Executor {
 
     /// An extension of `status_checked` that allows you to set a sequence of codes
     /// that can indicate success of a script
-    #[allow(dead_code)]
     pub fn status_checked_with_codes(&mut self, codes: &[i32]) -> Result<()> {
         match self {
             Executor::Wet(c) => c.status_checked_with(|status| {
@@ -195,7 +638,7 @@ impl Executor {
                 }
             }),
             Executor::Dry(c) => {
-                c.dry_run();
+                c.dry_run_accepting(codes);
                 Ok(())
             }
         }
@@ -213,10 +656,25 @@ pub struct DryCommand {
     program: OsString,
     args: Vec<OsString>,
     directory: Option<OsString>,
+    /// Environment variables to set, or to remove if `None`, in order.
+    env: Vec<(OsString, Option<OsString>)>,
 }
 
 impl DryCommand {
     fn dry_run(&self) {
+        self.dry_run_accepting(&[]);
+    }
+
+    /// Dry run a command that also succeeds with the exit codes `codes`.
+    fn dry_run_accepting(&self, codes: &[i32]) {
+        plan::record_command(self.to_planned(codes));
+        if let Some(script) = SCRIPT.lock().unwrap().as_mut() {
+            if let Err(e) = writeln!(script, "{}", self.to_shell()) {
+                debug!("Failed to write to the script: {e}");
+            }
+            return;
+        }
+
         print!(
             "{}",
             t!(
@@ -237,6 +695,52 @@ impl DryCommand {
     }
 }
 
+impl DryCommand {
+    /// The command as `--plan-out` saves it.
+    fn to_planned(&self, accepted_codes: &[i32]) -> PlannedCommand {
+        let lossy = |s: &OsString| s.to_string_lossy().into_owned();
+        PlannedCommand {
+            program: lossy(&self.program),
+            args: self.args.iter().map(lossy).collect(),
+            directory: self.directory.as_ref().map(lossy),
+            env: self
+                .env
+                .iter()
+                .map(|(key, value)| (lossy(key), value.as_ref().map(lossy)))
+                .collect(),
+            accepted_codes: accepted_codes.to_vec(),
+        }
+    }
+
+    /// Format the command as a line of shell script, running in a subshell if it needs a
+    /// different directory or environment.
+    fn to_shell(&self) -> String {
+        let quote = |s: &OsString| shell_words::quote(&s.to_string_lossy()).into_owned();
+
+        let command = iter::once(&self.program)
+            .chain(&self.args)
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let setup = self
+            .directory
+            .iter()
+            .map(|dir| format!("cd {}", quote(dir)))
+            .chain(self.env.iter().map(|(key, value)| match value {
+                Some(value) => format!("export {}={}", key.to_string_lossy(), quote(value)),
+                None => format!("unset {}", key.to_string_lossy()),
+            }))
+            .collect::<Vec<_>>();
+
+        if setup.is_empty() {
+            command
+        } else {
+            format!("({} && {command})", setup.join(" && "))
+        }
+    }
+}
+
 /// The Result of spawn. Contains an actual `std::process::Child` if executed by a wet command.
 pub enum ExecutorChild {
     #[allow(unused)] // this type has not been used
@@ -274,3 +778,41 @@ impl CommandExt for Executor {
         self.spawn()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(args: &[&str]) -> DryCommand {
+        DryCommand {
+            program: OsString::from("echo"),
+            args: args.iter().map(OsString::from).collect(),
+            ..DryCommand::default()
+        }
+    }
+
+    #[test]
+    fn test_to_shell_quotes_arguments() {
+        assert_eq!(command(&["hello"]).to_shell(), "echo hello");
+        assert_eq!(
+            command(&["hello world", "it's", "\"quoted\""]).to_shell(),
+            r#"echo 'hello world' 'it'\''s' '"quoted"'"#
+        );
+    }
+
+    #[test]
+    fn test_to_shell_directory_and_env() {
+        let mut dry = command(&["hi"]);
+        dry.directory = Some(OsString::from("/tmp/some dir"));
+        assert_eq!(dry.to_shell(), "(cd '/tmp/some dir' && echo hi)");
+
+        dry.env = vec![
+            (OsString::from("FOO"), Some(OsString::from("a b"))),
+            (OsString::from("BAR"), None),
+        ];
+        assert_eq!(
+            dry.to_shell(),
+            "(cd '/tmp/some dir' && export FOO='a b' && unset BAR && echo hi)"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Listing the packages steps would upgrade, see `--explain`.
+
+use std::sync::Mutex;
+
+use rust_i18n::t;
+
+use crate::terminal::{print_info, print_separator};
+
+/// How many packages each step that listed them would upgrade, in the order they ran.
+static UPGRADABLE: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+
+/// Print the packages `name` would upgrade, and remember how many there are for the summary.
+pub fn print_upgradable<S: AsRef<str>>(name: &str, packages: &[S]) {
+    print_info(t!("{count} upgradable packages", count = packages.len()));
+    for package in packages {
+        println!("  {}", package.as_ref());
+    }
+
+    UPGRADABLE.lock().unwrap().push((name.to_owned(), packages.len()));
+}
+
+/// Print how many packages each step would upgrade, if any step listed them.
+pub fn print_summary() {
+    let upgradable = UPGRADABLE.lock().unwrap();
+    if upgradable.is_empty() {
+        return;
+    }
+
+    print_separator(t!("Upgradable packages"));
+    for (name, count) in upgradable.iter() {
+        println!("{name}: {count}");
+    }
+    println!(
+        "{}",
+        t!(
+            "Total: {count}",
+            count = upgradable.iter().map(|(_, count)| count).sum::<usize>()
+        )
+    );
+}
@@ -0,0 +1,40 @@
+//! Remembering when Topgrade last ran successfully, see `--if-older-than`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::{Context, Result};
+use tracing::debug;
+
+use crate::breaking_changes::data_dir;
+
+/// The file holding the time of the last successful run, in seconds since the Unix epoch.
+fn timestamp_file() -> PathBuf {
+    data_dir().join("topgrade_last_run")
+}
+
+/// Tell whether the last successful run finished less than `duration` ago.
+pub fn ran_within(duration: Duration) -> bool {
+    let Some(last_run) = fs::read_to_string(timestamp_file())
+        .ok()
+        .and_then(|contents| contents.trim().parse::<u64>().ok())
+    else {
+        return false;
+    };
+
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH + Duration::from_secs(last_run))
+        .unwrap_or_default();
+    debug!("Last successful run was {} seconds ago", elapsed.as_secs());
+
+    elapsed < duration
+}
+
+/// Record that a run just finished successfully.
+pub fn record() -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = timestamp_file();
+    fs::create_dir_all(data_dir())?;
+    fs::write(&path, now.to_string()).with_context(|| format!("Failed to write {}", path.display()))
+}
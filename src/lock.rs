@@ -0,0 +1,128 @@
+//! Keeping two runs of Topgrade from upgrading at the same time, see `--wait` and `--no-lock`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use color_eyre::eyre::{Context, Result};
+use rust_i18n::t;
+
+use crate::breaking_changes::data_dir;
+use crate::ctrlc;
+use crate::error::AlreadyRunning;
+use crate::terminal::print_info;
+
+/// How often to check whether the other run finished, with `--wait`.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The lock held for the whole run. The operating system releases it when the process exits,
+/// however it exits, so a run that crashed never leaves it behind.
+pub struct RunLock {
+    #[cfg(unix)]
+    _file: nix::fcntl::Flock<File>,
+    #[cfg(windows)]
+    _file: File,
+}
+
+/// The lock file, in the runtime directory if there is one, since it is emptied on logout.
+fn lock_file() -> PathBuf {
+    #[cfg(unix)]
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(runtime_dir).join("topgrade.lock");
+    }
+
+    data_dir().join("topgrade.lock")
+}
+
+/// Take the lock, or fail if another run holds it. With `wait`, wait for the other run to
+/// finish instead.
+pub fn acquire(wait: bool) -> Result<RunLock> {
+    let path = lock_file();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut waiting = false;
+    loop {
+        if let Some(lock) = try_lock(&path).with_context(|| format!("Failed to lock {}", path.display()))? {
+            return Ok(lock);
+        }
+
+        // The lock file holds the PID of the run that holds it.
+        let pid = fs::read_to_string(&path)
+            .ok()
+            .map(|contents| contents.trim().to_owned())
+            .filter(|pid| !pid.is_empty())
+            .unwrap_or_else(|| "?".to_owned());
+        if !wait {
+            return Err(AlreadyRunning(pid).into());
+        }
+        if !waiting {
+            print_info(t!(
+                "Waiting for the Topgrade already running (PID {pid}) to finish",
+                pid = pid
+            ));
+            waiting = true;
+        }
+        if ctrlc::interrupted() {
+            return Err(io::Error::from(io::ErrorKind::Interrupted).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Take the lock if no other run holds it.
+#[cfg(unix)]
+fn try_lock(path: &Path) -> io::Result<Option<RunLock>> {
+    use nix::errno::Errno;
+    use nix::fcntl::{Flock, FlockArg};
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+        Ok(file) => {
+            write_pid(&file)?;
+            Ok(Some(RunLock { _file: file }))
+        }
+        Err((_, Errno::EWOULDBLOCK)) => Ok(None),
+        Err((_, errno)) => Err(errno.into()),
+    }
+}
+
+/// Take the lock if no other run holds it. Other processes can still read the file while it is
+/// open, but not open it for writing.
+#[cfg(windows)]
+fn try_lock(path: &Path) -> io::Result<Option<RunLock>> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .share_mode(FILE_SHARE_READ)
+        .open(path)
+    {
+        Ok(file) => {
+            write_pid(&file)?;
+            Ok(Some(RunLock { _file: file }))
+        }
+        Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_pid(mut file: &File) -> io::Result<()> {
+    file.set_len(0)?;
+    write!(file, "{}", process::id())
+}
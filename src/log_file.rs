@@ -0,0 +1,212 @@
+//! Keeping a log file of every run.
+//!
+//! Everything written to stdout and stderr, by Topgrade as well as by the commands it runs,
+//! is copied to the log file. When they point at a terminal, they are replaced by a pseudo
+//! terminal, so that commands still print colors and progress bars.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::Local;
+use color_eyre::eyre::{Context, Result};
+use nix::pty::{openpty, OpenptyResult, Winsize};
+use nix::sys::termios::{tcgetattr, tcsetattr, OutputFlags, SetArg};
+use nix::unistd::{dup, dup2, pipe};
+use tracing::debug;
+
+/// Placeholder in `log_file` that gets replaced by the time the run started.
+const DATE_PLACEHOLDER: &str = "{date}";
+
+/// Expand `~` and `{date}` in the configured log file path.
+fn log_file_path(template: &str) -> PathBuf {
+    let date = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    PathBuf::from(shellexpand::tilde(&template.replace(DATE_PLACEHOLDER, &date)).into_owned())
+}
+
+/// Remove the oldest log files created from `template`, so that at most `max` of them are kept.
+///
+/// Only templates containing `{date}` produce more than one file, so there is nothing to prune
+/// for the others.
+fn prune(template: &str, max: usize) -> Result<()> {
+    let template = PathBuf::from(shellexpand::tilde(template).into_owned());
+    let (Some(directory), Some(file_name)) = (template.parent(), template.file_name().and_then(|f| f.to_str())) else {
+        return Ok(());
+    };
+    let Some((prefix, suffix)) = file_name.split_once(DATE_PLACEHOLDER) else {
+        return Ok(());
+    };
+
+    let mut logs: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name().and_then(|f| f.to_str()).is_some_and(|name| {
+                name.len() > prefix.len() + suffix.len() && name.starts_with(prefix) && name.ends_with(suffix)
+            })
+        })
+        .collect();
+    // The dates sort chronologically.
+    logs.sort();
+
+    for log in logs.iter().take(logs.len().saturating_sub(max)) {
+        debug!("Removing old log file {}", log.display());
+        fs::remove_file(log).with_context(|| format!("Failed to remove {}", log.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Open the log file for this run, creating its directory if needed.
+fn open(path: &Path) -> Result<File> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).with_context(|| format!("Failed to create {}", directory.display()))?;
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Open a pseudo terminal the size of the real one, which passes output through unchanged.
+fn open_pty() -> Result<OpenptyResult> {
+    let (rows, columns) = console::Term::stdout()
+        .size_checked()
+        .or_else(|| console::Term::stderr().size_checked())
+        .unwrap_or((24, 80));
+    let size = Winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let pty = openpty(Some(&size), None)?;
+
+    // The real terminal already turns `\n` into `\r\n`.
+    let mut termios = tcgetattr(&pty.slave)?;
+    termios.output_flags.remove(OutputFlags::OPOST);
+    tcsetattr(&pty.slave, SetArg::TCSANOW, &termios)?;
+
+    Ok(pty)
+}
+
+/// Copies stdout and stderr to the log file for as long as it is alive.
+pub struct LogFile {
+    /// Duplicates of the original stdout and stderr, restored on drop.
+    originals: Vec<(OwnedFd, RawFd)>,
+    copiers: Vec<JoinHandle<()>>,
+}
+
+impl LogFile {
+    /// Start copying stdout and stderr to the log file described by `template`, then remove
+    /// the oldest log files if there are more than `max`.
+    pub fn start(template: &str, max: Option<usize>) -> Result<Self> {
+        let path = log_file_path(template);
+        debug!("Logging to {}", path.display());
+        let log = Arc::new(Mutex::new(open(&path)?));
+
+        if let Some(max) = max {
+            prune(template, max).context("Failed to remove old log files")?;
+        }
+
+        // stdout and stderr no longer point at the terminal from now on, make sure we have
+        // looked at it before that.
+        crate::terminal::probe_terminal();
+        io::stdout().flush()?;
+        io::stderr().flush()?;
+
+        let mut originals = Vec::new();
+        let mut copiers = Vec::new();
+        // Shared by stdout and stderr, which both went to the terminal.
+        let mut pty = None;
+        for fd in [io::stdout().as_raw_fd(), io::stderr().as_raw_fd()] {
+            // SAFETY: `dup` returns a new file descriptor that nothing else owns.
+            let original = unsafe { OwnedFd::from_raw_fd(dup(fd)?) };
+            // SAFETY: stdout and stderr stay open for the whole run.
+            let is_terminal = unsafe { BorrowedFd::borrow_raw(fd) }.is_terminal();
+
+            if is_terminal {
+                if pty.is_none() {
+                    let OpenptyResult { master, slave } = open_pty()?;
+                    copiers.push(spawn_copier(master, &original, &log)?);
+                    pty = Some(slave);
+                }
+                if let Some(slave) = &pty {
+                    dup2(slave.as_raw_fd(), fd)?;
+                }
+            } else {
+                let (reader, writer) = pipe()?;
+                dup2(writer.as_raw_fd(), fd)?;
+                copiers.push(spawn_copier(reader, &original, &log)?);
+            }
+            originals.push((original, fd));
+        }
+
+        Ok(Self { originals, copiers })
+    }
+}
+
+/// Copy everything read from `reader` to `original` and `log` in another thread, see `copy`.
+fn spawn_copier(reader: OwnedFd, original: &OwnedFd, log: &Arc<Mutex<File>>) -> io::Result<JoinHandle<()>> {
+    let mut terminal = File::from(original.try_clone()?);
+    let log = Arc::clone(log);
+    Ok(thread::spawn(move || {
+        copy(File::from(reader), &mut terminal, &log);
+    }))
+}
+
+/// Copy everything read from `reader` to `terminal` as it comes, and to `log` line by line
+/// without the terminal escape codes.
+fn copy(mut reader: File, terminal: &mut File, log: &Mutex<File>) {
+    let mut buffer = [0; 8192];
+    let mut line = Vec::new();
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        terminal.write_all(&buffer[..read]).ok();
+
+        line.extend_from_slice(&buffer[..read]);
+        if let Some(end) = line.iter().rposition(|b| *b == b'\n') {
+            let lines: Vec<u8> = line.drain(..=end).collect();
+            let lines = console::strip_ansi_codes(&String::from_utf8_lossy(&lines)).into_owned();
+            log.lock().unwrap().write_all(lines.as_bytes()).ok();
+        }
+    }
+
+    if !line.is_empty() {
+        let line = console::strip_ansi_codes(&String::from_utf8_lossy(&line)).into_owned();
+        log.lock().unwrap().write_all(line.as_bytes()).ok();
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        io::stdout().flush().ok();
+        io::stderr().flush().ok();
+        for (original, fd) in &self.originals {
+            dup2(original.as_raw_fd(), *fd).ok();
+        }
+
+        // The copiers stop once every write end of their pipe or pseudo terminal is closed. A
+        // command that is still running in the background could keep one open, so don't wait
+        // forever.
+        let (done, finished) = mpsc::channel();
+        let copiers = mem::take(&mut self.copiers);
+        thread::spawn(move || {
+            for copier in copiers {
+                copier.join().ok();
+            }
+            done.send(()).ok();
+        });
+        finished.recv_timeout(Duration::from_secs(1)).ok();
+    }
+}
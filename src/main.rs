@@ -1,10 +1,11 @@
 #![allow(clippy::cognitive_complexity)]
 
 use std::env;
-use std::io;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::path::PathBuf;
 use std::process::exit;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::breaking_changes::{first_run_of_major_release, print_breaking_changes, should_skip, write_keep_file};
 use clap::CommandFactory;
@@ -19,12 +20,14 @@ use etcetera::base_strategy::Windows;
 use etcetera::base_strategy::Xdg;
 use once_cell::sync::Lazy;
 use rust_i18n::{i18n, t};
+use strum::IntoEnumIterator;
 use tracing::debug;
 
-use self::config::{CommandLineArgs, Config, Step};
-use self::error::StepFailed;
+use self::config::{CommandLineArgs, Config, OutputFormat, RebootMode, Step};
 #[cfg(all(windows, feature = "self-update"))]
 use self::error::Upgraded;
+use self::error::{is_skipped, ConfigError, ExitCode, StepFailed};
+use self::report::StepResult;
 #[allow(clippy::wildcard_imports)]
 use self::steps::{remote::*, *};
 #[allow(clippy::wildcard_imports)]
@@ -32,6 +35,7 @@ use self::terminal::*;
 
 use self::utils::{hostname, install_color_eyre, install_tracing, update_tracing};
 
+mod audit;
 mod breaking_changes;
 mod command;
 mod config;
@@ -39,7 +43,16 @@ mod ctrlc;
 mod error;
 mod execution_context;
 mod executor;
+mod explain;
+mod last_run;
+mod lock;
+#[cfg(unix)]
+mod log_file;
+mod manifests;
+mod notifications;
+mod plan;
 mod report;
+mod resume;
 mod runner;
 #[cfg(windows)]
 mod self_renamer;
@@ -48,6 +61,7 @@ mod self_update;
 mod steps;
 mod sudo;
 mod terminal;
+mod trace;
 mod utils;
 
 pub(crate) static HOME_DIR: Lazy<PathBuf> = Lazy::new(|| home::home_dir().expect("No home directory"));
@@ -62,6 +76,7 @@ i18n!("locales", fallback = "en");
 
 #[allow(clippy::too_many_lines)]
 fn run() -> Result<()> {
+    let started = Instant::now();
     install_color_eyre()?;
     ctrlc::set_handler();
 
@@ -113,11 +128,57 @@ fn run() -> Result<()> {
         return Ok(());
     }
 
-    let config = Config::load(opt)?;
+    let mut config = Config::load(opt).wrap_err(ConfigError)?;
+    if config.dump_config() {
+        print!("{}", config.dump()?);
+        return Ok(());
+    }
+    if config.if_older_than().is_some_and(last_run::ran_within) {
+        debug!("The last successful run is too recent, exiting");
+        return Ok(());
+    }
+    // Checked before stdout gets redirected below.
+    set_interactive(!config.no_interactive() && io::stdout().is_terminal());
+    set_assume_yes(config.yes_to_prompts());
+    // Keep stdout for the JSON report, everything else goes to stderr.
+    let json_stdout = match config.output_format() {
+        OutputFormat::Json => Some(take_stdout().context("Failed to redirect stdout to stderr")?),
+        OutputFormat::Human => None,
+    };
+    executor::set_silence_warning(config.step_silence_warning());
+    if config.plan_out().is_some() {
+        plan::start_recording();
+    }
+    if config.trace_out().is_some() {
+        trace::start_recording();
+    }
+    if config.dry_run_script() {
+        executor::start_script(take_stdout().context("Failed to redirect stdout to stderr")?)
+            .context("Failed to write the script")?;
+    }
+    #[cfg(windows)]
+    if config.log_file().is_some() {
+        print_warning(t!("log_file is not supported on Windows, ignoring it"));
+    }
+    #[cfg(unix)]
+    let _log_file = config.log_file().and_then(|template| {
+        log_file::LogFile::start(template, config.log_file_max())
+            .inspect_err(|e| print_warning(t!("Failed to set up the log file: {error}", error = format!("{e:?}"))))
+            .ok()
+    });
     // Update the logger with the full filter directives.
     update_tracing(&reload_handle, &config.tracing_filter_directives())?;
     set_title(config.set_title());
     display_time(config.display_time());
+    set_separator(
+        config.separator_char(),
+        config.separator_width(),
+        config.separator_color(),
+    );
+    if config.no_color() {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
     set_desktop_notifications(config.notify_each_step());
 
     debug!("Version: {}", crate_version!());
@@ -127,7 +188,7 @@ fn run() -> Result<()> {
     debug!("self-update Feature Enabled: {:?}", cfg!(feature = "self-update"));
     debug!("Configuration: {:?}", config);
 
-    if config.run_in_tmux() && env::var("TOPGRADE_INSIDE_TMUX").is_err() {
+    if config.run_in_tmux() && env::var("TOPGRADE_INSIDE_TMUX").is_err() && !config.list_steps() {
         #[cfg(unix)]
         {
             tmux::run_in_tmux(config.tmux_config()?)?;
@@ -135,6 +196,21 @@ fn run() -> Result<()> {
         }
     }
 
+    // Dry runs change nothing, so they don't need to keep other runs out.
+    let _lock = if config.no_lock() || config.dry_run() || config.list_steps() {
+        None
+    } else {
+        Some(lock::acquire(config.wait())?)
+    };
+
+    if config.interactive_select() {
+        let steps: Vec<Step> = Step::iter().filter(|step| config.should_run(*step)).collect();
+        match select_steps(&steps).context("Failed to show the step selection")? {
+            Some(steps) => config.select_steps(steps),
+            None => exit(130),
+        }
+    }
+
     let powershell = powershell::Powershell::new();
     let should_run_powershell = powershell.profile().is_some() && config.should_run(Step::Powershell);
     let emacs = emacs::Emacs::new();
@@ -142,32 +218,68 @@ fn run() -> Result<()> {
     let distribution = linux::Distribution::detect();
 
     let sudo = config.sudo_command().map_or_else(sudo::Sudo::detect, sudo::Sudo::new);
-    let run_type = executor::RunType::new(config.dry_run());
+    let run_type = executor::RunType::new(config.dry_run(), config.explain());
+    // Declared before `ctx`, since the steps borrowing it can live as long.
+    #[cfg(unix)]
+    let ssh_control_master = if config.ssh_control_master() && config.remote_topgrades().is_some() && !run_type.dry() {
+        Some(ssh::ControlMaster::new().context("Failed to create a directory for ssh control sockets")?)
+    } else {
+        None
+    };
+
+    // With `--plan-in`, the steps of the plan replace the ones added below, so the ones that
+    // run commands just to be added are left out. Declared before `ctx` as well.
+    let plan = config.plan_in().map(plan::Plan::load).transpose()?;
+
     let ctx = execution_context::ExecutionContext::new(run_type, sudo, &config);
+    if config.offline() {
+        print_info(t!(
+            "Offline mode: skipping self-update, remote topgrades, git pulls, containers and Vagrant boxes"
+        ));
+    }
     let mut runner = runner::Runner::new(&ctx);
 
+    let config_hash = config.file_hash()?;
+    if config.force() && !run_type.dry() {
+        resume::clear()?;
+    }
+    let resume_state = if config.resume() && !config.force() {
+        resume::ResumeState::load(config_hash)
+    } else {
+        resume::ResumeState::default()
+    };
+    runner.skip_succeeded(resume_state.succeeded());
+
     // If
     //
     // 1. the breaking changes notification shouldnot be skipped
-    // 2. this is the first execution of a major release
+    // 2. this is not a targeted run with `--only`
+    // 3. this is the first execution of a major release
     //
     // inform user of breaking changes
-    if !should_skip() && first_run_of_major_release()? {
+    if !should_skip() && !config.only_given() && !config.list_steps() && first_run_of_major_release()? {
         print_breaking_changes();
 
-        if prompt_yesno("Confirmed?")? {
-            write_keep_file()?;
-        } else {
-            exit(1);
+        // Without anyone to confirm them, go on and show them again next time.
+        if is_interactive() || assumes_yes() {
+            if prompt_yesno("Confirmed?")? {
+                write_keep_file()?;
+            } else {
+                exit(1);
+            }
         }
     }
 
     // Self-Update step, this will execute only if:
     // 1. the `self-update` feature is enabled
     // 2. it is not disabled from configuration (env var/CLI opt/file)
+    // 3. `--only` was not given, or explicitly includes `self_update`
     #[cfg(feature = "self-update")]
     {
-        let should_self_update = env::var("TOPGRADE_NO_SELF_UPGRADE").is_err() && !config.no_self_update();
+        let should_self_update = env::var("TOPGRADE_NO_SELF_UPGRADE").is_err()
+            && !config.no_self_update()
+            && !config.list_steps()
+            && config.plan_in().is_none();
 
         if should_self_update {
             runner.execute(Step::SelfUpdate, "Self Update", || self_update::self_update(&ctx))?;
@@ -181,173 +293,229 @@ fn run() -> Result<()> {
         None
     };
 
-    if let Some(commands) = config.pre_commands() {
+    if let Some(commands) = config.pre_commands().as_ref().filter(|_| !config.list_steps()) {
         for (name, command) in commands {
-            generic::run_custom_command(name, command, &ctx)?;
+            match generic::run_custom_command(name, command, &ctx) {
+                Err(e) if is_skipped(&e) => debug!("Skipping {name}: {e}"),
+                result => result?,
+            }
         }
     }
 
-    if config.pre_sudo() {
+    if config.pre_sudo() && !config.list_steps() {
         if let Some(sudo) = ctx.sudo() {
             sudo.elevate(&ctx)?;
         }
     }
 
+    let _sudo_keepalive = if config.sudo_keepalive() && !config.list_steps() && !run_type.dry() {
+        ctx.sudo().as_ref().and_then(sudo::Sudo::keepalive)
+    } else {
+        None
+    };
+
+    let mut steps = runner::Steps::new();
+
     if let Some(topgrades) = config.remote_topgrades() {
-        for remote_topgrade in topgrades.iter().filter(|t| config.should_execute_remote(hostname(), t)) {
-            runner.execute(Step::Remotes, format!("Remote ({remote_topgrade})"), || {
-                ssh::ssh_step(&ctx, remote_topgrade)
-            })?;
+        for remote_topgrade in topgrades
+            .iter()
+            .filter(|t| config.should_execute_remote(hostname(), t.host()))
+        {
+            let ctx = &ctx;
+            #[cfg(unix)]
+            let ssh_control_master = ssh_control_master.as_ref();
+            steps.add(
+                Step::Remotes,
+                format!("Remote ({})", remote_topgrade.host()),
+                move || {
+                    ssh::ssh_step(
+                        ctx,
+                        remote_topgrade,
+                        #[cfg(unix)]
+                        ssh_control_master,
+                    )
+                },
+            );
         }
     }
 
     #[cfg(windows)]
     {
-        runner.execute(Step::Wsl, "WSL", || windows::run_wsl_topgrade(&ctx))?;
-        runner.execute(Step::WslUpdate, "WSL", || windows::update_wsl(&ctx))?;
-        runner.execute(Step::Chocolatey, "Chocolatey", || windows::run_chocolatey(&ctx))?;
-        runner.execute(Step::Scoop, "Scoop", || windows::run_scoop(&ctx))?;
-        runner.execute(Step::Winget, "Winget", || windows::run_winget(&ctx))?;
-        runner.execute(Step::System, "Windows update", || windows::windows_update(&ctx))?;
-        runner.execute(Step::MicrosoftStore, "Microsoft Store", || {
+        steps.add(Step::Wsl, "WSL", || windows::run_wsl_topgrade(&ctx));
+        steps.add(Step::WslUpdate, "WSL", || windows::update_wsl(&ctx));
+        steps.add(Step::Chocolatey, "Chocolatey", || windows::run_chocolatey(&ctx));
+        steps.add(Step::Scoop, "Scoop", || windows::run_scoop(&ctx));
+        steps.add(Step::Winget, "Winget", || windows::run_winget(&ctx));
+        steps.add(Step::System, "Windows update", || windows::windows_update(&ctx));
+        steps.add(Step::MicrosoftStore, "Microsoft Store", || {
             windows::microsoft_store(&ctx)
-        })?;
+        });
     }
 
     #[cfg(target_os = "linux")]
     {
         // NOTE: Due to breaking `nu` updates, `packer.nu` needs to be updated before `nu` get updated
         // by other package managers.
-        runner.execute(Step::Shell, "packer.nu", || linux::run_packer_nu(&ctx))?;
+        steps.add(Step::Shell, "packer.nu", || linux::run_packer_nu(&ctx));
 
         match &distribution {
             Ok(distribution) => {
-                runner.execute(Step::System, "System update", || distribution.upgrade(&ctx))?;
+                steps.add(Step::System, "System update", || distribution.upgrade(&ctx));
             }
             Err(e) => {
                 println!("{}", t!("Error detecting current distribution: {error}", error = e));
             }
         }
-        runner.execute(Step::ConfigUpdate, "config-update", || linux::run_config_update(&ctx))?;
-
-        runner.execute(Step::AM, "am", || linux::run_am(&ctx))?;
-        runner.execute(Step::AppMan, "appman", || linux::run_appman(&ctx))?;
-        runner.execute(Step::DebGet, "deb-get", || linux::run_deb_get(&ctx))?;
-        runner.execute(Step::Toolbx, "toolbx", || toolbx::run_toolbx(&ctx))?;
-        runner.execute(Step::Snap, "snap", || linux::run_snap(&ctx))?;
-        runner.execute(Step::Pacstall, "pacstall", || linux::run_pacstall(&ctx))?;
-        runner.execute(Step::Pacdef, "pacdef", || linux::run_pacdef(&ctx))?;
-        runner.execute(Step::Protonup, "protonup", || linux::run_protonup_update(&ctx))?;
-        runner.execute(Step::Distrobox, "distrobox", || linux::run_distrobox_update(&ctx))?;
-        runner.execute(Step::DkpPacman, "dkp-pacman", || linux::run_dkp_pacman_update(&ctx))?;
-        runner.execute(Step::System, "pihole", || linux::run_pihole_update(&ctx))?;
-        runner.execute(Step::Firmware, "Firmware upgrades", || linux::run_fwupdmgr(&ctx))?;
-        runner.execute(Step::Restarts, "Restarts", || linux::run_needrestart(&ctx))?;
-
-        runner.execute(Step::Flatpak, "Flatpak", || linux::run_flatpak(&ctx))?;
-        runner.execute(Step::BrewFormula, "Brew", || {
+        steps.add(Step::ConfigUpdate, "config-update", || linux::run_config_update(&ctx));
+
+        steps.add(Step::AM, "am", || linux::run_am(&ctx));
+        steps.add(Step::AppMan, "appman", || linux::run_appman(&ctx));
+        steps.add(Step::DebGet, "deb-get", || linux::run_deb_get(&ctx));
+        steps.add(Step::Toolbx, "toolbx", || toolbx::run_toolbx(&ctx));
+        steps.add(Step::Snap, "snap", || linux::run_snap(&ctx));
+        steps.add(Step::Pacstall, "pacstall", || linux::run_pacstall(&ctx));
+        steps.add(Step::Pacdef, "pacdef", || linux::run_pacdef(&ctx));
+        steps.add(Step::Protonup, "protonup", || linux::run_protonup_update(&ctx));
+        steps.add(Step::Distrobox, "distrobox", || linux::run_distrobox_update(&ctx));
+        let run_in_containers = config.should_run(Step::Distrobox) || config.should_run(Step::Toolbx);
+        if let Some(containers) = config
+            .containers_to_topgrade()
+            .filter(|_| run_in_containers && !config.list_steps() && plan.is_none())
+        {
+            for (step, container) in linux::find_topgrade_containers(containers) {
+                let ctx = &ctx;
+                steps.add(step, format!("Container ({container})"), move || {
+                    linux::run_container_topgrade(ctx, step, &container)
+                });
+            }
+        }
+        steps.add(Step::DkpPacman, "dkp-pacman", || linux::run_dkp_pacman_update(&ctx));
+        steps.add(Step::System, "pihole", || linux::run_pihole_update(&ctx));
+        steps.add(Step::Firmware, "Firmware upgrades", || linux::run_fwupdmgr(&ctx));
+        steps.add(Step::Restarts, "Restarts", || linux::run_needrestart(&ctx));
+
+        steps.add(Step::Flatpak, "Flatpak", || linux::run_flatpak(&ctx));
+        steps.add(Step::BrewFormula, "Brew", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::Path)
-        })?;
-        runner.execute(Step::Lure, "LURE", || linux::run_lure_update(&ctx))?;
-        runner.execute(Step::Waydroid, "Waydroid", || linux::run_waydroid(&ctx))?;
-        runner.execute(Step::AutoCpufreq, "auto-cpufreq", || linux::run_auto_cpufreq(&ctx))?;
-        runner.execute(Step::CinnamonSpices, "Cinnamon spices", || {
+        });
+        steps.add(Step::BrewBundle, "Brew Bundle", || {
+            unix::run_brew_bundle(&ctx, unix::BrewVariant::Path)
+        });
+        steps.add(Step::Lure, "LURE", || linux::run_lure_update(&ctx));
+        steps.add(Step::Waydroid, "Waydroid", || linux::run_waydroid(&ctx));
+        steps.add(Step::AutoCpufreq, "auto-cpufreq", || linux::run_auto_cpufreq(&ctx));
+        steps.add(Step::CinnamonSpices, "Cinnamon spices", || {
             linux::run_cinnamon_spices_updater(&ctx)
-        })?;
+        });
     }
 
     #[cfg(target_os = "macos")]
     {
-        runner.execute(Step::BrewFormula, "Brew (ARM)", || {
+        steps.add(Step::BrewFormula, "Brew (ARM)", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::MacArm)
-        })?;
-        runner.execute(Step::BrewFormula, "Brew (Intel)", || {
+        });
+        steps.add(Step::BrewFormula, "Brew (Intel)", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::MacIntel)
-        })?;
-        runner.execute(Step::BrewFormula, "Brew", || {
+        });
+        steps.add(Step::BrewFormula, "Brew", || {
             unix::run_brew_formula(&ctx, unix::BrewVariant::Path)
-        })?;
-        runner.execute(Step::BrewCask, "Brew Cask (ARM)", || {
+        });
+        steps.add(Step::BrewCask, "Brew Cask (ARM)", || {
             unix::run_brew_cask(&ctx, unix::BrewVariant::MacArm)
-        })?;
-        runner.execute(Step::BrewCask, "Brew Cask (Intel)", || {
+        });
+        steps.add(Step::BrewCask, "Brew Cask (Intel)", || {
             unix::run_brew_cask(&ctx, unix::BrewVariant::MacIntel)
-        })?;
-        runner.execute(Step::BrewCask, "Brew Cask", || {
+        });
+        steps.add(Step::BrewCask, "Brew Cask", || {
             unix::run_brew_cask(&ctx, unix::BrewVariant::Path)
-        })?;
-        runner.execute(Step::Macports, "MacPorts", || macos::run_macports(&ctx))?;
-        runner.execute(Step::Xcodes, "Xcodes", || macos::update_xcodes(&ctx))?;
-        runner.execute(Step::Sparkle, "Sparkle", || macos::run_sparkle(&ctx))?;
-        runner.execute(Step::Mas, "App Store", || macos::run_mas(&ctx))?;
-        runner.execute(Step::System, "System upgrade", || macos::upgrade_macos(&ctx))?;
+        });
+        steps.add(Step::BrewBundle, "Brew Bundle (ARM)", || {
+            unix::run_brew_bundle(&ctx, unix::BrewVariant::MacArm)
+        });
+        steps.add(Step::BrewBundle, "Brew Bundle (Intel)", || {
+            unix::run_brew_bundle(&ctx, unix::BrewVariant::MacIntel)
+        });
+        steps.add(Step::BrewBundle, "Brew Bundle", || {
+            unix::run_brew_bundle(&ctx, unix::BrewVariant::Path)
+        });
+        steps.add(Step::Macports, "MacPorts", || macos::run_macports(&ctx));
+        steps.add(Step::Xcodes, "Xcodes", || macos::update_xcodes(&ctx));
+        steps.add(Step::Sparkle, "Sparkle", || macos::run_sparkle(&ctx));
+        steps.add(Step::Mas, "App Store", || macos::run_mas(&ctx));
+        steps.add(Step::System, "System upgrade", || macos::upgrade_macos(&ctx));
     }
 
     #[cfg(target_os = "dragonfly")]
     {
-        runner.execute(Step::Pkg, "DragonFly BSD Packages", || {
+        steps.add(Step::Pkg, "DragonFly BSD Packages", || {
             dragonfly::upgrade_packages(&ctx)
-        })?;
-        runner.execute(Step::Audit, "DragonFly Audit", || dragonfly::audit_packages(&ctx))?;
+        });
+        steps.add(Step::Audit, "DragonFly Audit", || dragonfly::audit_packages(&ctx));
     }
 
     #[cfg(target_os = "freebsd")]
     {
-        runner.execute(Step::Pkg, "FreeBSD Packages", || freebsd::upgrade_packages(&ctx))?;
-        runner.execute(Step::System, "FreeBSD Upgrade", || freebsd::upgrade_freebsd(&ctx))?;
-        runner.execute(Step::Audit, "FreeBSD Audit", || freebsd::audit_packages(&ctx))?;
+        steps.add(Step::Pkg, "FreeBSD Packages", || freebsd::upgrade_packages(&ctx));
+        steps.add(Step::System, "FreeBSD Upgrade", || freebsd::upgrade_freebsd(&ctx));
+        steps.add(Step::Audit, "FreeBSD Audit", || freebsd::audit_packages(&ctx));
     }
 
     #[cfg(target_os = "openbsd")]
     {
-        runner.execute(Step::Pkg, "OpenBSD Packages", || openbsd::upgrade_packages(&ctx))?;
-        runner.execute(Step::System, "OpenBSD Upgrade", || openbsd::upgrade_openbsd(&ctx))?;
+        steps.add(Step::Pkg, "OpenBSD Packages", || openbsd::upgrade_packages(&ctx));
+        steps.add(Step::System, "OpenBSD Upgrade", || openbsd::upgrade_openbsd(&ctx));
     }
 
     #[cfg(target_os = "android")]
     {
-        runner.execute(Step::Pkg, "Termux Packages", || android::upgrade_packages(&ctx))?;
+        steps.add(Step::Pkg, "Termux Packages", || android::upgrade_packages(&ctx));
     }
 
     #[cfg(unix)]
     {
-        runner.execute(Step::Yadm, "yadm", || unix::run_yadm(&ctx))?;
-        runner.execute(Step::Nix, "nix", || unix::run_nix(&ctx))?;
-        runner.execute(Step::Nix, "nix upgrade-nix", || unix::run_nix_self_upgrade(&ctx))?;
-        runner.execute(Step::NixHelper, "nh", || unix::run_nix_helper(&ctx))?;
-        runner.execute(Step::Guix, "guix", || unix::run_guix(&ctx))?;
-        runner.execute(Step::HomeManager, "home-manager", || unix::run_home_manager(&ctx))?;
-        runner.execute(Step::Asdf, "asdf", || unix::run_asdf(&ctx))?;
-        runner.execute(Step::Mise, "mise", || unix::run_mise(&ctx))?;
-        runner.execute(Step::Pkgin, "pkgin", || unix::run_pkgin(&ctx))?;
-        runner.execute(Step::BunPackages, "bun-packages", || unix::run_bun_packages(&ctx))?;
-        runner.execute(Step::Shell, "zr", || zsh::run_zr(&ctx))?;
-        runner.execute(Step::Shell, "antibody", || zsh::run_antibody(&ctx))?;
-        runner.execute(Step::Shell, "antidote", || zsh::run_antidote(&ctx))?;
-        runner.execute(Step::Shell, "antigen", || zsh::run_antigen(&ctx))?;
-        runner.execute(Step::Shell, "zgenom", || zsh::run_zgenom(&ctx))?;
-        runner.execute(Step::Shell, "zplug", || zsh::run_zplug(&ctx))?;
-        runner.execute(Step::Shell, "zinit", || zsh::run_zinit(&ctx))?;
-        runner.execute(Step::Shell, "zi", || zsh::run_zi(&ctx))?;
-        runner.execute(Step::Shell, "zim", || zsh::run_zim(&ctx))?;
-        runner.execute(Step::Shell, "oh-my-zsh", || zsh::run_oh_my_zsh(&ctx))?;
-        runner.execute(Step::Shell, "oh-my-bash", || unix::run_oh_my_bash(&ctx))?;
-        runner.execute(Step::Shell, "fisher", || unix::run_fisher(&ctx))?;
-        runner.execute(Step::Shell, "bash-it", || unix::run_bashit(&ctx))?;
-        runner.execute(Step::Shell, "oh-my-fish", || unix::run_oh_my_fish(&ctx))?;
-        runner.execute(Step::Shell, "fish-plug", || unix::run_fish_plug(&ctx))?;
-        runner.execute(Step::Shell, "fundle", || unix::run_fundle(&ctx))?;
-        runner.execute(Step::Tmux, "tmux", || tmux::run_tpm(&ctx))?;
-        runner.execute(Step::Tldr, "TLDR", || unix::run_tldr(&ctx))?;
-        runner.execute(Step::Pearl, "pearl", || unix::run_pearl(&ctx))?;
+        steps.add(Step::Yadm, "yadm", || unix::run_yadm(&ctx));
+        steps.add(Step::Nix, "nix", || unix::run_nix(&ctx));
+        steps.add(Step::Nix, "nix upgrade-nix", || unix::run_nix_self_upgrade(&ctx));
+        steps.add(Step::NixFlake, "nix flakes", || unix::run_nix_flake(&ctx));
+        steps.add(Step::NixHelper, "nh", || unix::run_nix_helper(&ctx));
+        steps.add(Step::Guix, "guix", || unix::run_guix(&ctx));
+        steps.add(Step::HomeManager, "home-manager", || unix::run_home_manager(&ctx));
+        steps.add(Step::Asdf, "asdf", || unix::run_asdf(&ctx));
+        steps.add(Step::Mise, "mise", || unix::run_mise(&ctx));
+        steps.add(Step::Pkgin, "pkgin", || unix::run_pkgin(&ctx));
+        steps.add(Step::BunPackages, "bun-packages", || unix::run_bun_packages(&ctx));
+        steps.add(Step::Shell, "zr", || zsh::run_zr(&ctx));
+        steps.add(Step::Shell, "antibody", || zsh::run_antibody(&ctx));
+        steps.add(Step::Shell, "antidote", || zsh::run_antidote(&ctx));
+        steps.add(Step::Shell, "antigen", || zsh::run_antigen(&ctx));
+        steps.add(Step::Shell, "zgenom", || zsh::run_zgenom(&ctx));
+        steps.add(Step::Shell, "zplug", || zsh::run_zplug(&ctx));
+        steps.add(Step::Shell, "zinit", || zsh::run_zinit(&ctx));
+        steps.add(Step::Shell, "zi", || zsh::run_zi(&ctx));
+        steps.add(Step::Shell, "zim", || zsh::run_zim(&ctx));
+        steps.add(Step::Shell, "oh-my-zsh", || zsh::run_oh_my_zsh(&ctx));
+        steps.add(Step::Shell, "zsh completions", || zsh::run_compinit(&ctx));
+        steps.add(Step::Shell, "oh-my-bash", || unix::run_oh_my_bash(&ctx));
+        steps.add(Step::Shell, "fisher", || unix::run_fisher(&ctx));
+        steps.add(Step::Shell, "bash-it", || unix::run_bashit(&ctx));
+        steps.add(Step::Shell, "oh-my-fish", || unix::run_oh_my_fish(&ctx));
+        steps.add(Step::Shell, "fish-plug", || unix::run_fish_plug(&ctx));
+        steps.add(Step::Shell, "fundle", || unix::run_fundle(&ctx));
+        steps.add(Step::Tmux, "tmux", || tmux::run_tpm(&ctx));
+        steps.add(Step::Tldr, "TLDR", || unix::run_tldr(&ctx));
+        steps.add(Step::Pearl, "pearl", || unix::run_pearl(&ctx));
         #[cfg(not(any(target_os = "macos", target_os = "android")))]
-        runner.execute(Step::GnomeShellExtensions, "Gnome Shell Extensions", || {
+        steps.add(Step::GnomeShellExtensions, "Gnome Shell Extensions", || {
             unix::upgrade_gnome_extensions(&ctx)
-        })?;
-        runner.execute(Step::Pyenv, "pyenv", || unix::run_pyenv(&ctx))?;
-        runner.execute(Step::Sdkman, "SDKMAN!", || unix::run_sdkman(&ctx))?;
-        runner.execute(Step::Rcm, "rcm", || unix::run_rcm(&ctx))?;
-        runner.execute(Step::Maza, "maza", || unix::run_maza(&ctx))?;
+        });
+        steps.add(Step::Pyenv, "pyenv", || unix::run_version_manager(&ctx, &unix::PYENV));
+        steps.add(Step::Rbenv, "rbenv", || unix::run_version_manager(&ctx, &unix::RBENV));
+        steps.add(Step::Nodenv, "nodenv", || {
+            unix::run_version_manager(&ctx, &unix::NODENV)
+        });
+        steps.add(Step::Sdkman, "SDKMAN!", || unix::run_sdkman(&ctx));
+        steps.add(Step::Rcm, "rcm", || unix::run_rcm(&ctx));
+        steps.add(Step::Maza, "maza", || unix::run_maza(&ctx));
     }
 
     #[cfg(not(any(
@@ -357,186 +525,239 @@ fn run() -> Result<()> {
         target_os = "dragonfly"
     )))]
     {
-        runner.execute(Step::Atom, "apm", || generic::run_apm(&ctx))?;
+        steps.add(Step::Atom, "apm", || generic::run_apm(&ctx));
     }
 
     // The following update function should be executed on all OSes.
-    runner.execute(Step::Fossil, "fossil", || generic::run_fossil(&ctx))?;
-    runner.execute(Step::Elan, "elan", || generic::run_elan(&ctx))?;
-    runner.execute(Step::Rye, "rye", || generic::run_rye(&ctx))?;
-    runner.execute(Step::Rustup, "rustup", || generic::run_rustup(&ctx))?;
-    runner.execute(Step::Juliaup, "juliaup", || generic::run_juliaup(&ctx))?;
-    runner.execute(Step::Dotnet, ".NET", || generic::run_dotnet_upgrade(&ctx))?;
-    runner.execute(Step::Choosenim, "choosenim", || generic::run_choosenim(&ctx))?;
-    runner.execute(Step::Cargo, "cargo", || generic::run_cargo_update(&ctx))?;
-    runner.execute(Step::Flutter, "Flutter", || generic::run_flutter_upgrade(&ctx))?;
-    runner.execute(Step::Go, "go-global-update", || go::run_go_global_update(&ctx))?;
-    runner.execute(Step::Go, "gup", || go::run_go_gup(&ctx))?;
-    runner.execute(Step::Emacs, "Emacs", || emacs.upgrade(&ctx))?;
-    runner.execute(Step::Opam, "opam", || generic::run_opam_update(&ctx))?;
-    runner.execute(Step::Vcpkg, "vcpkg", || generic::run_vcpkg_update(&ctx))?;
-    runner.execute(Step::Pipx, "pipx", || generic::run_pipx_update(&ctx))?;
-    runner.execute(Step::Pipxu, "pipxu", || generic::run_pipxu_update(&ctx))?;
-    runner.execute(Step::Vscode, "Visual Studio Code extensions", || {
+    steps.add(Step::Fossil, "fossil", || generic::run_fossil(&ctx));
+    steps.add_parallel(Step::Elan, "elan", || generic::run_elan(&ctx));
+    steps.add_parallel(Step::Rye, "rye", || generic::run_rye(&ctx));
+    steps.add_parallel(Step::Rustup, "rustup", || generic::run_rustup(&ctx));
+    steps.add_parallel(Step::Juliaup, "juliaup", || generic::run_juliaup(&ctx));
+    steps.add(Step::Dotnet, ".NET", || generic::run_dotnet_upgrade(&ctx));
+    steps.add_parallel(Step::Choosenim, "choosenim", || generic::run_choosenim(&ctx));
+    steps.add_parallel(Step::Cargo, "cargo", || generic::run_cargo_update(&ctx));
+    steps.add(Step::Flutter, "Flutter", || generic::run_flutter_upgrade(&ctx));
+    steps.add_parallel(Step::Go, "Go toolchain", || go::run_go_toolchain_update(&ctx));
+    steps.add_parallel(Step::Go, "go-global-update", || go::run_go_global_update(&ctx));
+    steps.add_parallel(Step::Go, "gup", || go::run_go_gup(&ctx));
+    steps.add(Step::Emacs, "Emacs", || emacs.upgrade(&ctx));
+    steps.add_parallel(Step::Opam, "opam", || generic::run_opam_update(&ctx));
+    steps.add(Step::Vcpkg, "vcpkg", || generic::run_vcpkg_update(&ctx));
+    steps.add_parallel(Step::Pipx, "pipx", || generic::run_pipx_update(&ctx));
+    steps.add_parallel(Step::Pipxu, "pipxu", || generic::run_pipxu_update(&ctx));
+    steps.add(Step::Vscode, "Visual Studio Code extensions", || {
         generic::run_vscode_extensions_update(&ctx)
-    })?;
-    runner.execute(Step::Vscodium, "VSCodium extensions", || {
+    });
+    steps.add(Step::Vscodium, "VSCodium extensions", || {
         generic::run_vscodium_extensions_update(&ctx)
-    })?;
-    runner.execute(Step::Conda, "conda", || generic::run_conda_update(&ctx))?;
-    runner.execute(Step::Mamba, "mamba", || generic::run_mamba_update(&ctx))?;
-    runner.execute(Step::Pixi, "pixi", || generic::run_pixi_update(&ctx))?;
-    runner.execute(Step::Miktex, "miktex", || generic::run_miktex_packages_update(&ctx))?;
-    runner.execute(Step::Pip3, "pip3", || generic::run_pip3_update(&ctx))?;
-    runner.execute(Step::PipReview, "pip-review", || generic::run_pip_review_update(&ctx))?;
-    runner.execute(Step::PipReviewLocal, "pip-review (local)", || {
+    });
+    steps.add(Step::Conda, "conda", || generic::run_conda_update(&ctx));
+    steps.add(Step::Mamba, "mamba", || generic::run_mamba_update(&ctx));
+    steps.add(Step::Pixi, "pixi", || generic::run_pixi_update(&ctx));
+    steps.add(Step::Miktex, "miktex", || generic::run_miktex_packages_update(&ctx));
+    steps.add(Step::Pip3, "pip3", || generic::run_pip3_update(&ctx));
+    steps.add(Step::PipReview, "pip-review", || generic::run_pip_review_update(&ctx));
+    steps.add(Step::PipReviewLocal, "pip-review (local)", || {
         generic::run_pip_review_local_update(&ctx)
-    })?;
-    runner.execute(Step::Pipupgrade, "pipupgrade", || generic::run_pipupgrade_update(&ctx))?;
-    runner.execute(Step::Ghcup, "ghcup", || generic::run_ghcup_update(&ctx))?;
-    runner.execute(Step::Stack, "stack", || generic::run_stack_update(&ctx))?;
-    runner.execute(Step::Tlmgr, "tlmgr", || generic::run_tlmgr_update(&ctx))?;
-    runner.execute(Step::Myrepos, "myrepos", || generic::run_myrepos_update(&ctx))?;
-    runner.execute(Step::Chezmoi, "chezmoi", || generic::run_chezmoi_update(&ctx))?;
-    runner.execute(Step::Jetpack, "jetpack", || generic::run_jetpack(&ctx))?;
-    runner.execute(Step::Vim, "vim", || vim::upgrade_vim(&ctx))?;
-    runner.execute(Step::Vim, "Neovim", || vim::upgrade_neovim(&ctx))?;
-    runner.execute(Step::Vim, "The Ultimate vimrc", || vim::upgrade_ultimate_vimrc(&ctx))?;
-    runner.execute(Step::Vim, "voom", || vim::run_voom(&ctx))?;
-    runner.execute(Step::Kakoune, "Kakoune", || kakoune::upgrade_kak_plug(&ctx))?;
-    runner.execute(Step::Helix, "helix", || generic::run_helix_grammars(&ctx))?;
-    runner.execute(Step::Node, "npm", || node::run_npm_upgrade(&ctx))?;
-    runner.execute(Step::Yarn, "yarn", || node::run_yarn_upgrade(&ctx))?;
-    runner.execute(Step::Pnpm, "pnpm", || node::run_pnpm_upgrade(&ctx))?;
-    runner.execute(Step::VoltaPackages, "volta packages", || {
+    });
+    steps.add(Step::Pipupgrade, "pipupgrade", || generic::run_pipupgrade_update(&ctx));
+    steps.add(Step::PythonVenvs, "Python venvs", || generic::run_python_venvs(&ctx));
+    steps.add_parallel(Step::Ghcup, "ghcup", || generic::run_ghcup_update(&ctx));
+    steps.add_parallel(Step::Stack, "stack", || generic::run_stack_update(&ctx));
+    steps.add(Step::Tlmgr, "tlmgr", || generic::run_tlmgr_update(&ctx));
+    steps.add(Step::Myrepos, "myrepos", || generic::run_myrepos_update(&ctx));
+    steps.add(Step::Chezmoi, "chezmoi", || generic::run_chezmoi_update(&ctx));
+    steps.add(Step::Jetpack, "jetpack", || generic::run_jetpack(&ctx));
+    steps.add(Step::Vim, "vim", || vim::upgrade_vim(&ctx));
+    steps.add(Step::Vim, "Neovim", || vim::upgrade_neovim(&ctx));
+    steps.add(Step::Vim, "The Ultimate vimrc", || vim::upgrade_ultimate_vimrc(&ctx));
+    steps.add(Step::Vim, "voom", || vim::run_voom(&ctx));
+    steps.add(Step::Kakoune, "Kakoune", || kakoune::upgrade_kak_plug(&ctx));
+    steps.add(Step::Helix, "helix", || generic::run_helix_grammars(&ctx));
+    steps.add(Step::Node, "npm", || node::run_npm_upgrade(&ctx));
+    steps.add(Step::Yarn, "yarn", || node::run_yarn_upgrade(&ctx));
+    steps.add(Step::Pnpm, "pnpm", || node::run_pnpm_upgrade(&ctx));
+    steps.add(Step::VoltaPackages, "volta packages", || {
         node::run_volta_packages_upgrade(&ctx)
-    })?;
-    runner.execute(Step::Containers, "Containers", || containers::run_containers(&ctx))?;
-    runner.execute(Step::Deno, "deno", || node::deno_upgrade(&ctx))?;
-    runner.execute(Step::Composer, "composer", || generic::run_composer_update(&ctx))?;
-    runner.execute(Step::Krew, "krew", || generic::run_krew_upgrade(&ctx))?;
-    runner.execute(Step::Helm, "helm", || generic::run_helm_repo_update(&ctx))?;
-    runner.execute(Step::Gem, "gem", || generic::run_gem(&ctx))?;
-    runner.execute(Step::RubyGems, "rubygems", || generic::run_rubygems(&ctx))?;
-    runner.execute(Step::Julia, "julia", || generic::update_julia_packages(&ctx))?;
-    runner.execute(Step::Haxelib, "haxelib", || generic::run_haxelib_update(&ctx))?;
-    runner.execute(Step::Sheldon, "sheldon", || generic::run_sheldon(&ctx))?;
-    runner.execute(Step::Stew, "stew", || generic::run_stew(&ctx))?;
-    runner.execute(Step::Rtcl, "rtcl", || generic::run_rtcl(&ctx))?;
-    runner.execute(Step::Bin, "bin", || generic::bin_update(&ctx))?;
-    runner.execute(Step::Gcloud, "gcloud", || generic::run_gcloud_components_update(&ctx))?;
-    runner.execute(Step::Micro, "micro", || generic::run_micro(&ctx))?;
-    runner.execute(Step::Raco, "raco", || generic::run_raco_update(&ctx))?;
-    runner.execute(Step::Spicetify, "spicetify", || generic::spicetify_upgrade(&ctx))?;
-    runner.execute(Step::GithubCliExtensions, "GitHub CLI Extensions", || {
+    });
+    steps.add(Step::Containers, "Containers", || containers::run_containers(&ctx));
+    steps.add_parallel(Step::Deno, "deno", || node::deno_upgrade(&ctx));
+    steps.add(Step::Composer, "composer", || generic::run_composer_update(&ctx));
+    steps.add_parallel(Step::Krew, "krew", || generic::run_krew_upgrade(&ctx));
+    steps.add_parallel(Step::Helm, "helm", || generic::run_helm_repo_update(&ctx));
+    steps.add(Step::Gem, "gem", || generic::run_gem(&ctx));
+    steps.add(Step::Gem, "bundle", || generic::run_bundle_update(&ctx));
+    steps.add(Step::RubyGems, "rubygems", || generic::run_rubygems(&ctx));
+    steps.add(Step::Terraform, "terraform", || generic::run_terraform(&ctx));
+    steps.add(Step::Julia, "julia", || generic::update_julia_packages(&ctx));
+    steps.add(Step::Haxelib, "haxelib", || generic::run_haxelib_update(&ctx));
+    steps.add_parallel(Step::Sheldon, "sheldon", || generic::run_sheldon(&ctx));
+    steps.add(Step::Stew, "stew", || generic::run_stew(&ctx));
+    steps.add(Step::Rtcl, "rtcl", || generic::run_rtcl(&ctx));
+    steps.add(Step::Bin, "bin", || generic::bin_update(&ctx));
+    steps.add_parallel(Step::Eget, "eget", || generic::run_eget(&ctx));
+    steps.add(Step::Gcloud, "gcloud", || generic::run_gcloud_components_update(&ctx));
+    steps.add_parallel(Step::Micro, "micro", || generic::run_micro(&ctx));
+    steps.add(Step::Raco, "raco", || generic::run_raco_update(&ctx));
+    steps.add(Step::Spicetify, "spicetify", || generic::spicetify_upgrade(&ctx));
+    steps.add_parallel(Step::GithubCliExtensions, "GitHub CLI Extensions", || {
         generic::run_ghcli_extensions_upgrade(&ctx)
-    })?;
-    runner.execute(Step::Bob, "Bob", || generic::run_bob(&ctx))?;
-    runner.execute(Step::Certbot, "Certbot", || generic::run_certbot(&ctx))?;
-    runner.execute(Step::GitRepos, "Git Repositories", || git::run_git_pull(&ctx))?;
-    runner.execute(Step::ClamAvDb, "ClamAV Databases", || generic::run_freshclam(&ctx))?;
-    runner.execute(Step::PlatformioCore, "PlatformIO Core", || {
+    });
+    steps.add(Step::Bob, "Bob", || generic::run_bob(&ctx));
+    steps.add(Step::Certbot, "Certbot", || generic::run_certbot(&ctx));
+    steps.add(Step::GitRepos, "Git Repositories", || git::run_git_pull(&ctx));
+    steps.add(Step::ClamAvDb, "ClamAV Databases", || generic::run_freshclam(&ctx));
+    steps.add(Step::PlatformioCore, "PlatformIO Core", || {
         generic::run_platform_io(&ctx)
-    })?;
-    runner.execute(Step::Lensfun, "Lensfun's database update", || {
+    });
+    steps.add(Step::Lensfun, "Lensfun's database update", || {
         generic::run_lensfun_update_data(&ctx)
-    })?;
-    runner.execute(Step::Poetry, "Poetry", || generic::run_poetry(&ctx))?;
-    runner.execute(Step::Uv, "uv", || generic::run_uv(&ctx))?;
-    runner.execute(Step::Zvm, "ZVM", || generic::run_zvm(&ctx))?;
-    runner.execute(Step::Aqua, "aqua", || generic::run_aqua(&ctx))?;
-    runner.execute(Step::Bun, "bun", || generic::run_bun(&ctx))?;
-    runner.execute(Step::Zigup, "zigup", || generic::run_zigup(&ctx))?;
-    runner.execute(Step::JetbrainsToolbox, "JetBrains Toolbox", || {
+    });
+    steps.add_parallel(Step::Poetry, "Poetry", || generic::run_poetry(&ctx));
+    steps.add_parallel(Step::Uv, "uv", || generic::run_uv(&ctx));
+    steps.add(Step::Zvm, "ZVM", || generic::run_zvm(&ctx));
+    steps.add(Step::Aqua, "aqua", || generic::run_aqua(&ctx));
+    steps.add_parallel(Step::Bun, "bun", || generic::run_bun(&ctx));
+    steps.add_parallel(Step::Zigup, "zigup", || generic::run_zigup(&ctx));
+    steps.add(Step::JetbrainsToolbox, "JetBrains Toolbox", || {
         generic::run_jetbrains_toolbox(&ctx)
-    })?;
-    runner.execute(Step::AndroidStudio, "Android Studio plugins", || {
+    });
+    steps.add(Step::AndroidStudio, "Android Studio plugins", || {
         generic::run_android_studio(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsAqua, "JetBrains Aqua plugins", || {
+    });
+    steps.add(Step::JetbrainsAqua, "JetBrains Aqua plugins", || {
         generic::run_jetbrains_aqua(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsClion, "JetBrains CLion plugins", || {
+    });
+    steps.add(Step::JetbrainsClion, "JetBrains CLion plugins", || {
         generic::run_jetbrains_clion(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsDatagrip, "JetBrains DataGrip plugins", || {
+    });
+    steps.add(Step::JetbrainsDatagrip, "JetBrains DataGrip plugins", || {
         generic::run_jetbrains_datagrip(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsDataspell, "JetBrains DataSpell plugins", || {
+    });
+    steps.add(Step::JetbrainsDataspell, "JetBrains DataSpell plugins", || {
         generic::run_jetbrains_dataspell(&ctx)
-    })?;
+    });
     // JetBrains dotCover has no CLI
     // JetBrains dotMemory has no CLI
     // JetBrains dotPeek has no CLI
     // JetBrains dotTrace has no CLI
     // JetBrains Fleet has a different CLI without a `fleet update` command.
-    runner.execute(Step::JetbrainsGateway, "JetBrains Gateway plugins", || {
+    steps.add(Step::JetbrainsGateway, "JetBrains Gateway plugins", || {
         generic::run_jetbrains_gateway(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsGoland, "JetBrains GoLand plugins", || {
+    });
+    steps.add(Step::JetbrainsGoland, "JetBrains GoLand plugins", || {
         generic::run_jetbrains_goland(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsIdea, "JetBrains IntelliJ IDEA plugins", || {
+    });
+    steps.add(Step::JetbrainsIdea, "JetBrains IntelliJ IDEA plugins", || {
         generic::run_jetbrains_idea(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsMps, "JetBrains MPS plugins", || {
+    });
+    steps.add(Step::JetbrainsMps, "JetBrains MPS plugins", || {
         generic::run_jetbrains_mps(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsPhpstorm, "JetBrains PhpStorm plugins", || {
+    });
+    steps.add(Step::JetbrainsPhpstorm, "JetBrains PhpStorm plugins", || {
         generic::run_jetbrains_phpstorm(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsPycharm, "JetBrains PyCharm plugins", || {
+    });
+    steps.add(Step::JetbrainsPycharm, "JetBrains PyCharm plugins", || {
         generic::run_jetbrains_pycharm(&ctx)
-    })?;
+    });
     // JetBrains ReSharper has no CLI (it's a VSCode extension)
     // JetBrains ReSharper C++ has no CLI (it's a VSCode extension)
-    runner.execute(Step::JetbrainsRider, "JetBrains Rider plugins", || {
+    steps.add(Step::JetbrainsRider, "JetBrains Rider plugins", || {
         generic::run_jetbrains_rider(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsRubymine, "JetBrains RubyMine plugins", || {
+    });
+    steps.add(Step::JetbrainsRubymine, "JetBrains RubyMine plugins", || {
         generic::run_jetbrains_rubymine(&ctx)
-    })?;
-    runner.execute(Step::JetbrainsRustrover, "JetBrains RustRover plugins", || {
+    });
+    steps.add(Step::JetbrainsRustrover, "JetBrains RustRover plugins", || {
         generic::run_jetbrains_rustrover(&ctx)
-    })?;
+    });
     // JetBrains Space Desktop does not have a CLI
-    runner.execute(Step::JetbrainsWebstorm, "JetBrains WebStorm plugins", || {
+    steps.add(Step::JetbrainsWebstorm, "JetBrains WebStorm plugins", || {
         generic::run_jetbrains_webstorm(&ctx)
-    })?;
-    runner.execute(Step::Yazi, "Yazi packages", || generic::run_yazi(&ctx))?;
+    });
+    steps.add(Step::Yazi, "Yazi packages", || generic::run_yazi(&ctx));
 
     if should_run_powershell {
-        runner.execute(Step::Powershell, "Powershell Modules Update", || {
+        steps.add(Step::Powershell, "Powershell Modules Update", || {
             powershell.update_modules(&ctx)
-        })?;
+        });
     }
 
     if let Some(commands) = config.commands() {
         for (name, command) in commands {
             if config.should_run_custom_command(name) {
-                runner.execute(Step::CustomCommands, name, || {
-                    generic::run_custom_command(name, command, &ctx)
-                })?;
+                let ctx = &ctx;
+                steps.add(Step::CustomCommands, name, move || {
+                    generic::run_custom_command(name, command, ctx)
+                });
             }
         }
     }
 
-    if config.should_run(Step::Vagrant) {
+    for manager in config.custom_package_managers() {
+        let ctx = &ctx;
+        steps.add(Step::CustomPackageManagers, manager.name(), move || {
+            generic::run_custom_package_manager(manager, ctx)
+        });
+    }
+
+    if config.should_run(Step::Vagrant) && !config.list_steps() && plan.is_none() {
         if let Ok(boxes) = vagrant::collect_boxes(&ctx) {
             for vagrant_box in boxes {
-                runner.execute(Step::Vagrant, format!("Vagrant ({})", vagrant_box.smart_name()), || {
-                    vagrant::topgrade_vagrant_box(&ctx, &vagrant_box)
-                })?;
+                let ctx = &ctx;
+                steps.add(
+                    Step::Vagrant,
+                    format!("Vagrant ({})", vagrant_box.smart_name()),
+                    move || vagrant::topgrade_vagrant_box(ctx, &vagrant_box),
+                );
             }
         }
     }
-    runner.execute(Step::Vagrant, "Vagrant boxes", || vagrant::upgrade_vagrant_boxes(&ctx))?;
+    steps.add(Step::Vagrant, "Vagrant boxes", || vagrant::upgrade_vagrant_boxes(&ctx));
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    steps.add(Step::Maintenance, "Maintenance", || unix::run_maintenance(&ctx));
+
+    if config.list_steps() {
+        steps.print_list(&config);
+        return Ok(());
+    }
+
+    if let Some(plan) = &plan {
+        steps = plan.steps(&ctx);
+    }
+
+    runner.run_steps(steps)?;
+
+    if let Some(path) = config.plan_out() {
+        plan::save(path)?;
+    }
+    if let Some(path) = config.trace_out() {
+        trace::save(path)?;
+    }
 
-    if !runner.report().data().is_empty() {
+    if config.output_format() == OutputFormat::Human && !runner.report().data().is_empty() {
         print_separator(t!("Summary"));
 
-        for (key, result) in runner.report().data() {
-            print_result(key, result);
+        if config.summary_group_by_status() {
+            print_grouped_results(runner.report().data());
+        } else {
+            for step in runner.report().data() {
+                print_result(step);
+            }
+        }
+
+        if let Some((packages, managers)) = runner.report().updated_packages() {
+            print_info(t!(
+                "Updated {packages} packages across {managers} package managers",
+                packages = packages,
+                managers = managers
+            ));
+        }
+
+        if config.display_time() {
+            print_total_time(started.elapsed());
         }
 
         #[cfg(target_os = "linux")]
@@ -547,16 +768,26 @@ fn run() -> Result<()> {
         }
     }
 
+    if config.explain() && config.output_format() == OutputFormat::Human {
+        explain::print_summary();
+    }
+
+    if config.output_format() == OutputFormat::Human {
+        audit::print_summary();
+    }
+
     let mut post_command_failed = false;
     if let Some(commands) = config.post_commands() {
         for (name, command) in commands {
-            if generic::run_custom_command(name, command, &ctx).is_err() {
-                post_command_failed = true;
+            match generic::run_custom_command(name, command, &ctx) {
+                Err(e) if is_skipped(&e) => debug!("Skipping {name}: {e}"),
+                Err(_) => post_command_failed = true,
+                Ok(()) => (),
             }
         }
     }
 
-    if config.keep_at_end() {
+    if config.keep_at_end() && is_interactive() {
         print_info(t!("\n(R)eboot\n(S)hell\n(Q)uit"));
         loop {
             match get_key() {
@@ -575,17 +806,73 @@ fn run() -> Result<()> {
         }
     }
 
-    let failed = post_command_failed || runner.report().data().iter().any(|(_, result)| result.failed());
+    let failed = post_command_failed || runner.report().data().iter().any(|step| step.result.failed());
+
+    if let Some(mut stdout) = json_stdout {
+        writeln!(stdout, "{}", runner.report().to_json(failed)).context("Failed to write the JSON report")?;
+    }
+
+    if let Some(path) = config.report_junit() {
+        fs::write(path, runner.report().to_junit())
+            .with_context(|| format!("Failed to write the JUnit report to {}", path.display()))?;
+    }
 
     if !config.skip_notify() {
-        notify_desktop(
-            if failed {
-                t!("Topgrade finished with errors")
-            } else {
-                t!("Topgrade finished successfully")
-            },
-            Some(Duration::from_secs(10)),
-        );
+        let message = if failed {
+            t!("Topgrade finished with errors")
+        } else {
+            t!("Topgrade finished successfully")
+        };
+        notify_desktop(&message, Some(Duration::from_secs(10)));
+
+        let failed_steps: Vec<&str> = runner
+            .report()
+            .data()
+            .iter()
+            .filter(|step| step.result.failed())
+            .map(|step| step.key.as_ref())
+            .collect();
+        let mut message = message.into_owned();
+        if !failed_steps.is_empty() {
+            message.push('\n');
+            message.push_str(&t!("Failed steps: {steps}", steps = failed_steps.join(", ")));
+        }
+        notifications::send(&config, &message, runner.report(), failed);
+    }
+
+    if !config.dry_run() {
+        let result = if failed {
+            let succeeded = runner
+                .report()
+                .data()
+                .iter()
+                .filter(|step| matches!(step.result, StepResult::Success))
+                .map(|step| step.key.as_ref());
+            resume_state.save(config_hash, succeeded)
+        } else {
+            resume::clear()
+        };
+        if let Err(e) = result {
+            debug!("Failed to record the steps that succeeded: {e:?}");
+        }
+    }
+
+    if !failed && !config.dry_run() {
+        if let Err(e) = last_run::record() {
+            debug!("Failed to record the time of this run: {e:?}");
+        }
+    }
+
+    if ctx.reboot_required() {
+        match config.reboot() {
+            RebootMode::Auto => reboot().context("Failed to reboot")?,
+            RebootMode::Prompt => {
+                if prompt_yesno(&t!("A reboot is needed to finish updating. Reboot now?"))? {
+                    reboot().context("Failed to reboot")?;
+                }
+            }
+            RebootMode::Never => print_info(t!("A reboot is needed to finish updating")),
+        }
     }
 
     if failed {
@@ -595,32 +882,51 @@ fn run() -> Result<()> {
     }
 }
 
+/// Print the results of the steps under a header for each kind of result, failures first.
+fn print_grouped_results(steps: &[report::StepReport]) {
+    let group = |result: &StepResult| match result {
+        // Ignored failures didn't succeed either.
+        StepResult::Failure(_) | StepResult::TimedOut | StepResult::Ignored => 0,
+        StepResult::Skipped(_) => 1,
+        StepResult::NotInstalled(_) => 2,
+        StepResult::Success => 3,
+    };
+    let headers = [t!("Failed"), t!("Skipped"), t!("Not installed"), t!("Succeeded")];
+    for (index, header) in headers.into_iter().enumerate() {
+        let mut steps = steps.iter().filter(|step| group(&step.result) == index).peekable();
+        if steps.peek().is_some() {
+            print_info(header);
+            steps.for_each(print_result);
+        }
+    }
+}
+
 fn main() {
     match run() {
         Ok(()) => {
-            exit(0);
+            exit(ExitCode::Success as i32);
         }
         Err(error) => {
             #[cfg(all(windows, feature = "self-update"))]
             {
                 if let Some(Upgraded(status)) = error.downcast_ref::<Upgraded>() {
-                    exit(status.code().unwrap());
+                    exit(status.code().unwrap_or(ExitCode::SelfUpdated as i32));
                 }
             }
 
-            let skip_print = (error.downcast_ref::<StepFailed>().is_some())
-                || (error
-                    .downcast_ref::<io::Error>()
-                    .filter(|io_error| io_error.kind() == io::ErrorKind::Interrupted)
-                    .is_some());
+            let exit_code = ExitCode::from_error(&error);
+            let skip_print = matches!(exit_code, ExitCode::StepFailed | ExitCode::Interrupted);
 
-            if !skip_print {
+            if exit_code == ExitCode::AlreadyRunning {
+                // Not a failure of Topgrade, so there is nothing to debug.
+                println!("{}", t!("Error: {error}", error = error));
+            } else if !skip_print {
                 // The `Debug` implementation of `eyre::Result` prints a multi-line
                 // error message that includes all the 'causes' added with
                 // `.with_context(...)` calls.
                 println!("{}", t!("Error: {error}", error = format!("{:?}", error)));
             }
-            exit(1);
+            exit(exit_code as i32);
         }
     }
 }
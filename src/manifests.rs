@@ -0,0 +1,165 @@
+//! Skipping steps whose manifest did not change since the last run, see `skip_unchanged`.
+//!
+//! After a successful run, a step remembers a hash of the files describing what it manages
+//! (`.crates.toml` for cargo, the global `package-lock.json` for npm, ...), or of some state
+//! it gets up front, like the remote refs of the Git repositories. When `skip_unchanged` is
+//! enabled, the step is skipped as long as that hash stays the same.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use color_eyre::eyre::{Context, Result};
+use rust_i18n::t;
+use tracing::debug;
+
+use crate::breaking_changes::data_dir;
+use crate::config::Step;
+use crate::error::SkipStep;
+use crate::execution_context::ExecutionContext;
+
+/// Steps running in parallel must not overwrite each other's hashes.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The file the hashes are stored in, one entry per step.
+fn state_file() -> PathBuf {
+    data_dir().join("topgrade_manifests.toml")
+}
+
+fn load(path: &Path) -> BTreeMap<String, String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// A 64-bit FNV-1a hash, which unlike `DefaultHasher` stays the same across Rust releases.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The initial value of FNV-1a hashes.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// A hash of `bytes` that stays the same across Topgrade builds, to be saved between runs.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, bytes)
+}
+
+/// Hash the paths of `files` along with the contents of the ones that are regular files.
+fn hash(files: &[PathBuf]) -> String {
+    let hash = files.iter().fold(FNV_OFFSET_BASIS, |hash, file| {
+        let hash = fnv1a(hash, file.to_string_lossy().as_bytes());
+        match fs::read(file) {
+            Ok(contents) => fnv1a(fnv1a(hash, b"\0"), &contents),
+            Err(_) => fnv1a(hash, b"\x01"),
+        }
+    });
+
+    format!("{hash:016x}")
+}
+
+/// What the hash of a step is computed from.
+enum Source {
+    /// Files the step updates, hashed again once it ran.
+    Files(Vec<PathBuf>),
+    /// The hash of state the step doesn't change, if it could be read.
+    State(Option<String>),
+}
+
+impl Source {
+    fn hash(&self) -> Option<String> {
+        match self {
+            Source::Files(files) => Some(hash(files)),
+            Source::State(hash) => hash.clone(),
+        }
+    }
+}
+
+/// The manifests of a step, to be remembered once it ran successfully.
+pub struct Manifests<'a> {
+    ctx: &'a ExecutionContext<'a>,
+    step: Step,
+    source: Source,
+}
+
+impl<'a> Manifests<'a> {
+    /// Skip `step` if `skip_unchanged` is enabled and `files` haven't changed since its last
+    /// successful run.
+    pub fn check(ctx: &'a ExecutionContext<'a>, step: Step, files: Vec<PathBuf>) -> Result<Self> {
+        Self::check_source(ctx, step, Source::Files(files))
+    }
+
+    /// Like `check`, but for files that take work to find, like running the package manager.
+    ///
+    /// `files` is only called when `skip_unchanged` is enabled, and returns `None` when they
+    /// can't be found, in which case the step runs and nothing is remembered.
+    pub fn check_with(
+        ctx: &'a ExecutionContext<'a>,
+        step: Step,
+        files: impl FnOnce() -> Option<Vec<PathBuf>>,
+    ) -> Result<Self> {
+        let source = if ctx.config().skip_unchanged() {
+            files().map_or(Source::State(None), Source::Files)
+        } else {
+            Source::State(None)
+        };
+
+        Self::check_source(ctx, step, source)
+    }
+
+    /// Like `check`, but for steps whose work shows in state they don't change themselves.
+    ///
+    /// `state` is only read when `skip_unchanged` is enabled, and returns `None` when it
+    /// can't be read, in which case the step runs and nothing is remembered.
+    pub fn check_state(
+        ctx: &'a ExecutionContext<'a>,
+        step: Step,
+        state: impl FnOnce() -> Option<Vec<u8>>,
+    ) -> Result<Self> {
+        let hash = if ctx.config().skip_unchanged() {
+            state().map(|state| format!("{:016x}", stable_hash(&state)))
+        } else {
+            None
+        };
+
+        Self::check_source(ctx, step, Source::State(hash))
+    }
+
+    fn check_source(ctx: &'a ExecutionContext<'a>, step: Step, source: Source) -> Result<Self> {
+        if ctx.config().skip_unchanged() && !ctx.config().force() {
+            let _lock = STATE_LOCK.lock().unwrap();
+            if let Some(hash) = source.hash() {
+                if load(&state_file()).get(step.as_ref()) == Some(&hash) {
+                    return Err(SkipStep(t!("Manifest unchanged since the last run").to_string()).into());
+                }
+            }
+        }
+
+        Ok(Self { ctx, step, source })
+    }
+
+    /// Store the hash of the manifests as they are now, which is usually after the step
+    /// updated them. This also happens with `--force`, so that the next run can be skipped.
+    pub fn remember(self) -> Result<()> {
+        if self.ctx.run_type().dry() || !self.ctx.config().skip_unchanged() {
+            return Ok(());
+        }
+
+        let Some(hash) = self.source.hash() else {
+            return Ok(());
+        };
+
+        let _lock = STATE_LOCK.lock().unwrap();
+        let path = state_file();
+        let mut hashes = load(&path);
+        debug!("Manifest hash of {:?} is {}", self.step, hash);
+        hashes.insert(self.step.as_ref().to_owned(), hash);
+
+        fs::create_dir_all(data_dir())?;
+        fs::write(&path, toml::to_string(&hashes)?).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,342 @@
+//! Notifications about the end of a run, sent to services other than the desktop.
+
+use std::io::Write;
+use std::process::Command;
+
+use chrono::Local;
+use color_eyre::eyre::{eyre, Result};
+use rust_i18n::t;
+use serde_json::{json, Value};
+use tempfile::NamedTempFile;
+use tracing::error;
+
+use crate::command::CommandExt;
+use crate::config::{Config, EmailConfig, EmailTls, WebhookFormat};
+use crate::report::{Report, StepResult};
+use crate::terminal::format_duration;
+use crate::utils::{hostname, require};
+
+/// Send `message` to every configured notification service.
+///
+/// Failing to reach a service is logged, but never fails the run.
+pub fn send(config: &Config, message: &str, report: &Report, failed: bool) {
+    let title = match hostname() {
+        Ok(hostname) => format!("Topgrade ({hostname})"),
+        Err(_) => String::from("Topgrade"),
+    };
+
+    if let Some(url) = config.ntfy_url() {
+        if let Err(e) = ntfy(url, config.ntfy_token(), &title, message, failed) {
+            error!("Failed to send the ntfy notification: {e:?}");
+        }
+    }
+
+    if let Some(url) = config.webhook_url() {
+        let counts = Counts::new(report);
+        let text = webhook_text(config.webhook_template(), message, report, &counts);
+        let payload = webhook_payload(config.webhook_format(), &title, &text, &counts, failed);
+        if let Err(e) = webhook(url, &payload) {
+            error!("Failed to send the webhook notification: {e:?}");
+        }
+    }
+
+    if let (Some(bot_token), Some(chat_id)) = (config.telegram_bot_token(), config.telegram_chat_id()) {
+        let text = telegram_text(&title, message, report);
+        if let Err(e) = telegram(bot_token, chat_id, &text) {
+            error!("Failed to send the Telegram notification: {e:?}");
+        }
+    }
+
+    if let Some(email_config) = config.email() {
+        let subject = format!("{title}: {}", if failed { t!("FAILED") } else { t!("OK") });
+        if let Err(e) = email(&email_config, &subject, &email_text(message, report)) {
+            error!("Failed to send the email notification: {e:?}");
+        }
+    }
+}
+
+/// How many steps ended up in each state.
+struct Counts {
+    success: usize,
+    failed: usize,
+    skipped: usize,
+    ignored: usize,
+}
+
+impl Counts {
+    fn new(report: &Report) -> Self {
+        let mut counts = Self {
+            success: 0,
+            failed: 0,
+            skipped: 0,
+            ignored: 0,
+        };
+        for step in report.data() {
+            match step.result {
+                StepResult::Success => counts.success += 1,
+                StepResult::Failure(_) | StepResult::TimedOut => counts.failed += 1,
+                StepResult::Skipped(_) | StepResult::NotInstalled(_) => counts.skipped += 1,
+                StepResult::Ignored => counts.ignored += 1,
+            }
+        }
+
+        counts
+    }
+}
+
+/// The text of the webhook message, rendered from `template` when there is one.
+fn webhook_text(template: Option<&str>, message: &str, report: &Report, counts: &Counts) -> String {
+    let Some(template) = template else {
+        return format!(
+            "{message}\n{}",
+            t!(
+                "{success} succeeded, {failed} failed, {skipped} skipped",
+                success = counts.success,
+                failed = counts.failed,
+                skipped = counts.skipped
+            )
+        );
+    };
+
+    let failed_steps: Vec<&str> = report
+        .data()
+        .iter()
+        .filter(|step| step.result.failed())
+        .map(|step| step.key.as_ref())
+        .collect();
+
+    template
+        .replace("{hostname}", &hostname().unwrap_or_default())
+        .replace("{message}", message)
+        .replace("{failed_steps}", &failed_steps.join(", "))
+        .replace("{success}", &counts.success.to_string())
+        .replace("{failed}", &counts.failed.to_string())
+        .replace("{skipped}", &counts.skipped.to_string())
+        .replace("{ignored}", &counts.ignored.to_string())
+}
+
+/// Build the JSON payload expected by the webhook.
+fn webhook_payload(format: WebhookFormat, title: &str, text: &str, counts: &Counts, failed: bool) -> Value {
+    match format {
+        WebhookFormat::Generic => json!({
+            "title": title,
+            "text": text,
+            "failed": failed,
+            "counts": {
+                "success": counts.success,
+                "failed": counts.failed,
+                "skipped": counts.skipped,
+                "ignored": counts.ignored,
+            },
+        }),
+        // https://api.slack.com/reference/messaging/attachments
+        WebhookFormat::Slack => json!({
+            "attachments": [{
+                "color": if failed { "#a30200" } else { "#2eb886" },
+                "title": title,
+                "text": text,
+            }],
+        }),
+        // https://discord.com/developers/docs/resources/webhook#execute-webhook
+        WebhookFormat::Discord => json!({
+            "embeds": [{
+                "color": if failed { 0xa3_0200 } else { 0x2e_b886 },
+                "title": title,
+                "description": text,
+            }],
+        }),
+    }
+}
+
+/// Post `payload` to a webhook.
+fn webhook(url: &str, payload: &Value) -> Result<()> {
+    let curl = require("curl")?;
+    // Slack and Discord webhook URLs contain a token.
+    let secrets = curl_secrets(&[("url", url.to_owned())])?;
+    Command::new(curl)
+        .args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+        .args(["--header", "Content-Type: application/json"])
+        .arg("--data-binary")
+        .arg(payload.to_string())
+        .arg("--config")
+        .arg(secrets.path())
+        .output_checked()?;
+
+    Ok(())
+}
+
+/// The longest message the Telegram Bot API accepts.
+const TELEGRAM_MAX_LENGTH: usize = 4096;
+
+/// The text of the Telegram message: `message` followed by the result of every step, cut
+/// short if it gets too long.
+fn telegram_text(title: &str, message: &str, report: &Report) -> String {
+    let mut text = format!("{title}\n{message}\n");
+    for step in report.data() {
+        let result = match &step.result {
+            StepResult::Success => t!("OK"),
+            StepResult::Failure(_) => t!("FAILED"),
+            StepResult::Ignored => t!("IGNORED"),
+            StepResult::Skipped(_) => t!("SKIPPED"),
+            StepResult::NotInstalled(_) => t!("NOT INSTALLED"),
+            StepResult::TimedOut => t!("TIMED OUT"),
+        };
+        text.push_str(&format!("\n{}: {result}", step.key));
+    }
+
+    if text.chars().count() > TELEGRAM_MAX_LENGTH {
+        text = text.chars().take(TELEGRAM_MAX_LENGTH - 1).collect();
+        text.push('…');
+    }
+
+    text
+}
+
+/// Send a message through a Telegram bot.
+///
+/// See: https://core.telegram.org/bots/api#sendmessage
+fn telegram(bot_token: &str, chat_id: &str, text: &str) -> Result<()> {
+    let curl = require("curl")?;
+    // The URL contains the token.
+    let secrets = curl_secrets(&[("url", format!("https://api.telegram.org/bot{bot_token}/sendMessage"))])?;
+    let output = Command::new(curl)
+        .args(["--silent", "--show-error", "--max-time", "30"])
+        .args(["--header", "Content-Type: application/json"])
+        .arg("--data-binary")
+        .arg(json!({ "chat_id": chat_id, "text": text }).to_string())
+        .arg("--config")
+        .arg(secrets.path())
+        .output_checked_with_utf8(|_| Ok(()))?;
+
+    // The API answers with `{"ok": false, "description": ...}` on errors.
+    match serde_json::from_str::<Value>(&output.stdout) {
+        Ok(response) if response["ok"] == true => Ok(()),
+        Ok(response) => Err(eyre!("{}", response["description"].as_str().unwrap_or(&output.stdout))),
+        Err(_) => Err(eyre!("{}", output.stderr.trim())),
+    }
+}
+
+/// Publish a message to an ntfy topic.
+///
+/// See: https://docs.ntfy.sh/publish/
+fn ntfy(url: &str, token: Option<&str>, title: &str, message: &str, failed: bool) -> Result<()> {
+    let curl = require("curl")?;
+    let mut command = Command::new(curl);
+    command
+        .args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+        .arg("--header")
+        .arg(format!("Title: {title}"));
+
+    if failed {
+        command.args(["--header", "Priority: high", "--header", "Tags: warning"]);
+    }
+
+    let mut secrets = Vec::new();
+    if let Some(token) = token {
+        secrets.push(("header", format!("Authorization: Bearer {token}")));
+    }
+    let secrets = curl_secrets(&secrets)?;
+    command.arg("--config").arg(secrets.path());
+
+    command.arg("--data-binary").arg(message).arg(url).output_checked()?;
+
+    Ok(())
+}
+
+/// The body of the email: `message` followed by the summary of the run.
+fn email_text(message: &str, report: &Report) -> String {
+    let mut text = format!("{message}\n");
+    for step in report.data() {
+        let result = match &step.result {
+            StepResult::Success => t!("OK").into_owned(),
+            StepResult::Failure(_) => t!("FAILED").into_owned(),
+            StepResult::Ignored => t!("IGNORED").into_owned(),
+            StepResult::Skipped(reason) => format!("{}: {reason}", t!("SKIPPED")),
+            StepResult::NotInstalled(reason) => format!("{}: {reason}", t!("NOT INSTALLED")),
+            StepResult::TimedOut => t!("TIMED OUT").into_owned(),
+        };
+        text.push_str(&format!(
+            "\n{}: {result} [{}]",
+            step.key,
+            format_duration(step.duration)
+        ));
+    }
+
+    text
+}
+
+/// Encode a header value as an RFC 2047 encoded word if it isn't plain ASCII.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_owned();
+    }
+
+    let encoded: String = value
+        .bytes()
+        .map(|byte| match byte {
+            b' ' => String::from("_"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => char::from(byte).to_string(),
+            _ => format!("={byte:02X}"),
+        })
+        .collect();
+    format!("=?UTF-8?Q?{encoded}?=")
+}
+
+/// Quote `value` for a curl config file.
+fn curl_config_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Write `options` to a curl config file, to be passed with `--config`, so that secrets show
+/// up neither in the process list nor in errors. Only the user can read the file.
+fn curl_secrets(options: &[(&str, String)]) -> Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+    for (option, value) in options {
+        writeln!(file, "{option} = {}", curl_config_quote(value))?;
+    }
+
+    Ok(file)
+}
+
+/// Send an email through an SMTP server.
+fn email(config: &EmailConfig, subject: &str, text: &str) -> Result<()> {
+    let curl = require("curl")?;
+
+    let mut mail = NamedTempFile::new()?;
+    write!(
+        mail,
+        "From: {}\nTo: {}\nSubject: {}\nDate: {}\nMIME-Version: 1.0\n\
+         Content-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: 8bit\n\n{text}\n",
+        config.from,
+        config.to.join(", "),
+        encode_header(subject),
+        Local::now().to_rfc2822(),
+    )?;
+
+    let mut command = Command::new(curl);
+    command
+        .args(["--silent", "--show-error", "--max-time", "60", "--crlf"])
+        .arg("--url")
+        .arg(&config.url)
+        .arg("--mail-from")
+        .arg(config.from)
+        .arg("--upload-file")
+        .arg(mail.path());
+    for to in config.to {
+        command.arg("--mail-rcpt").arg(to);
+    }
+    if config.tls == EmailTls::Starttls {
+        command.arg("--ssl-reqd");
+    }
+
+    let mut credentials = Vec::new();
+    if let Some(username) = config.username {
+        credentials.push(("user", format!("{username}:{}", config.password.unwrap_or_default())));
+    }
+    let credentials = curl_secrets(&credentials)?;
+    command.arg("--config").arg(credentials.path());
+
+    command.output_checked()?;
+
+    Ok(())
+}
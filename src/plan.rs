@@ -0,0 +1,171 @@
+//! Saving the commands a dry run would run, and running them later, see `--plan-out` and
+//! `--plan-in`.
+//!
+//! Only the commands steps run through `RunType::execute` are saved. Commands steps run
+//! directly with `Command::new`, like the ones finding out what to update, are not replayed.
+//! Neither is the fact that a step ignores the failure of a command: a replayed command that
+//! fails makes its step fail, unless it exits with one of the codes the step accepts, see
+//! `Executor::status_checked_with_codes`.
+
+use std::cell::Cell;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use color_eyre::eyre::{Context, Result};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Step;
+use crate::execution_context::ExecutionContext;
+use crate::runner::Steps;
+use crate::terminal::{print_info, print_warning};
+
+/// The steps of a run, in the order they ran, and the commands they ran.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    /// Version of Topgrade that wrote the plan.
+    version: String,
+    steps: Vec<PlannedStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PlannedStep {
+    step: Step,
+    name: String,
+    commands: Vec<PlannedCommand>,
+    /// Steps that were skipped or failed are left out of the saved plan.
+    #[serde(skip)]
+    dropped: bool,
+}
+
+/// A command as `DryCommand` holds it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub directory: Option<String>,
+    /// Environment variables to set, or to remove if `None`, in order.
+    pub env: Vec<(String, Option<String>)>,
+    /// Exit codes other than 0 the command succeeds with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted_codes: Vec<i32>,
+}
+
+/// The plan being recorded, if `--plan-out` was given.
+static RECORDING: Mutex<Option<Vec<PlannedStep>>> = Mutex::new(None);
+
+thread_local! {
+    /// Index in `RECORDING` of the step running on this thread.
+    static RECORDING_STEP: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Start recording the steps that run and the commands they would run.
+pub fn start_recording() {
+    *RECORDING.lock().unwrap() = Some(Vec::new());
+}
+
+/// Record that the step `name` starts running on this thread.
+pub fn start_step(step: Step, name: &str) {
+    let index = RECORDING.lock().unwrap().as_mut().map(|steps| {
+        steps.push(PlannedStep {
+            step,
+            name: name.to_owned(),
+            commands: Vec::new(),
+            dropped: false,
+        });
+        steps.len() - 1
+    });
+    RECORDING_STEP.with(|cell| cell.set(index));
+}
+
+/// Record that the step running on this thread finished, and whether to leave it out of the
+/// plan.
+pub fn finish_step(dropped: bool) {
+    let Some(index) = RECORDING_STEP.with(Cell::take) else {
+        return;
+    };
+    if let Some(steps) = RECORDING.lock().unwrap().as_mut() {
+        steps[index].dropped = dropped;
+    }
+}
+
+/// Record a command the step running on this thread would run.
+pub fn record_command(command: PlannedCommand) {
+    let Some(index) = RECORDING_STEP.with(Cell::get) else {
+        return;
+    };
+    if let Some(steps) = RECORDING.lock().unwrap().as_mut() {
+        steps[index].commands.push(command);
+    }
+}
+
+/// Write the recorded plan to `path`.
+pub fn save(path: &Path) -> Result<()> {
+    let plan = Plan {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        steps: RECORDING
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|step| !step.dropped)
+            .collect(),
+    };
+    fs::write(path, serde_json::to_string_pretty(&plan)?)
+        .with_context(|| format!("Failed to write the plan to {}", path.display()))?;
+    print_info(t!("Saved the plan to {path}", path = path.display()));
+    Ok(())
+}
+
+impl Plan {
+    /// Read the plan saved to `path` by `--plan-out`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("Failed to read the plan from {}", path.display()))?;
+        let plan: Self =
+            serde_json::from_str(&contents).with_context(|| format!("Failed to parse the plan {}", path.display()))?;
+
+        if plan.version != env!("CARGO_PKG_VERSION") {
+            print_warning(t!(
+                "The plan was made by Topgrade {version}, this is Topgrade {current}",
+                version = plan.version,
+                current = env!("CARGO_PKG_VERSION")
+            ));
+        }
+
+        Ok(plan)
+    }
+
+    /// Steps running the commands of the plan, instead of finding out what to run.
+    pub fn steps<'a>(&'a self, ctx: &'a ExecutionContext) -> Steps<'a> {
+        let mut steps = Steps::new();
+        for planned in &self.steps {
+            steps.add(planned.step, planned.name.as_str(), move || {
+                for command in &planned.commands {
+                    command.run(ctx)?;
+                }
+                Ok(())
+            });
+        }
+        steps
+    }
+}
+
+impl PlannedCommand {
+    fn run(&self, ctx: &ExecutionContext) -> Result<()> {
+        let mut executor = ctx.run_type().execute(&self.program);
+        executor.args(&self.args);
+        if let Some(directory) = &self.directory {
+            executor.current_dir(directory);
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => executor.env(key, value),
+                None => executor.env_remove(key),
+            };
+        }
+        executor.status_checked_with_codes(&self.accepted_codes)
+    }
+}
@@ -1,23 +1,61 @@
 use std::borrow::Cow;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+/// Version of the JSON report emitted by `--output-format json`.
+///
+/// Bump this whenever a field is removed or its meaning changes.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
 
 pub enum StepResult {
     Success,
-    Failure,
+    /// The step failed with this error.
+    Failure(String),
     Ignored,
     Skipped(String),
+    /// The step was skipped because the tool it updates isn't installed.
+    NotInstalled(String),
+    TimedOut,
 }
 
 impl StepResult {
     pub fn failed(&self) -> bool {
         match self {
-            StepResult::Success | StepResult::Ignored | StepResult::Skipped(_) => false,
-            StepResult::Failure => true,
+            StepResult::Success | StepResult::Ignored | StepResult::Skipped(_) | StepResult::NotInstalled(_) => false,
+            StepResult::Failure(_) | StepResult::TimedOut => true,
+        }
+    }
+
+    /// The status of the step in the JSON report.
+    fn status(&self) -> &'static str {
+        match self {
+            StepResult::Success => "success",
+            StepResult::Failure(_) => "failed",
+            StepResult::Ignored => "ignored",
+            StepResult::Skipped(_) => "skipped",
+            StepResult::NotInstalled(_) => "not_installed",
+            StepResult::TimedOut => "timed_out",
         }
     }
 }
 
 type CowString<'a> = Cow<'a, str>;
-type ReportData<'a> = Vec<(CowString<'a>, StepResult)>;
+
+/// The outcome of a single step.
+pub struct StepReport<'a> {
+    pub key: CowString<'a>,
+    pub result: StepResult,
+    pub duration: Duration,
+    /// `0` for successful steps, otherwise the exit code of the command that made the step fail, if known.
+    pub exit_code: Option<i32>,
+    /// How many times the step was run, including retries.
+    pub attempts: u32,
+    /// How many packages the step updated, for the package managers that tell.
+    pub updated_packages: Option<u64>,
+}
+
+type ReportData<'a> = Vec<StepReport<'a>>;
 pub struct Report<'a> {
     data: ReportData<'a>,
 }
@@ -27,19 +65,140 @@ impl<'a> Report<'a> {
         Self { data: Vec::new() }
     }
 
-    pub fn push_result<M>(&mut self, result: Option<(M, StepResult)>)
-    where
+    pub fn push_result<M>(
+        &mut self,
+        key: M,
+        result: StepResult,
+        duration: Duration,
+        exit_code: Option<i32>,
+        attempts: u32,
+        updated_packages: Option<u64>,
+    ) where
         M: Into<CowString<'a>>,
     {
-        if let Some((key, success)) = result {
-            let key = key.into();
+        let key = key.into();
 
-            debug_assert!(!self.data.iter().any(|(k, _)| k == &key), "{key} already reported");
-            self.data.push((key, success));
-        }
+        debug_assert!(!self.data.iter().any(|s| s.key == key), "{key} already reported");
+        self.data.push(StepReport {
+            key,
+            result,
+            duration,
+            exit_code,
+            attempts,
+            updated_packages,
+        });
     }
 
     pub fn data(&self) -> &ReportData<'a> {
         &self.data
     }
+
+    /// How many packages were updated in total, and by how many steps, if any step told.
+    pub fn updated_packages(&self) -> Option<(u64, usize)> {
+        let counts: Vec<u64> = self.data.iter().filter_map(|step| step.updated_packages).collect();
+        (!counts.is_empty()).then(|| (counts.iter().sum(), counts.len()))
+    }
+
+    /// Render the report as JSON. `failed` tells whether the whole run failed.
+    pub fn to_json(&self, failed: bool) -> Value {
+        let steps: Vec<Value> = self
+            .data
+            .iter()
+            .map(|step| {
+                let mut value = json!({
+                    "key": step.key,
+                    "status": step.result.status(),
+                    "duration": step.duration.as_secs_f64(),
+                    "exit_code": step.exit_code,
+                    "attempts": step.attempts,
+                });
+                if let Some(updated_packages) = step.updated_packages {
+                    value["updated_packages"] = json!(updated_packages);
+                }
+                match &step.result {
+                    StepResult::Skipped(reason) | StepResult::NotInstalled(reason) => value["reason"] = json!(reason),
+                    StepResult::Failure(error) => value["error"] = json!(error),
+                    _ => (),
+                }
+                value
+            })
+            .collect();
+
+        json!({
+            "schema_version": JSON_SCHEMA_VERSION,
+            "failed": failed,
+            "steps": steps,
+        })
+    }
+
+    /// Render the report as a JUnit XML document, one test case per step.
+    pub fn to_junit(&self) -> String {
+        let count = |f: fn(&StepResult) -> bool| self.data.iter().filter(|step| f(&step.result)).count();
+        let failures = count(StepResult::failed);
+        let skipped = count(|result| {
+            matches!(
+                result,
+                StepResult::Skipped(_) | StepResult::NotInstalled(_) | StepResult::Ignored
+            )
+        });
+        let time: f64 = self.data.iter().map(|step| step.duration.as_secs_f64()).sum();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"topgrade\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{time:.3}\">\n",
+            tests = self.data.len()
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"topgrade\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{time:.3}\">\n",
+            tests = self.data.len()
+        ));
+
+        for step in &self.data {
+            let testcase = format!(
+                "    <testcase name=\"{}\" classname=\"topgrade\" time=\"{:.3}\"",
+                xml_escape(&step.key),
+                step.duration.as_secs_f64()
+            );
+            match &step.result {
+                StepResult::Success => xml.push_str(&format!("{testcase}/>\n")),
+                StepResult::Failure(error) => xml.push_str(&format!(
+                    "{testcase}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                    xml_escape(error.lines().next().unwrap_or_default()),
+                    xml_escape(error)
+                )),
+                StepResult::TimedOut => xml.push_str(&format!(
+                    "{testcase}>\n      <failure message=\"Timed out\"/>\n    </testcase>\n"
+                )),
+                StepResult::Skipped(reason) | StepResult::NotInstalled(reason) => xml.push_str(&format!(
+                    "{testcase}>\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                    xml_escape(reason)
+                )),
+                StepResult::Ignored => xml.push_str(&format!(
+                    "{testcase}>\n      <skipped message=\"Failure ignored\"/>\n    </testcase>\n"
+                )),
+            }
+        }
+
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+}
+
+/// Escape `text` for use in XML attributes and text.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML 1.0, and of no use in a report.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => (),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
 }
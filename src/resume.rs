@@ -0,0 +1,79 @@
+//! Remembering which steps succeeded in a run that failed, see `--resume`.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use color_eyre::eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::breaking_changes::data_dir;
+
+/// The steps that succeeded in the last run, if it failed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResumeState {
+    /// Hash of the configuration of the run, see `Config::file_hash`. Resuming a run made
+    /// with another configuration would skip the wrong steps.
+    config_hash: u64,
+    /// Keys of the steps that succeeded.
+    succeeded: Vec<String>,
+}
+
+fn state_file() -> PathBuf {
+    data_dir().join("topgrade_resume.json")
+}
+
+impl ResumeState {
+    /// The state left by the last run, if it failed with the configuration hashed to
+    /// `config_hash`.
+    pub fn load(config_hash: u64) -> Self {
+        let state: Option<Self> = fs::read_to_string(state_file())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok());
+
+        match state {
+            Some(state) if state.config_hash == config_hash => state,
+            Some(_) => {
+                debug!("The configuration changed since the last run, not resuming it");
+                Self::default()
+            }
+            None => Self::default(),
+        }
+    }
+
+    /// Keys of the steps that succeeded in the last run.
+    pub fn succeeded(&self) -> &[String] {
+        &self.succeeded
+    }
+
+    /// Record that a run with the configuration hashed to `config_hash` failed, after the
+    /// steps `succeeded` and the ones already skipped from this state succeeded.
+    pub fn save<'a>(mut self, config_hash: u64, succeeded: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        if self.config_hash != config_hash {
+            self.succeeded.clear();
+        }
+        self.config_hash = config_hash;
+        for key in succeeded {
+            if !self.succeeded.iter().any(|succeeded| succeeded == key) {
+                self.succeeded.push(key.to_owned());
+            }
+        }
+
+        let path = state_file();
+        fs::create_dir_all(data_dir())?;
+        fs::write(&path, serde_json::to_string_pretty(&self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Forget the last run, after a run that succeeded or with `--force`.
+pub fn clear() -> Result<()> {
+    let path = state_file();
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
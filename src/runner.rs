@@ -1,17 +1,387 @@
+use crate::config::{Config, CustomCommand, OutputFormat};
 use crate::ctrlc;
-use crate::error::{DryRun, SkipStep};
+use crate::error::{is_skipped, DryRun, NotInstalled, SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
+use crate::executor::{
+    kill_running_children, reset_timed_out, script_comment, set_current_step, set_output_prefix, set_step_env,
+};
+use crate::plan;
 use crate::report::{Report, StepResult};
-use crate::terminal::print_error;
+use crate::steps::generic::run_custom_command;
+use crate::terminal::{
+    capture_output, print_captured, print_error, print_info, print_warning, prompt_yesno, set_progress, step_tag,
+};
+use crate::trace;
 use crate::{config::Step, terminal::should_retry};
-use color_eyre::eyre::Result;
+use chrono::Local;
+use color_eyre::eyre::{self, Result};
+use console::style;
+use rust_i18n::t;
 use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
 use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::mem;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
 use tracing::debug;
 
+/// Watches a running step from another thread and kills its child processes once the
+/// deadline passes.
+struct Watchdog {
+    cancel: Sender<()>,
+    handle: JoinHandle<bool>,
+    step: usize,
+}
+
+impl Watchdog {
+    fn start(timeout: Duration, step: usize) -> Self {
+        let (cancel, cancelled) = mpsc::channel();
+        let handle = thread::spawn(move || match cancelled.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => {
+                kill_running_children(step);
+                true
+            }
+            // Either cancelled explicitly or the sender was dropped.
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => false,
+        });
+
+        Self { cancel, handle, step }
+    }
+
+    /// Stop watching, returning whether the deadline passed.
+    fn stop(self) -> bool {
+        self.cancel.send(()).ok();
+        let timed_out = self.handle.join().unwrap_or(false);
+        reset_timed_out(self.step);
+        timed_out
+    }
+}
+
+/// The exit code of the command that made a step fail.
+fn exit_code(error: &eyre::Report) -> Option<i32> {
+    error
+        .chain()
+        .find_map(|cause| match cause.downcast_ref::<TopgradeError>() {
+            Some(TopgradeError::ProcessFailed(_, status) | TopgradeError::ProcessFailedWithOutput(_, status, _)) => {
+                status.code()
+            }
+            _ => None,
+        })
+}
+
+/// What happened when running a step once.
+enum Outcome {
+    /// How many packages the step updated, if it told, see
+    /// `ExecutionContext::record_updated_packages`.
+    Success(Option<u64>),
+    DryRun,
+    Skipped(String),
+    /// The tool the step updates isn't installed.
+    NotInstalled(String),
+    TimedOut,
+    Failed(eyre::Report),
+}
+
+impl Outcome {
+    /// How `--trace-out` names the outcome.
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Success(_) => "success",
+            Outcome::DryRun => "dry_run",
+            Outcome::Skipped(_) => "skipped",
+            Outcome::NotInstalled(_) => "not_installed",
+            Outcome::TimedOut => "timed_out",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Run the command configured to run before or after a step. Commands that skip themselves,
+/// see `requires` and `run_if`, count as successful.
+fn run_step_command(ctx: &ExecutionContext, name: &str, command: &CustomCommand) -> Result<()> {
+    match run_custom_command(name, command, ctx) {
+        Err(e) if is_skipped(&e) => {
+            debug!("Skipping {name}: {e}");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// The error aborting the run after Ctrl+C was pressed twice, see `ctrl_c_skips_step`.
+fn abort_error() -> eyre::Report {
+    eyre::Report::new(io::Error::from(io::ErrorKind::Interrupted)).wrap_err(t!("Interrupted twice, aborting the run"))
+}
+
+/// Run a step once, killing its children if it runs past its timeout.
+///
+/// `id` tells apart steps running at the same time, see `set_current_step`.
+fn run_once(
+    ctx: &ExecutionContext,
+    step: Step,
+    key: &str,
+    func: &dyn Fn() -> Result<()>,
+    id: usize,
+) -> (Outcome, Duration) {
+    // No child processes get spawned when dry running, so there is nothing to time out.
+    let timeout = if ctx.run_type().dry() {
+        None
+    } else {
+        ctx.config().step_timeout(step)
+    };
+
+    if is_disruptive(step) {
+        if let Some(allowed_hours) = ctx.config().allowed_hours() {
+            if !allowed_hours.contains(Local::now().time()) {
+                let reason = t!("Outside of the allowed hours {hours}", hours = allowed_hours);
+                return (Outcome::Skipped(reason.to_string()), Duration::ZERO);
+            }
+        }
+    }
+
+    let span = tracing::span!(parent: tracing::Span::none(), tracing::Level::TRACE, "step", step = ?step, key = %key);
+    let _guard = span.enter();
+
+    let watchdog = timeout.map(|timeout| Watchdog::start(timeout, id));
+    script_comment(key);
+    plan::start_step(step, key);
+    set_step_env(ctx.config().step_env(step));
+    set_output_prefix(ctx.config().prefix_step_output().then(|| step_tag(key)));
+    ctx.set_current_step(Some(step));
+    let started = Instant::now();
+    let result = func();
+    let duration = started.elapsed();
+    ctx.set_current_step(None);
+    let updated_packages = ctx.take_updated_packages();
+    set_output_prefix(None);
+    set_step_env(Vec::new());
+    plan::finish_step(result.as_ref().is_err_and(|e| e.downcast_ref::<DryRun>().is_none()));
+    let outcome = if watchdog.is_some_and(Watchdog::stop) {
+        let seconds = timeout.unwrap_or_default().as_secs();
+        print_warning(t!(
+            "{key} timed out after {seconds} seconds",
+            key = key,
+            seconds = seconds
+        ));
+        Outcome::TimedOut
+    } else {
+        match result {
+            Ok(()) => Outcome::Success(updated_packages),
+            Err(e) if e.downcast_ref::<DryRun>().is_some() => Outcome::DryRun,
+            Err(e) if e.downcast_ref::<SkipStep>().is_some() => Outcome::Skipped(e.to_string()),
+            Err(e) if e.downcast_ref::<NotInstalled>().is_some() => Outcome::NotInstalled(e.to_string()),
+            Err(e) => Outcome::Failed(e),
+        }
+    };
+    trace::record(step, key, id, started, duration, outcome.name());
+
+    (outcome, duration)
+}
+
+/// Steps that hand the terminal over to another interactive Topgrade, which should not be re-run
+/// without asking.
+fn is_interactive(step: Step) -> bool {
+    matches!(step, Step::Remotes | Step::Vagrant | Step::Wsl)
+}
+
+/// Steps that may disrupt the system while they run, which only run during `allowed_hours`.
+fn is_disruptive(step: Step) -> bool {
+    matches!(step, Step::System | Step::Firmware | Step::Restarts)
+}
+
+/// Run a step, automatically retrying it with exponential backoff if it fails.
+///
+/// `attempts` is incremented for every time the step runs. The returned duration covers all
+/// of them, but not the time spent waiting in between.
+fn run_with_retries(
+    ctx: &ExecutionContext,
+    step: Step,
+    key: &str,
+    func: &dyn Fn() -> Result<()>,
+    id: usize,
+    attempts: &mut u32,
+) -> (Outcome, Duration) {
+    let retries = if is_interactive(step) {
+        0
+    } else {
+        ctx.config().retries()
+    };
+    let mut delay = ctx.config().retry_delay();
+    let mut total = Duration::ZERO;
+    let mut retry = 0;
+
+    loop {
+        *attempts += 1;
+        let (outcome, duration) = run_once(ctx, step, key, func, id);
+        total += duration;
+
+        match outcome {
+            Outcome::Failed(e) if retry < retries && !ctrlc::interrupted() => {
+                debug!("Step {:?} failed: {:?}", key, e);
+                print_warning(t!(
+                    "{key} failed, retrying in {seconds} seconds ({retry}/{retries})",
+                    key = key,
+                    seconds = delay.as_secs(),
+                    retry = retry + 1,
+                    retries = retries
+                ));
+                thread::sleep(delay);
+                delay *= 2;
+                retry += 1;
+            }
+            outcome => return (outcome, total),
+        }
+    }
+}
+
+/// A step waiting to run alongside other steps.
+struct QueuedStep<'a> {
+    step: Step,
+    key: Cow<'a, str>,
+    func: Box<dyn Fn() -> Result<()> + Send + Sync + 'a>,
+    progress: Option<(usize, usize)>,
+}
+
+/// The function running a step, and whether it may run alongside other steps.
+enum StepFunc<'a> {
+    Serial(Box<dyn Fn() -> Result<()> + 'a>),
+    Parallel(Box<dyn Fn() -> Result<()> + Send + Sync + 'a>),
+}
+
+/// The steps of a run, in their default order. See `Runner::run_steps`.
+pub struct Steps<'a> {
+    steps: Vec<(Step, Cow<'a, str>, StepFunc<'a>)>,
+}
+
+impl<'a> Steps<'a> {
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Add a step, see `Runner::execute`.
+    pub fn add<F, M>(&mut self, step: Step, key: M, func: F)
+    where
+        F: Fn() -> Result<()> + 'a,
+        M: Into<Cow<'a, str>>,
+    {
+        self.steps.push((step, key.into(), StepFunc::Serial(Box::new(func))));
+    }
+
+    /// Add a step that may run alongside other steps, see `Runner::execute_parallel`.
+    pub fn add_parallel<F, M>(&mut self, step: Step, key: M, func: F)
+    where
+        F: Fn() -> Result<()> + Send + Sync + 'a,
+        M: Into<Cow<'a, str>>,
+    {
+        self.steps.push((step, key.into(), StepFunc::Parallel(Box::new(func))));
+    }
+
+    /// Print every step, telling whether it would run and why not, see `--list-steps`.
+    ///
+    /// Whether the tools a step needs are installed is only found out when it runs.
+    pub fn print_list(&self, config: &Config) {
+        for step in Step::iter() {
+            // Some steps are only added when there is something for them to do.
+            let configurable = matches!(step, Step::Remotes | Step::CustomCommands | Step::CustomPackageManagers);
+            let available = self.steps.iter().any(|(added, ..)| *added == step)
+                || (step == Step::SelfUpdate && cfg!(feature = "self-update"));
+
+            let reason = if !available && !configurable {
+                Some(t!("Not available on this platform"))
+            } else if !config.should_run(step) {
+                Some(config.disabled_reason(step))
+            } else if !available {
+                Some(t!("Nothing configured"))
+            } else if step == Step::SelfUpdate && config.no_self_update() {
+                Some(t!("Disabled by `no_self_update`"))
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => println!("{}: {} ({reason})", step.as_ref(), style(t!("disabled")).yellow()),
+                None => println!("{}: {}", step.as_ref(), style(t!("enabled")).green()),
+            }
+        }
+    }
+
+    /// Move the steps listed in `order` to the front, in that order. The other steps keep
+    /// their relative order, as do different instances of the same step.
+    fn reorder(&mut self, order: &[Step]) {
+        self.steps
+            .sort_by_key(|(step, ..)| order.iter().position(|ordered| ordered == step).unwrap_or(order.len()));
+    }
+
+    /// Move the steps listed in `last` to the end, in that order. The other steps keep their
+    /// relative order, as do different instances of the same step.
+    fn move_to_end(&mut self, last: &[Step]) {
+        self.steps.sort_by_key(|(step, ..)| {
+            last.iter()
+                .position(|ordered| ordered == step)
+                .map_or(0, |position| position + 1)
+        });
+    }
+
+    /// Shuffle the steps not listed in `order`, which `reorder` moved to the front. Different
+    /// instances of the same step stay together, in their relative order.
+    fn shuffle(&mut self, order: &[Step]) {
+        let pinned = self.steps.iter().filter(|(step, ..)| order.contains(step)).count();
+        let mut groups: Vec<Vec<(Step, Cow<'a, str>, StepFunc<'a>)>> = Vec::new();
+        for entry in self.steps.drain(pinned..) {
+            match groups.iter_mut().find(|group| group[0].0 == entry.0) {
+                Some(group) => group.push(entry),
+                None => groups.push(vec![entry]),
+            }
+        }
+
+        for i in (1..groups.len()).rev() {
+            groups.swap(i, random_below(i as u64 + 1) as usize);
+        }
+        self.steps.extend(groups.into_iter().flatten());
+    }
+}
+
+/// The steps named in the option `option`, warning about names that aren't steps.
+fn parse_steps(names: Option<&Vec<String>>, option: &str) -> Vec<Step> {
+    names
+        .into_iter()
+        .flatten()
+        .filter_map(|name| match Step::from_str(name) {
+            Ok(step) => Some(step),
+            Err(_) => {
+                print_warning(t!("Unknown step {step} in {option}", step = name, option = option));
+                None
+            }
+        })
+        .collect()
+}
+
+/// A random number below `bound`, which must not be 0. The standard library has no random
+/// number generator, but it seeds the keys of its hash maps randomly.
+fn random_below(bound: u64) -> u64 {
+    RandomState::new().build_hasher().finish() % bound
+}
+
 pub struct Runner<'a> {
     ctx: &'a ExecutionContext<'a>,
     report: Report<'a>,
+    queued: Vec<QueuedStep<'a>>,
+    /// Why the remaining steps are skipped, once `max_failures` was reached or a healthcheck
+    /// failed with `healthcheck_abort`.
+    aborted: Option<String>,
+    /// Position of the current step among the steps given to `run_steps`, and their count.
+    progress: Option<(usize, usize)>,
+    /// How many steps were skipped so far, which `progress` leaves out.
+    skipped: usize,
+    /// Keys of the steps that succeeded in the last run, which `--resume` skips.
+    succeeded_before: Vec<String>,
 }
 
 impl<'a> Runner<'a> {
@@ -19,6 +389,89 @@ impl<'a> Runner<'a> {
         Runner {
             ctx,
             report: Report::new(),
+            queued: Vec::new(),
+            aborted: None,
+            progress: None,
+            skipped: 0,
+            succeeded_before: Vec::new(),
+        }
+    }
+
+    /// Skip the steps `keys` in `run_steps`, since they succeeded in the last run.
+    pub fn skip_succeeded(&mut self, keys: &[String]) {
+        self.succeeded_before = keys.to_vec();
+    }
+
+    /// Tell whether `max_failures` steps have failed, warning about it the first time.
+    fn too_many_failures(&mut self) -> bool {
+        if self.aborted.is_some() {
+            return true;
+        }
+
+        let max_failures = self.ctx.config().max_failures();
+        let failures = self.report.data().iter().filter(|step| step.result.failed()).count();
+        if max_failures == 0 || failures < max_failures {
+            return false;
+        }
+
+        print_warning(t!(
+            "{failures} steps failed, skipping the remaining steps",
+            failures = failures
+        ));
+        self.aborted = Some(t!("Too many failed steps").to_string());
+        true
+    }
+
+    /// Record a step that didn't run because of `max_failures` or `healthcheck_abort`. It shows
+    /// up in the summary even when other skipped steps don't.
+    fn skip_after_failures(&mut self, key: Cow<'a, str>) {
+        self.skipped += 1;
+        self.report.push_result(
+            key,
+            StepResult::Skipped(self.aborted.clone().unwrap_or_default()),
+            Duration::ZERO,
+            None,
+            0,
+            None,
+        );
+    }
+
+    /// Whether skipped steps should end up in the report.
+    fn report_skipped(&self) -> bool {
+        let config = self.ctx.config();
+        config.verbose()
+            || config.show_skipped()
+            || config.output_format() == OutputFormat::Json
+            || config.report_junit().is_some()
+    }
+
+    /// Preview `step` with a dry run, then ask whether to run it for real, see `--confirm`.
+    ///
+    /// Steps handing the terminal over to another Topgrade can't be previewed, so they are
+    /// only asked about. Returns why the step should be skipped, if it should.
+    fn preview(&self, step: Step, key: &str, func: &dyn Fn() -> Result<()>) -> Result<Option<String>> {
+        if !is_interactive(step) {
+            self.ctx.set_simulating(true);
+            let (outcome, _) = run_once(self.ctx, step, key, func, 0);
+            self.ctx.set_simulating(false);
+
+            match outcome {
+                // The step would skip itself for real too
+                Outcome::Skipped(reason) | Outcome::NotInstalled(reason) => return Ok(Some(reason)),
+                Outcome::Failed(e) => {
+                    debug!("Previewing step {:?} failed: {:?}", key, e);
+                    print_warning(t!("Could not preview {key}", key = key));
+                }
+                _ => (),
+            }
+        }
+
+        if prompt_yesno(&t!("Run {key}?", key = key))? {
+            println!();
+            Ok(None)
+        } else {
+            println!();
+            Ok(Some(t!("Declined").to_string()))
         }
     }
 
@@ -31,37 +484,104 @@ impl<'a> Runner<'a> {
             return Ok(());
         }
 
+        // Steps queued by `execute_parallel` must not overlap with this one.
+        self.run_queued()?;
+
+        // The second Ctrl+C may come in between steps.
+        if self.ctx.config().ctrl_c_skips_step() && ctrlc::aborted() {
+            return Err(abort_error());
+        }
+
         let key = key.into();
+        if self.too_many_failures() {
+            self.skip_after_failures(key);
+            return Ok(());
+        }
         debug!("Step {:?}", key);
 
-        // alter the `func` to put it in a span
-        let func = || {
-            let span =
-                tracing::span!(parent: tracing::Span::none(), tracing::Level::TRACE, "step", step = ?step, key = %key);
-            let _guard = span.enter();
-            func()
-        };
+        set_progress(self.progress);
+        if self.ctx.config().confirm() {
+            if let Some(reason) = self.preview(step, &key, &func)? {
+                self.skipped += 1;
+                if self.report_skipped() {
+                    self.report
+                        .push_result(key, StepResult::Skipped(reason), Duration::ZERO, None, 0, None);
+                }
+                set_progress(None);
+                return Ok(());
+            }
+        }
 
-        loop {
-            match func() {
-                Ok(()) => {
-                    self.report.push_result(Some((key, StepResult::Success)));
-                    break;
+        if let Some(command) = self.ctx.config().pre_step_command(step) {
+            if let Err(e) = run_step_command(self.ctx, &t!("Before {key}", key = key), command) {
+                print_warning(t!("The command to run before {key} failed, skipping it", key = key));
+                self.skipped += 1;
+                if self.report_skipped() {
+                    self.report.push_result(
+                        key,
+                        StepResult::Skipped(format!("{e:#}")),
+                        Duration::ZERO,
+                        None,
+                        0,
+                        None,
+                    );
                 }
-                Err(e) if e.downcast_ref::<DryRun>().is_some() => break,
-                Err(e) if e.downcast_ref::<SkipStep>().is_some() => {
-                    if self.ctx.config().verbose() || self.ctx.config().show_skipped() {
-                        self.report.push_result(Some((key, StepResult::Skipped(e.to_string()))));
-                    }
-                    break;
+                set_progress(None);
+                return Ok(());
+            }
+        }
+
+        let mut attempts = 0;
+        let mut updated_packages = None;
+        let result = loop {
+            let (outcome, duration) = if self.ctx.config().quiet(step) {
+                let ((outcome, duration), output) =
+                    capture_output(|| run_with_retries(self.ctx, step, &key, &func, 0, &mut attempts));
+                if matches!(outcome, Outcome::Failed(_) | Outcome::TimedOut) {
+                    print_captured(&key, &output, self.ctx.config().prefix_step_output());
+                }
+                (outcome, duration)
+            } else {
+                run_with_retries(self.ctx, step, &key, &func, 0, &mut attempts)
+            };
+
+            match outcome {
+                Outcome::TimedOut => break Some((StepResult::TimedOut, duration, None)),
+                Outcome::Success(updated) => {
+                    updated_packages = updated;
+                    break Some((StepResult::Success, duration, Some(0)));
                 }
-                Err(e) => {
+                Outcome::DryRun => break None,
+                Outcome::Skipped(reason) => {
+                    self.skipped += 1;
+                    break self
+                        .report_skipped()
+                        .then_some((StepResult::Skipped(reason), duration, None));
+                }
+                Outcome::NotInstalled(reason) => {
+                    self.skipped += 1;
+                    break self
+                        .report_skipped()
+                        .then_some((StepResult::NotInstalled(reason), duration, None));
+                }
+                Outcome::Failed(e) => {
                     debug!("Step {:?} failed: {:?}", key, e);
                     let interrupted = ctrlc::interrupted();
                     if interrupted {
                         ctrlc::unset_interrupted();
                     }
 
+                    if interrupted && self.ctx.config().ctrl_c_skips_step() {
+                        if ctrlc::aborted() {
+                            return Err(abort_error());
+                        }
+                        print_warning(t!(
+                            "{key} was interrupted, press Ctrl+C twice to abort the run",
+                            key = key
+                        ));
+                        break Some((StepResult::Skipped(t!("Interrupted").into_owned()), duration, None));
+                    }
+
                     let ignore_failure = self.ctx.config().ignore_failure(step);
                     let should_ask = interrupted || !(self.ctx.config().no_retry() || ignore_failure);
                     let should_retry = if should_ask {
@@ -72,18 +592,314 @@ impl<'a> Runner<'a> {
                     };
 
                     if !should_retry {
-                        self.report.push_result(Some((
-                            key,
-                            if ignore_failure {
-                                StepResult::Ignored
-                            } else {
-                                StepResult::Failure
-                            },
-                        )));
-                        break;
+                        let result = if ignore_failure {
+                            StepResult::Ignored
+                        } else {
+                            StepResult::Failure(format!("{e:#}"))
+                        };
+                        break Some((result, duration, exit_code(&e)));
+                    }
+                }
+            }
+        };
+
+        // The command to run after the step undoes what the one before it did, so it runs
+        // however the step went.
+        let mut result = result;
+        if let Some(command) = self.ctx.config().post_step_command(step) {
+            if let Err(e) = run_step_command(self.ctx, &t!("After {key}", key = key), command) {
+                print_error(&key, format!("{e:?}"));
+                let duration = result.map_or(Duration::ZERO, |(_, duration, _)| duration);
+                result = Some((StepResult::Failure(format!("{e:#}")), duration, exit_code(&e)));
+            }
+        }
+
+        if let Some(command) = self.ctx.config().step_healthcheck(step) {
+            if let Some((StepResult::Success, duration, _)) = result {
+                set_step_env(self.ctx.config().step_env(step));
+                let healthcheck = run_step_command(self.ctx, &t!("Healthcheck of {key}", key = key), command);
+                set_step_env(Vec::new());
+                if let Err(e) = healthcheck {
+                    print_error(&key, format!("{e:?}"));
+                    let reason = t!("The healthcheck failed: {error}", error = format!("{e:#}"));
+                    result = Some((StepResult::Failure(reason.into_owned()), duration, exit_code(&e)));
+                    if self.ctx.config().healthcheck_abort() {
+                        print_warning(t!(
+                            "The healthcheck of {key} failed, skipping the remaining steps",
+                            key = key
+                        ));
+                        self.aborted = Some(t!("A healthcheck failed").to_string());
+                    }
+                }
+            }
+        }
+
+        if let Some((result, duration, exit_code)) = result {
+            // A healthcheck may have failed the step after all.
+            let updated_packages = updated_packages.filter(|_| matches!(result, StepResult::Success));
+            self.report
+                .push_result(key, result, duration, exit_code, attempts, updated_packages);
+        }
+        set_progress(None);
+
+        Ok(())
+    }
+
+    /// Like `execute`, but the step may run alongside other steps queued this way when
+    /// `--jobs` is greater than 1. Only use this for steps that never need `sudo` or user input.
+    ///
+    /// Queued steps run once a step added with `execute` comes up, or when `run_queued` is
+    /// called. Their output is printed all at once when they finish, and failed steps are not
+    /// retried.
+    pub fn execute_parallel<F, M>(&mut self, step: Step, key: M, func: F) -> Result<()>
+    where
+        F: Fn() -> Result<()> + Send + Sync + 'a,
+        M: Into<Cow<'a, str>> + Debug,
+    {
+        let config = self.ctx.config();
+        if config.jobs() <= 1
+            || self.ctx.run_type().dry()
+            || config.confirm()
+            || config.pre_step_command(step).is_some()
+            || config.post_step_command(step).is_some()
+            || config.step_healthcheck(step).is_some()
+        {
+            return self.execute(step, key, func);
+        }
+
+        if !self.ctx.config().should_run(step) {
+            return Ok(());
+        }
+
+        if self.too_many_failures() {
+            self.skip_after_failures(key.into());
+            return Ok(());
+        }
+
+        self.queued.push(QueuedStep {
+            step,
+            key: key.into(),
+            func: Box::new(func),
+            progress: self.progress,
+        });
+
+        Ok(())
+    }
+
+    /// Run `steps`, starting with the ones listed in `step_order`. With `jitter`, the other
+    /// steps are shuffled and the run starts after a random delay.
+    pub fn run_steps(&mut self, mut steps: Steps<'a>) -> Result<()> {
+        let last = parse_steps(self.ctx.config().run_last(), "run_last");
+        let mut order = parse_steps(self.ctx.config().step_order(), "step_order");
+        order.retain(|step| {
+            let conflict = last.contains(step);
+            if conflict {
+                print_warning(t!(
+                    "Step {step} is in both step_order and run_last, it runs last",
+                    step = step.as_ref()
+                ));
+            }
+            !conflict
+        });
+        steps.reorder(&order);
+
+        if self.ctx.config().jitter() {
+            steps.shuffle(&order);
+
+            let max_delay = self.ctx.config().jitter_max_delay().as_millis() as u64;
+            if max_delay > 0 && !self.ctx.run_type().dry() {
+                let delay = Duration::from_millis(random_below(max_delay + 1));
+                print_info(t!(
+                    "Waiting {seconds} seconds before starting",
+                    seconds = delay.as_secs()
+                ));
+                thread::sleep(delay);
+            }
+        }
+        steps.move_to_end(&last);
+
+        let config = self.ctx.config();
+        if let Some(from) = config.start_step() {
+            match steps
+                .steps
+                .iter()
+                .position(|(step, ..)| *step == from && config.should_run(*step))
+            {
+                Some(position) => {
+                    steps.steps.drain(..position);
+                }
+                None => print_warning(t!(
+                    "Step {step} does not run, ignoring --from-step",
+                    step = from.as_ref()
+                )),
+            }
+        }
+        if !self.succeeded_before.is_empty() {
+            let before = steps.steps.len();
+            steps.steps.retain(|(step, key, _)| {
+                !(config.should_run(*step) && self.succeeded_before.iter().any(|succeeded| succeeded == key))
+            });
+            let count = before - steps.steps.len();
+            if count > 0 {
+                print_info(t!(
+                    "Skipping {count} steps that succeeded in the last run",
+                    count = count
+                ));
+            }
+        }
+
+        // There is no telling in advance which steps will be skipped, for instance because the
+        // tool they update isn't installed, so they are left out of the count as they happen.
+        let total = steps
+            .steps
+            .iter()
+            .filter(|(step, ..)| self.ctx.config().should_run(*step))
+            .count();
+        let skipped_before = self.skipped;
+        let mut position = 0;
+        for (step, key, func) in steps.steps {
+            if self.ctx.config().should_run(step) {
+                position += 1;
+                let skipped = self.skipped - skipped_before;
+                self.progress = Some((position - skipped, total - skipped));
+            }
+            match func {
+                StepFunc::Serial(func) => self.execute(step, key, func)?,
+                StepFunc::Parallel(func) => self.execute_parallel(step, key, func)?,
+            }
+        }
+        self.progress = None;
+
+        self.run_queued()?;
+        Ok(())
+    }
+
+    /// Run the steps queued by `execute_parallel`, up to `--jobs` at a time.
+    ///
+    /// Results are reported in the order the steps were queued in. Ctrl+C reaches every step
+    /// that was running, so it aborts the run and the steps that haven't started are skipped,
+    /// unless `ctrl_c_skips_step` is enabled, in which case only the interrupted steps are.
+    pub fn run_queued(&mut self) -> Result<()> {
+        if self.queued.is_empty() {
+            return Ok(());
+        }
+
+        let queued = mem::take(&mut self.queued);
+        let ctx = self.ctx;
+        let next = AtomicUsize::new(0);
+        let finished = Mutex::new(Vec::with_capacity(queued.len()));
+        let stop_on_interrupt = !ctx.config().ctrl_c_skips_step();
+        // Failed steps, including the ones that ran before, counted as they fail, see `max_failures`.
+        let max_failures = ctx.config().max_failures();
+        let failures = AtomicUsize::new(self.report.data().iter().filter(|step| step.result.failed()).count());
+
+        thread::scope(|scope| {
+            for _ in 0..ctx.config().jobs().min(queued.len()) {
+                scope.spawn(|| {
+                    while let Some((index, queued)) = {
+                        if (stop_on_interrupt && ctrlc::interrupted())
+                            || (max_failures > 0 && failures.load(Ordering::SeqCst) >= max_failures)
+                        {
+                            None
+                        } else {
+                            let index = next.fetch_add(1, Ordering::SeqCst);
+                            queued.get(index).map(|queued| (index, queued))
+                        }
+                    } {
+                        debug!("Step {:?}", queued.key);
+                        // `0` is used by steps running on the main thread.
+                        let id = index + 1;
+                        set_current_step(id);
+                        set_progress(queued.progress);
+                        let mut attempts = 0;
+                        let ((outcome, duration), output) = capture_output(|| {
+                            let (outcome, duration) =
+                                run_with_retries(ctx, queued.step, &queued.key, &*queued.func, id, &mut attempts);
+                            if let Outcome::Failed(e) = &outcome {
+                                debug!("Step {:?} failed: {:?}", queued.key, e);
+                                print_error(&queued.key, format!("{e:?}\n"));
+                            }
+                            (outcome, duration)
+                        });
+                        let failed = matches!(outcome, Outcome::Failed(_) | Outcome::TimedOut);
+                        if matches!(outcome, Outcome::TimedOut)
+                            || (matches!(outcome, Outcome::Failed(_)) && !ctx.config().ignore_failure(queued.step))
+                        {
+                            failures.fetch_add(1, Ordering::SeqCst);
+                        }
+                        if failed || !ctx.config().quiet(queued.step) {
+                            print_captured(&queued.key, &output, ctx.config().prefix_step_output());
+                        }
+                        finished.lock().unwrap().push((index, outcome, duration, attempts));
                     }
+                });
+            }
+        });
+
+        let mut results: Vec<Option<(Outcome, Duration, u32)>> = queued.iter().map(|_| None).collect();
+        for (index, outcome, duration, attempts) in finished.into_inner().unwrap() {
+            results[index] = Some((outcome, duration, attempts));
+        }
+        let report_skipped = self.report_skipped();
+        let interrupted = ctrlc::interrupted();
+        let skip_interrupted = interrupted && self.ctx.config().ctrl_c_skips_step();
+        for (result, queued) in results.into_iter().zip(queued) {
+            let Some((outcome, duration, attempts)) = result else {
+                // The step never started, because of `max_failures` or because the run was
+                // interrupted.
+                if !(stop_on_interrupt && interrupted) {
+                    self.too_many_failures();
+                    self.skip_after_failures(queued.key);
+                    continue;
+                }
+                self.skipped += 1;
+                self.report.push_result(
+                    queued.key,
+                    StepResult::Skipped(t!("Interrupted").into_owned()),
+                    Duration::ZERO,
+                    None,
+                    0,
+                    None,
+                );
+                continue;
+            };
+            let outcome = match outcome {
+                Outcome::Failed(_) if skip_interrupted => Outcome::Skipped(t!("Interrupted").into_owned()),
+                outcome => outcome,
+            };
+            if let Outcome::Skipped(_) | Outcome::NotInstalled(_) = outcome {
+                self.skipped += 1;
+            }
+            let updated_packages = match outcome {
+                Outcome::Success(updated) => updated,
+                _ => None,
+            };
+            let (result, exit_code) = match outcome {
+                Outcome::Success(_) => (StepResult::Success, Some(0)),
+                Outcome::TimedOut => (StepResult::TimedOut, None),
+                Outcome::Skipped(reason) if report_skipped => (StepResult::Skipped(reason), None),
+                Outcome::NotInstalled(reason) if report_skipped => (StepResult::NotInstalled(reason), None),
+                Outcome::Skipped(_) | Outcome::NotInstalled(_) | Outcome::DryRun => continue,
+                Outcome::Failed(e) if self.ctx.config().ignore_failure(queued.step) => {
+                    (StepResult::Ignored, exit_code(&e))
                 }
+                Outcome::Failed(e) => (StepResult::Failure(format!("{e:#}")), exit_code(&e)),
+            };
+            self.report
+                .push_result(queued.key, result, duration, exit_code, attempts, updated_packages);
+        }
+
+        if interrupted {
+            ctrlc::unset_interrupted();
+            if !skip_interrupted {
+                return Err(io::Error::from(io::ErrorKind::Interrupted).into());
+            }
+            if ctrlc::aborted() {
+                return Err(abort_error());
             }
+            print_warning(t!(
+                "Steps running in parallel were interrupted, press Ctrl+C twice to abort the run"
+            ));
         }
 
         Ok(())
@@ -93,3 +909,36 @@ impl<'a> Runner<'a> {
         &self.report
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::CommandExt;
+    use crate::executor::RunType;
+    use std::process::Command;
+
+    #[test]
+    fn test_step_timeout_kills_children() {
+        let config = Config::from_toml("[misc]\ntimeout = 1\n");
+        let ctx = ExecutionContext::new(RunType::Wet, None, &config);
+
+        // The shell leaves `sleep` behind holding the output pipe, unless its whole process
+        // group gets killed.
+        let started = Instant::now();
+        let (outcome, _) = run_once(
+            &ctx,
+            Step::Shell,
+            "sleep",
+            &|| {
+                Command::new("sh")
+                    .args(["-c", "sleep 30; true"])
+                    .output_checked()
+                    .map(|_| ())
+            },
+            0,
+        );
+
+        assert!(matches!(outcome, Outcome::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -4,18 +4,24 @@ use std::os::unix::process::CommandExt as _;
 use std::process::Command;
 
 use crate::config::Step;
-use color_eyre::eyre::{bail, Result};
+use color_eyre::eyre::{bail, eyre, Result};
 use rust_i18n::t;
-use self_update_crate::backends::github::Update;
-use self_update_crate::update::UpdateStatus;
+use self_update_crate::backends::github::{ReleaseList, Update, UpdateBuilder};
+use self_update_crate::update::{Release, UpdateStatus};
+use semver::Version;
 
-use super::terminal::{print_info, print_separator};
+use super::terminal::{print_info, print_separator, print_warning};
+#[cfg(unix)]
+use crate::error::RespawnFailed;
 #[cfg(windows)]
 use crate::error::Upgraded;
+#[cfg(unix)]
+use color_eyre::eyre::WrapErr;
 
 use crate::execution_context::ExecutionContext;
 
 pub fn self_update(ctx: &ExecutionContext) -> Result<()> {
+    ctx.require_online()?;
     print_separator(t!("Self update"));
 
     if ctx.run_type().dry() {
@@ -25,15 +31,15 @@ pub fn self_update(ctx: &ExecutionContext) -> Result<()> {
         let assume_yes = ctx.config().yes(Step::SelfUpdate);
         let current_exe = env::current_exe();
 
-        let target = self_update_crate::get_target();
-        let result = Update::configure()
-            .repo_owner("topgrade-rs")
-            .repo_name("topgrade")
-            .target(target)
-            .bin_name(if cfg!(windows) { "topgrade.exe" } else { "topgrade" })
-            .show_output(true)
-            .show_download_progress(true)
-            .current_version(self_update_crate::cargo_crate_version!())
+        let release = latest_release(ctx)?;
+        if !self_update_crate::version::bump_is_greater(self_update_crate::cargo_crate_version!(), &release.version)? {
+            println!("{}", t!("Topgrade is up-to-date"));
+            return Ok(());
+        }
+        print_info(t!("Updating Topgrade to {version}", version = release.version));
+
+        let result = configure()
+            .target_version_tag(&format!("v{}", release.version))
             .no_confirm(assume_yes)
             .build()?
             .update_extended()?;
@@ -56,7 +62,7 @@ pub fn self_update(ctx: &ExecutionContext) -> Result<()> {
                 #[cfg(unix)]
                 {
                     let err = command.exec();
-                    bail!(err);
+                    return Err(err).wrap_err(RespawnFailed);
                 }
 
                 #[cfg(windows)]
@@ -71,3 +77,53 @@ pub fn self_update(ctx: &ExecutionContext) -> Result<()> {
         Ok(())
     }
 }
+
+/// The updater of the Topgrade executable, from the releases of topgrade-rs on GitHub.
+fn configure() -> UpdateBuilder {
+    let mut builder = Update::configure();
+    builder
+        .repo_owner("topgrade-rs")
+        .repo_name("topgrade")
+        .target(self_update_crate::get_target())
+        .bin_name(if cfg!(windows) { "topgrade.exe" } else { "topgrade" })
+        .show_output(true)
+        .show_download_progress(true)
+        .current_version(self_update_crate::cargo_crate_version!());
+    builder
+}
+
+/// The newest release of the `self_update_channel`, falling back to the stable channel when
+/// the channel is unknown or the prereleases can't be listed.
+fn latest_release(ctx: &ExecutionContext) -> Result<Release> {
+    match ctx.config().self_update_channel() {
+        None | Some("stable") => (),
+        Some("prerelease") => match latest_prerelease() {
+            Ok(release) => return Ok(release),
+            Err(e) => print_warning(t!(
+                "Failed to look for prereleases, using the stable channel: {error}",
+                error = e
+            )),
+        },
+        Some(channel) => print_warning(t!(
+            "Unknown self_update_channel {channel}, using the stable channel",
+            channel = channel
+        )),
+    }
+
+    Ok(configure().build()?.get_latest_release()?)
+}
+
+/// The newest release, prereleases included. The latest release GitHub reports never is one.
+fn latest_prerelease() -> Result<Release> {
+    ReleaseList::configure()
+        .repo_owner("topgrade-rs")
+        .repo_name("topgrade")
+        .with_target(self_update_crate::get_target())
+        .build()?
+        .fetch()?
+        .into_iter()
+        .filter_map(|release| Some((Version::parse(&release.version).ok()?, release)))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, release)| release)
+        .ok_or_else(|| eyre!("No release found"))
+}
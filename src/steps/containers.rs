@@ -1,5 +1,5 @@
 use std::fmt::{Display, Formatter};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use color_eyre::eyre::eyre;
@@ -9,9 +9,11 @@ use tracing::{debug, error, warn};
 use wildmatch::WildMatch;
 
 use crate::command::CommandExt;
+use crate::config::{ContainerRuntime, Step};
 use crate::error::{self, TopgradeError};
-use crate::terminal::print_separator;
-use crate::{execution_context::ExecutionContext, utils::require};
+use crate::execution_context::ExecutionContext;
+use crate::terminal::{is_interactive, print_separator, prompt_yesno};
+use crate::utils::{require, require_one};
 use rust_i18n::t;
 
 // A string found in the output of docker for containers that weren't found in
@@ -21,6 +23,10 @@ use rust_i18n::t;
 // themselves or when using docker-compose.
 const NONEXISTENT_REPO: &str = "repository does not exist";
 
+// What podman says instead of `NONEXISTENT_REPO`, as registries answer the same way for
+// images that don't exist and for images that may not be pulled.
+const PODMAN_NONEXISTENT_REPO: &str = "requested access to the resource is denied";
+
 /// Uniquely identifies a `Container`.
 #[derive(Debug)]
 struct Container {
@@ -128,11 +134,36 @@ fn list_containers(crt: &Path, ignored_containers: Option<&Vec<String>>) -> Resu
     Ok(retval)
 }
 
+/// Find the container runtime to use, along with whether it is podman. This is also the case
+/// when `docker` is podman's Docker compatible wrapper.
+fn container_runtime(ctx: &ExecutionContext) -> Result<(PathBuf, ContainerRuntime)> {
+    let crt = match ctx.config().containers_runtime() {
+        ContainerRuntime::Auto => require_one(["docker", "podman"])?,
+        runtime => require(runtime.to_string())?,
+    };
+
+    let is_podman = crt.file_stem().is_some_and(|stem| stem == "podman")
+        || Command::new(&crt)
+            .arg("--version")
+            .output_checked_utf8()
+            .is_ok_and(|output| output.stdout.to_lowercase().contains("podman"));
+
+    let runtime = if is_podman {
+        ContainerRuntime::Podman
+    } else {
+        ContainerRuntime::Docker
+    };
+    Ok((crt, runtime))
+}
+
 pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
-    // Check what runtime is specified in the config
-    let container_runtime = ctx.config().containers_runtime().to_string();
-    let crt = require(container_runtime)?;
-    debug!("Using container runtime '{}'", crt.display());
+    let (crt, runtime) = container_runtime(ctx)?;
+    ctx.require_online()?;
+    debug!("Using container runtime '{}' ({})", crt.display(), runtime);
+    let nonexistent_repo = match runtime {
+        ContainerRuntime::Podman => PODMAN_NONEXISTENT_REPO,
+        _ => NONEXISTENT_REPO,
+    };
 
     print_separator(t!("Containers"));
     let mut success = true;
@@ -160,9 +191,9 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
             // docker-compose or pulled from the docker hub, look exactly the same to us. We can
             // only find out what went wrong by manually parsing the output of the command...
             if match exec.output_checked_utf8() {
-                Ok(s) => s.stdout.contains(NONEXISTENT_REPO) || s.stderr.contains(NONEXISTENT_REPO),
+                Ok(s) => s.stdout.contains(nonexistent_repo) || s.stderr.contains(nonexistent_repo),
                 Err(e) => match e.downcast_ref::<TopgradeError>() {
-                    Some(TopgradeError::ProcessFailedWithOutput(_, _, stderr)) => stderr.contains(NONEXISTENT_REPO),
+                    Some(TopgradeError::ProcessFailedWithOutput(_, _, stderr)) => stderr.contains(nonexistent_repo),
                     _ => false,
                 },
             } {
@@ -188,9 +219,56 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
+    if ctx.config().containers_cleanup() {
+        if let Err(e) = prune_system(ctx, &crt) {
+            error!("Pruning the container system failed: {}", e);
+            success = false;
+        }
+    }
+
     if success {
         Ok(())
     } else {
         Err(eyre!(error::StepFailed))
     }
 }
+
+/// Remove stopped containers, unused networks, build cache and dangling volumes, and print
+/// how much space this reclaimed.
+fn prune_system(ctx: &ExecutionContext, crt: &Path) -> Result<()> {
+    if ctx.run_type().dry() {
+        ctx.run_type()
+            .execute(crt)
+            .args(["system", "prune", "--force", "--volumes"])
+            .status_checked()?;
+        return Ok(());
+    }
+
+    if is_interactive()
+        && !ctx.config().yes(Step::Containers)
+        && !prompt_yesno(&t!(
+            "Remove stopped containers, unused networks, build cache and dangling volumes?"
+        ))?
+    {
+        return Ok(());
+    }
+
+    debug!("Pruning the container system");
+    let output = ctx
+        .run_type()
+        .execute(crt)
+        .args(["system", "prune", "--force", "--volumes"])
+        .output_checked_utf8()?;
+    debug!("`system prune` output: {output:?}");
+
+    // Both docker and podman end with `Total reclaimed space: 1.2GB`.
+    if let Some(reclaimed) = output
+        .stdout
+        .lines()
+        .find(|line| line.starts_with("Total reclaimed space"))
+    {
+        println!("{reclaimed}");
+    }
+
+    Ok(())
+}
@@ -1,10 +1,13 @@
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
 use color_eyre::eyre::{eyre, OptionExt};
+use console::style;
+use etcetera::base_strategy::BaseStrategy;
 use jetbrains_toolbox_updater::{find_jetbrains_toolbox, update_jetbrains_toolbox, FindError};
 use regex::bytes::Regex;
 use rust_i18n::t;
 use semver::Version;
+use serde::Deserialize;
 use std::ffi::OsString;
 use std::path::PathBuf;
 use std::process::Command;
@@ -15,9 +18,11 @@ use tempfile::tempfile_in;
 use tracing::{debug, error, warn};
 
 use crate::command::{CommandExt, Utf8Output};
+use crate::config::{CondaEnvironments, CustomCommand, CustomPackageManager};
 use crate::execution_context::ExecutionContext;
 use crate::executor::ExecutorOutput;
-use crate::terminal::{print_separator, shell};
+use crate::manifests::Manifests;
+use crate::terminal::{print_info, print_line, print_separator, shell};
 use crate::utils::{
     check_is_python_2_or_shim, get_require_sudo_string, require, require_one, require_option, which, PathExt,
 };
@@ -40,6 +45,32 @@ pub fn is_wsl() -> Result<bool> {
     Ok(false)
 }
 
+/// Names of the crates in `.crates.toml` that were installed from a registry, and of the ones
+/// installed from git.
+///
+/// Its entries look like `"ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["rg"]`.
+fn installed_crates(crates_toml: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let contents = fs::read_to_string(crates_toml)?;
+    let crates: toml::Table =
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", crates_toml.display()))?;
+
+    let names = |sources: &[&str]| -> Vec<String> {
+        crates
+            .get("v1")
+            .and_then(|v1| v1.as_table())
+            .map(|v1| {
+                v1.keys()
+                    .filter(|key| sources.iter().any(|source| key.contains(source)))
+                    .filter_map(|key| key.split(' ').next())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    Ok((names(&["(registry+", "(sparse+"]), names(&["(git+"])))
+}
+
 pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     let cargo_dir = env::var_os("CARGO_HOME")
         .map_or_else(|| HOME_DIR.join(".cargo"), PathBuf::from)
@@ -57,21 +88,72 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep(format!("{} exists but empty", &toml_file.display())).into());
     }
 
+    let manifests = Manifests::check(
+        ctx,
+        Step::Cargo,
+        vec![toml_file.clone(), cargo_dir.join(".crates2.json")],
+    )?;
+
     print_separator("Cargo");
     let cargo_update = require("cargo-install-update")
         .ok()
         .or_else(|| cargo_dir.join("bin/cargo-install-update").if_exists());
+    let cargo_binstall = if ctx.config().cargo_use_binstall() {
+        require("cargo-binstall")
+            .ok()
+            .or_else(|| cargo_dir.join("bin/cargo-binstall").if_exists())
+    } else {
+        None
+    };
 
-    let Some(cargo_update) = cargo_update else {
+    if cargo_update.is_none() && cargo_binstall.is_none() {
         let message = String::from("cargo-update isn't installed so Topgrade can't upgrade cargo packages.\nInstall cargo-update by running `cargo install cargo-update`");
         print_warning(&message);
         return Err(SkipStep(message).into());
-    };
+    }
 
-    ctx.run_type()
-        .execute(cargo_update)
-        .args(["install-update", "--git", "--all"])
-        .status_checked()?;
+    // cargo-binstall fetches prebuilt binaries where it can, and compiles the
+    // other crates from source itself. It only handles crates coming from a
+    // registry though, so cargo-update still takes care of the ones installed
+    // from git, and of the registry ones if cargo-binstall failed.
+    if let Some(cargo_binstall) = cargo_binstall {
+        let (registry, mut git) = installed_crates(&toml_file)?;
+        let binstalled = if registry.is_empty() {
+            Ok(())
+        } else {
+            ctx.run_type()
+                .execute(cargo_binstall)
+                .args(["binstall", "--no-confirm"])
+                .args(&registry)
+                .status_checked()
+        };
+
+        match (binstalled, cargo_update) {
+            (Err(e), None) => return Err(e),
+            (binstalled, Some(cargo_update)) => {
+                if let Err(e) = binstalled {
+                    print_warning(t!(
+                        "cargo-binstall failed, updating the crates with cargo-update instead: {error}",
+                        error = format!("{e:#}")
+                    ));
+                    git.extend(registry);
+                }
+                if !git.is_empty() {
+                    ctx.run_type()
+                        .execute(cargo_update)
+                        .args(["install-update", "--git"])
+                        .args(git)
+                        .status_checked()?;
+                }
+            }
+            (Ok(()), None) => (),
+        }
+    } else if let Some(cargo_update) = cargo_update {
+        ctx.run_type()
+            .execute(cargo_update)
+            .args(["install-update", "--git", "--all"])
+            .status_checked()?;
+    }
 
     if ctx.config().cleanup() {
         let cargo_cache = require("cargo-cache")
@@ -85,7 +167,7 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
-    Ok(())
+    manifests.remember()
 }
 
 pub fn run_flutter_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -112,6 +194,87 @@ pub fn run_gem(ctx: &ExecutionContext) -> Result<()> {
     command.status_checked()
 }
 
+/// Update the dependencies of the Ruby project in the current directory, see `ruby_update_bundle`.
+pub fn run_bundle_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().ruby_update_bundle() {
+        return Err(SkipStep(t!("Updating the Gemfile is not enabled").to_string()).into());
+    }
+    let dir = env::current_dir()?;
+    if !dir.join("Gemfile").is_file() {
+        return Err(SkipStep(t!("No Gemfile in {dir}", dir = dir.display()).to_string()).into());
+    }
+    let bundle = require("bundle")?;
+
+    print_separator(format!("Bundler ({})", dir.display()));
+
+    ctx.run_type().execute(bundle).arg("update").status_checked()
+}
+
+/// Upgrade the providers and modules of the Terraform configurations in `terraform_dirs`.
+pub fn run_terraform(ctx: &ExecutionContext) -> Result<()> {
+    let dirs = ctx
+        .config()
+        .terraform_dirs()
+        .ok_or_else(|| SkipStep(t!("No Terraform directories configured").to_string()))?;
+    let terraform = match ctx.config().terraform_binary() {
+        Some(binary) => require(binary)?,
+        None => require_one(["terraform", "tofu"])?,
+    };
+
+    let dirs: Vec<&Path> = dirs
+        .iter()
+        .map(Path::new)
+        .filter(|dir| {
+            let has_tf = fs::read_dir(dir).is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| entry.path().extension().is_some_and(|extension| extension == "tf"))
+            });
+            if !has_tf {
+                print_warning(t!("{dir} does not contain any .tf file", dir = dir.display()));
+            }
+            has_tf
+        })
+        .collect();
+    if dirs.is_empty() {
+        return Err(SkipStep(t!("None of the Terraform directories contains a .tf file").to_string()).into());
+    }
+
+    print_separator("Terraform");
+
+    let mut failed = Vec::new();
+    for dir in dirs {
+        let result = ctx
+            .run_type()
+            .execute(&terraform)
+            .current_dir(dir)
+            .args(["init", "-upgrade"])
+            .status_checked();
+
+        match result {
+            Ok(()) if ctx.run_type().dry() => (),
+            Ok(()) => println!("{} {}", style(t!("Updated")).green().bold(), dir.display()),
+            Err(e) => {
+                println!("{} {}", style(t!("Failed")).red().bold(), dir.display());
+                debug!(
+                    "Failed to upgrade the Terraform configuration in {}: {e:?}",
+                    dir.display()
+                );
+                failed.push(dir.display().to_string());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(t!(
+            "Failed to upgrade the Terraform configurations in {dirs}",
+            dirs = failed.join(", ")
+        )))
+    }
+}
+
 pub fn run_rubygems(ctx: &ExecutionContext) -> Result<()> {
     HOME_DIR.join(".gem").require()?;
     let gem = require("gem")?;
@@ -278,7 +441,86 @@ pub fn run_rustup(ctx: &ExecutionContext) -> Result<()> {
     let rustup = require("rustup")?;
 
     print_separator("rustup");
-    ctx.run_type().execute(rustup).arg("update").status_checked()
+
+    let mut self_update = ctx.config().rustup_self_update();
+    if self_update == Some(true) && !rustup_manages_itself(&rustup) {
+        print_warning(t!(
+            "{rustup} seems to be installed by a package manager, not running `rustup self update`",
+            rustup = rustup.display()
+        ));
+        self_update = Some(false);
+    }
+    if self_update == Some(true) {
+        ctx.run_type()
+            .execute(&rustup)
+            .args(["self", "update"])
+            .status_checked()?;
+    }
+    // When `self_update` is set, `rustup update` must not decide on its own.
+    let no_self_update = self_update.is_some().then_some("--no-self-update");
+
+    let Some(toolchains) = ctx.config().rustup_toolchains() else {
+        return ctx
+            .run_type()
+            .execute(rustup)
+            .arg("update")
+            .args(no_self_update)
+            .status_checked();
+    };
+
+    // Lines look like `nightly-x86_64-unknown-linux-gnu (default)`.
+    let installed = Command::new(&rustup)
+        .args(["toolchain", "list"])
+        .output_checked_utf8()?
+        .stdout;
+    let toolchains: Vec<&String> = toolchains
+        .iter()
+        .filter(|toolchain| {
+            let is_installed = installed
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .any(|name| name == toolchain.as_str() || name.starts_with(&format!("{toolchain}-")));
+            if !is_installed {
+                print_warning(t!("Toolchain {toolchain} is not installed", toolchain = toolchain));
+            }
+            is_installed
+        })
+        .collect();
+
+    if toolchains.is_empty() {
+        return Err(SkipStep(t!("None of the configured toolchains is installed").to_string()).into());
+    }
+
+    ctx.run_type()
+        .execute(&rustup)
+        .arg("update")
+        .args(no_self_update)
+        .args(&toolchains)
+        .status_checked()?;
+
+    if let Some(components) = ctx.config().rustup_components() {
+        for toolchain in toolchains {
+            ctx.run_type()
+                .execute(&rustup)
+                .args(["component", "add", "--toolchain", toolchain])
+                .args(components)
+                .status_checked()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Tell whether `rustup` was installed by itself into `$CARGO_HOME/bin`, rather than by a
+/// package manager that updates it instead.
+fn rustup_manages_itself(rustup: &Path) -> bool {
+    let cargo_bin = env::var_os("CARGO_HOME")
+        .map_or_else(|| HOME_DIR.join(".cargo"), PathBuf::from)
+        .join("bin");
+    // `rustup` may be reached through a symlink, e.g. in `~/.local/bin`.
+    let rustup = fs::canonicalize(rustup).unwrap_or_else(|_| rustup.to_owned());
+    let cargo_bin = fs::canonicalize(&cargo_bin).unwrap_or(cargo_bin);
+    rustup.starts_with(cargo_bin)
 }
 
 pub fn run_rye(ctx: &ExecutionContext) -> Result<()> {
@@ -448,8 +690,16 @@ fn run_vscode_compatible<const VSCODIUM: bool>(ctx: &ExecutionContext) -> Result
     }
 
     let name = if VSCODIUM { "VSCodium" } else { "VSCode" };
-    let bin_name = if VSCODIUM { "codium" } else { "code" };
-    let bin = require(bin_name)?;
+    // Fall back to the Insiders build when the stable one isn't installed
+    let bin_names = if VSCODIUM {
+        ["codium", "codium-insiders"]
+    } else {
+        ["code", "code-insiders"]
+    };
+    let bin = require_one(bin_names)?;
+    let bin_name = bin
+        .file_stem()
+        .map_or(bin_names[0].into(), |stem| stem.to_string_lossy());
 
     // VSCode has update command only since 1.86 version ("january 2024" update), disable the update for prior versions
     // Use command `code --version` which returns 3 lines: version, git commit, instruction set. We parse only the first one
@@ -465,7 +715,9 @@ fn run_vscode_compatible<const VSCODIUM: bool>(ctx: &ExecutionContext) -> Result
         Some(item) => {
             // Strip leading zeroes because `semver` does not allow them, but VSCodium uses them sometimes.
             //  This is not the case for VSCode, but just in case, and it can't really cause any issues.
+            // Insiders builds report versions like `1.96.0-insider`.
             let item = item
+                .trim_end_matches("-insider")
                 .split('.')
                 .map(|s| if s == "0" { "0" } else { s.trim_start_matches('0') })
                 .collect::<Vec<_>>()
@@ -497,7 +749,7 @@ fn run_vscode_compatible<const VSCODIUM: bool>(ctx: &ExecutionContext) -> Result
         "Visual Studio Code extensions"
     });
 
-    let mut cmd = ctx.run_type().execute(bin);
+    let mut cmd = ctx.run_type().execute(&bin);
     // If its VSCode (not VSCodium)
     if !VSCODIUM {
         // And we have configured use of a profile
@@ -507,7 +759,20 @@ fn run_vscode_compatible<const VSCODIUM: bool>(ctx: &ExecutionContext) -> Result
         }
     }
 
-    cmd.arg("--update-extensions").status_checked()
+    cmd.arg("--update-extensions").status_checked()?;
+
+    if ctx.config().vscode_update_app() {
+        // Only the standalone CLI has an `update` subcommand, the desktop app updates itself
+        // (or is updated by the system package manager)
+        let help = Command::new(&bin).arg("--help").output_checked_utf8()?.stdout;
+        if help.lines().any(|line| line.trim_start().starts_with("update ")) {
+            ctx.run_type().execute(&bin).arg("update").status_checked()?;
+        } else {
+            print_info(t!("{name} has no update command, it updates itself", name = name));
+        }
+    }
+
+    Ok(())
 }
 
 /// Make VSCodium a separate step because:
@@ -526,15 +791,26 @@ pub fn run_pipx_update(ctx: &ExecutionContext) -> Result<()> {
     let pipx = require("pipx")?;
     print_separator("pipx");
 
-    let mut command_args = vec!["upgrade-all", "--include-injected"];
+    let mut command_args = vec!["upgrade-all"];
 
-    // pipx version 1.4.0 introduced a new command argument `pipx upgrade-all --quiet`
-    // (see https://pipx.pypa.io/stable/docs/#pipx-upgrade-all)
     let version_str = Command::new(&pipx)
         .args(["--version"])
         .output_checked_utf8()
-        .map(|s| s.stdout.trim().to_owned());
-    let version = Version::parse(&version_str?);
+        .map(|s| s.stdout.trim().to_owned())?;
+    // Versions before 1.0 have four components, e.g. `0.15.6.0`.
+    let version = Version::parse(&version_str.split('.').take(3).collect::<Vec<_>>().join("."));
+
+    // `--include-injected` appeared in pipx 0.15.0.0
+    if ctx.config().pipx_include_injected() {
+        if matches!(version, Ok(ref version) if version < &Version::new(0, 15, 0)) {
+            debug!("pipx {version_str} doesn't support --include-injected");
+        } else {
+            command_args.push("--include-injected");
+        }
+    }
+
+    // pipx version 1.4.0 introduced a new command argument `pipx upgrade-all --quiet`
+    // (see https://pipx.pypa.io/stable/docs/#pipx-upgrade-all)
     if matches!(version, Ok(version) if version >= Version::new(1, 4, 0)) {
         command_args.push("--quiet");
     }
@@ -555,22 +831,61 @@ pub fn run_pipxu_update(ctx: &ExecutionContext) -> Result<()> {
 pub fn run_conda_update(ctx: &ExecutionContext) -> Result<()> {
     let conda = require("conda")?;
 
-    let output = Command::new(&conda)
-        .args(["config", "--show", "auto_activate_base"])
-        .output_checked_utf8()?;
-    debug!("Conda output: {}", output.stdout);
-    if output.stdout.contains("False") {
-        return Err(SkipStep("auto_activate_base is set to False".to_string()).into());
-    }
+    // Each environment as the arguments selecting it, by name or by path.
+    let environments: Vec<[String; 2]> = match ctx.config().conda_environments() {
+        None => {
+            let output = Command::new(&conda)
+                .args(["config", "--show", "auto_activate_base"])
+                .output_checked_utf8()?;
+            debug!("Conda output: {}", output.stdout);
+            if output.stdout.contains("False") {
+                return Err(SkipStep("auto_activate_base is set to False".to_string()).into());
+            }
+            vec![["-n".into(), "base".into()]]
+        }
+        Some(CondaEnvironments::Named(names)) => names.iter().map(|name| ["-n".into(), name.clone()]).collect(),
+        Some(CondaEnvironments::All(_)) => {
+            // Environments outside of `envs_dirs` have no name, so we go by path.
+            let output = Command::new(&conda)
+                .args(["env", "list", "--json"])
+                .output_checked_utf8()?;
+            let list: CondaEnvList =
+                serde_json::from_str(&output.stdout).context("Failed to parse `conda env list`")?;
+            list.envs.into_iter().map(|path| ["-p".into(), path]).collect()
+        }
+    };
 
     print_separator("Conda");
 
-    let mut command = ctx.run_type().execute(&conda);
-    command.args(["update", "--all", "-n", "base"]);
-    if ctx.config().yes(Step::Conda) {
-        command.arg("--yes");
+    let mut failed = Vec::new();
+    for [flag, environment] in &environments {
+        let mut command = ctx.run_type().execute(&conda);
+        command.args(["update", "--all", flag, environment]);
+        if ctx.config().yes(Step::Conda) {
+            command.arg("--yes");
+        }
+        let result = command.status_checked();
+
+        // A single environment is reported like any other step.
+        if environments.len() == 1 {
+            result?;
+            continue;
+        }
+        match result {
+            Ok(()) => println!("{} {environment}", style(t!("Updated")).green().bold()),
+            Err(e) => {
+                println!("{} {environment}", style(t!("Failed")).red().bold());
+                debug!("Failed to update the conda environment {environment}: {e:?}");
+                failed.push(environment.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(eyre!(t!(
+            "Failed to update the conda environments {environments}",
+            environments = failed.join(", ")
+        )));
     }
-    command.status_checked()?;
 
     if ctx.config().cleanup() {
         let mut command = ctx.run_type().execute(conda);
@@ -584,6 +899,12 @@ pub fn run_conda_update(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// The output of `conda env list --json`.
+#[derive(Deserialize)]
+struct CondaEnvList {
+    envs: Vec<String>,
+}
+
 pub fn run_pixi_update(ctx: &ExecutionContext) -> Result<()> {
     let pixi = require("pixi")?;
     print_separator("Pixi");
@@ -747,6 +1068,118 @@ pub fn run_pip_review_update(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// A package `pip list --outdated --format=json` lists.
+#[derive(Deserialize)]
+struct OutdatedPackage {
+    name: String,
+}
+
+/// The Python interpreter of the virtual environment `dir`, if it is one.
+fn venv_python(dir: &Path) -> Option<PathBuf> {
+    if !dir.join("pyvenv.cfg").is_file() {
+        return None;
+    }
+
+    #[cfg(unix)]
+    let python = dir.join("bin").join("python");
+    #[cfg(windows)]
+    let python = dir.join("Scripts").join("python.exe");
+    python.if_exists()
+}
+
+/// Upgrade the packages of the virtual environment whose interpreter is `python`, and tell
+/// whether any was outdated.
+fn upgrade_venv(ctx: &ExecutionContext, venv: &Path, python: &Path) -> Result<bool> {
+    #[cfg(unix)]
+    let pip_review = venv.join("bin").join("pip-review");
+    #[cfg(windows)]
+    let pip_review = venv.join("Scripts").join("pip-review.exe");
+    if pip_review.exists() {
+        ctx.run_type()
+            .execute(python)
+            .args(["-m", "pip_review", "--local", "--auto"])
+            .status_checked_with_codes(&[1])?;
+        return Ok(true);
+    }
+
+    let output = Command::new(python)
+        .args(["-m", "pip", "list", "--outdated", "--local", "--format=json"])
+        .output_checked_utf8()?;
+    let outdated: Vec<OutdatedPackage> =
+        serde_json::from_str(&output.stdout).context("Failed to parse `pip list --outdated`")?;
+    if outdated.is_empty() {
+        return Ok(false);
+    }
+
+    ctx.run_type()
+        .execute(python)
+        .args(["-m", "pip", "install", "--upgrade"])
+        .args(outdated.iter().map(|package| &package.name))
+        .status_checked()?;
+    Ok(true)
+}
+
+pub fn run_python_venvs(ctx: &ExecutionContext) -> Result<()> {
+    let entries = ctx
+        .config()
+        .python_venvs()
+        .ok_or_else(|| SkipStep(t!("No virtual environments configured").to_string()))?;
+
+    let mut venvs = Vec::new();
+    for entry in entries {
+        let is_pattern = entry.contains(['*', '?', '[']);
+        let dirs: Vec<PathBuf> = if is_pattern {
+            match glob::glob(entry) {
+                Ok(paths) => paths.flatten().collect(),
+                Err(e) => {
+                    print_warning(t!("Invalid pattern {pattern}: {error}", pattern = entry, error = e));
+                    Vec::new()
+                }
+            }
+        } else {
+            vec![PathBuf::from(entry)]
+        };
+
+        for dir in dirs {
+            match venv_python(&dir) {
+                Some(python) => venvs.push((dir, python)),
+                // Patterns usually match other directories too.
+                None if is_pattern => debug!("{} is not a virtual environment", dir.display()),
+                None => print_warning(t!("{dir} is not a virtual environment", dir = dir.display())),
+            }
+        }
+    }
+    if venvs.is_empty() {
+        return Err(SkipStep(t!("None of python_venvs is a virtual environment").to_string()).into());
+    }
+
+    print_separator(t!("Python virtual environments"));
+
+    let mut failed = Vec::new();
+    for (venv, python) in &venvs {
+        print_info(venv.display().to_string());
+        match upgrade_venv(ctx, venv, python) {
+            Ok(_) if ctx.run_type().dry() => (),
+            Ok(true) => println!("{} {}", style(t!("Updated")).green().bold(), venv.display()),
+            Ok(false) => println!("{} {}", style(t!("Up-to-date")).green().bold(), venv.display()),
+            Err(e) => {
+                println!("{} {}", style(t!("Failed")).red().bold(), venv.display());
+                debug!("Failed to upgrade the packages of {}: {e:?}", venv.display());
+                failed.push(venv.display().to_string());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(t!(
+            "Failed to upgrade the packages of {venvs}",
+            venvs = failed.join(", ")
+        )))
+    }
+}
+
 pub fn run_pip_review_local_update(ctx: &ExecutionContext) -> Result<()> {
     let pip_review = require("pip-review")?;
 
@@ -877,9 +1310,33 @@ pub fn run_myrepos_update(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
-pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) -> Result<()> {
+pub fn run_custom_command(name: &str, command: &CustomCommand, ctx: &ExecutionContext) -> Result<()> {
+    if let Some(binary) = command.requires() {
+        require(binary)?;
+    }
+    let shell = command.shell().map_or_else(|| shell().to_owned(), String::from);
+    let cwd = command
+        .cwd()
+        .map(|cwd| shellexpand::full(cwd).with_context(|| format!("Failed to expand {cwd}")))
+        .transpose()?;
+    if let Some(run_if) = command.run_if() {
+        // This only decides whether to run the command, so it runs for real in dry runs too.
+        let mut check = Command::new(&shell);
+        if let Some(cwd) = &cwd {
+            check.current_dir(cwd.as_ref());
+        }
+        let output = check.arg("-c").arg(run_if).output_checked_with(|_| Ok(()))?;
+        if !output.status.success() {
+            return Err(SkipStep(t!("`{run_if}` failed", run_if = run_if).to_string()).into());
+        }
+    }
+
     print_separator(name);
-    let mut exec = ctx.run_type().execute(shell());
+    let mut exec = ctx.run_type().execute(&shell);
+    if let Some(cwd) = &cwd {
+        exec.current_dir(cwd.as_ref());
+    }
+    let command = command.command();
     #[cfg(unix)]
     let command = if let Some(command) = command.strip_prefix("-i ") {
         exec.arg("-i");
@@ -890,6 +1347,27 @@ pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) ->
     exec.arg("-c").arg(command).status_checked()
 }
 
+pub fn run_custom_package_manager(manager: &CustomPackageManager, ctx: &ExecutionContext) -> Result<()> {
+    let shell = manager.shell().map_or_else(|| shell().to_owned(), String::from);
+    // This only decides whether to run the update, so it runs for real in dry runs too.
+    let detected = Command::new(&shell)
+        .arg("-c")
+        .arg(manager.detect())
+        .output_checked_with(|_| Ok(()))?
+        .status
+        .success();
+    if !detected {
+        return Err(SkipStep(t!("`{detect}` failed", detect = manager.detect()).to_string()).into());
+    }
+
+    print_separator(manager.name());
+    ctx.run_type()
+        .execute(&shell)
+        .arg("-c")
+        .arg(manager.update())
+        .status_checked()
+}
+
 pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
     let composer = require("composer")?;
     let composer_home = Command::new(&composer)
@@ -1083,6 +1561,92 @@ pub fn bin_update(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(bin).arg("update").status_checked()
 }
 
+/// The eget configuration file, looked for where eget looks for it.
+fn eget_config() -> PathBuf {
+    if let Some(path) = env::var_os("EGET_CONFIG") {
+        return PathBuf::from(path);
+    }
+
+    let home_config = HOME_DIR.join(".eget.toml");
+    if home_config.exists() {
+        return home_config;
+    }
+
+    #[cfg(unix)]
+    let config_dir = crate::XDG_DIRS.config_dir();
+    #[cfg(windows)]
+    let config_dir = crate::WINDOWS_DIRS.cache_dir();
+    config_dir.join("eget").join("eget.toml")
+}
+
+/// Download the binaries of `repo` with eget if its latest release is newer than the one
+/// installed, and tell whether it was.
+fn eget_download(ctx: &ExecutionContext, eget: &Path, repo: &str) -> Result<bool> {
+    // The settings of the repository in the configuration file apply as well.
+    let ExecutorOutput::Wet(output) = ctx.run_type().execute(eget).args(["--upgrade-only", repo]).output()? else {
+        return Ok(false);
+    };
+    let output = Utf8Output::try_from(output)?;
+    if !output.status.success() {
+        return Err(eyre!("{}", output.stderr.trim()));
+    }
+
+    // eget reports the files it extracted, and nothing when the release is not newer.
+    Ok(output.stderr.contains("Extracted"))
+}
+
+pub fn run_eget(ctx: &ExecutionContext) -> Result<()> {
+    let eget = require("eget")?;
+    let config = eget_config().require()?;
+
+    // Every table but `global` is named after a repository to download binaries from.
+    let contents = fs::read_to_string(&config).with_context(|| format!("Failed to read {}", config.display()))?;
+    let table: toml::Table =
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", config.display()))?;
+    let repos: Vec<&String> = table
+        .iter()
+        .filter(|(name, value)| *name != "global" && value.is_table())
+        .map(|(name, _)| name)
+        .collect();
+    if repos.is_empty() {
+        return Err(SkipStep(t!("No repositories in {config}", config = config.display()).to_string()).into());
+    }
+
+    print_separator("eget");
+
+    let mut updated = 0;
+    let mut failed = Vec::new();
+    for repo in repos {
+        match eget_download(ctx, &eget, repo) {
+            Ok(_) if ctx.run_type().dry() => (),
+            Ok(true) => {
+                updated += 1;
+                print_line(format!("{} {}", style(t!("Updated")).green().bold(), repo));
+            }
+            Ok(false) => print_line(format!("{} {}", style(t!("Up-to-date")).green().bold(), repo)),
+            Err(e) => {
+                print_line(format!("{} {}", style(t!("Failed")).red().bold(), repo));
+                failed.push((repo.as_str(), e));
+            }
+        }
+    }
+    if !ctx.run_type().dry() {
+        ctx.record_updated_packages(updated);
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        let repos: Vec<&str> = failed.iter().map(|(repo, _)| *repo).collect();
+        let errors: Vec<String> = failed.iter().map(|(repo, e)| format!("{repo}: {e:#}")).collect();
+        Err(eyre!(
+            "{}\n{}",
+            t!("Failed to download the binaries of {repos}", repos = repos.join(", ")),
+            errors.join("\n")
+        ))
+    }
+}
+
 pub fn spicetify_upgrade(ctx: &ExecutionContext) -> Result<()> {
     // As of 04-07-2023 NixOS packages Spicetify with the `spicetify-cli` binary name
     let spicetify = require("spicetify").or(require("spicetify-cli"))?;
@@ -1129,7 +1693,7 @@ pub fn run_helm_repo_update(ctx: &ExecutionContext) -> Result<()> {
 
     let no_repo = "no repositories found";
     let mut success = true;
-    let mut exec = ctx.run_type().execute(helm);
+    let mut exec = ctx.run_type().execute(&helm);
     if let Err(e) = exec.arg("repo").arg("update").status_checked() {
         error!("Updating repositories failed: {e}");
         success = match exec.output_checked_utf8() {
@@ -1141,13 +1705,124 @@ pub fn run_helm_repo_update(ctx: &ExecutionContext) -> Result<()> {
         };
     }
 
-    if success {
+    if !success {
+        return Err(eyre!(StepFailed));
+    }
+
+    if ctx.config().helm_upgrade_releases() {
+        upgrade_helm_releases(ctx, &helm)?;
+    }
+
+    Ok(())
+}
+
+/// A release in the output of `helm list --output json`.
+#[derive(Deserialize)]
+struct HelmRelease {
+    name: String,
+    namespace: String,
+    /// The chart name and version, like `ingress-nginx-4.10.1`.
+    chart: String,
+}
+
+/// A chart in the output of `helm search repo --output json`.
+#[derive(Deserialize)]
+struct HelmChart {
+    /// The repository and chart name, like `ingress-nginx/ingress-nginx`.
+    name: String,
+    version: String,
+}
+
+/// Upgrade the releases whose chart has a newer version in the repositories.
+fn upgrade_helm_releases(ctx: &ExecutionContext, helm: &Path) -> Result<()> {
+    // Listing only reads, so it runs for real in dry runs too.
+    let mut releases: Vec<HelmRelease> = Vec::new();
+    match ctx.config().helm_namespaces() {
+        Some(namespaces) => {
+            for namespace in namespaces {
+                let output = Command::new(helm)
+                    .args(["list", "--output", "json", "--namespace", namespace])
+                    .output_checked_utf8()?;
+                releases.extend(serde_json::from_str::<Vec<_>>(&output.stdout).context("Failed to parse `helm list`")?);
+            }
+        }
+        None => {
+            let output = Command::new(helm)
+                .args(["list", "--output", "json", "--all-namespaces"])
+                .output_checked_utf8()?;
+            releases = serde_json::from_str(&output.stdout).context("Failed to parse `helm list`")?;
+        }
+    }
+    if releases.is_empty() {
+        return Ok(());
+    }
+
+    let output = Command::new(helm)
+        .args(["search", "repo", "--output", "json"])
+        .output_checked_utf8()?;
+    let charts: Vec<HelmChart> = serde_json::from_str(&output.stdout).context("Failed to parse `helm search repo`")?;
+
+    let mut failed = Vec::new();
+    for release in &releases {
+        let Some((chart, version)) = newer_helm_chart(release, &charts) else {
+            continue;
+        };
+
+        let result = ctx
+            .run_type()
+            .execute(helm)
+            .args(["upgrade", &release.name, &chart.name, "--namespace", &release.namespace])
+            .args(["--version", &chart.version, "--reuse-values"])
+            .status_checked();
+        let name = format!(
+            "{}/{} ({version} -> {})",
+            release.namespace, release.name, chart.version
+        );
+        match result {
+            Ok(()) if ctx.run_type().dry() => (),
+            Ok(()) => println!("{} {name}", style(t!("Upgraded")).green().bold()),
+            Err(e) => {
+                println!("{} {name}", style(t!("Failed")).red().bold());
+                debug!("Failed to upgrade the Helm release {name}: {e:?}");
+                failed.push(format!("{}/{}", release.namespace, release.name));
+            }
+        }
+    }
+
+    if failed.is_empty() {
         Ok(())
     } else {
-        Err(eyre!(StepFailed))
+        Err(eyre!(t!(
+            "Failed to upgrade the Helm releases {releases}",
+            releases = failed.join(", ")
+        )))
     }
 }
 
+/// Find the chart of `release` in `charts`, along with the version the release runs, if the
+/// chart has a newer version.
+///
+/// Releases only record the chart name, so releases whose chart is in several repositories
+/// are left alone rather than guessing.
+fn newer_helm_chart<'c>(release: &HelmRelease, charts: &'c [HelmChart]) -> Option<(&'c HelmChart, Version)> {
+    let mut candidates = charts.iter().filter_map(|chart| {
+        let name = chart.name.rsplit('/').next()?;
+        let installed = release.chart.strip_prefix(name)?.strip_prefix('-')?;
+        Some((chart, Version::parse(installed).ok()?))
+    });
+    let (chart, installed) = candidates.next()?;
+    if candidates.next().is_some() {
+        print_warning(t!(
+            "The chart of the Helm release {release} is in several repositories, not upgrading it",
+            release = release.name
+        ));
+        return None;
+    }
+
+    let available = Version::parse(&chart.version).ok()?;
+    (available > installed).then_some((chart, installed))
+}
+
 pub fn run_stew(ctx: &ExecutionContext) -> Result<()> {
     let stew = require("stew")?;
 
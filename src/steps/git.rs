@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
@@ -7,17 +9,18 @@ use color_eyre::eyre::Context;
 use color_eyre::eyre::{eyre, Result};
 use console::style;
 use futures::stream::{iter, FuturesUnordered, StreamExt};
-use glob::{glob_with, MatchOptions};
-use tokio::process::Command as AsyncCommand;
+use glob::{glob_with, MatchOptions, Pattern};
 use tokio::runtime;
 use tracing::{debug, error};
 
-use crate::command::CommandExt;
+use crate::command::{async_output, CommandExt};
 use crate::config::Step;
 use crate::execution_context::ExecutionContext;
+use crate::executor::ExecutorOutput;
+use crate::manifests::Manifests;
 use crate::steps::emacs::Emacs;
 use crate::terminal::print_separator;
-use crate::utils::{require, PathExt};
+use crate::utils::{require, which, PathExt};
 use crate::{error::SkipStep, terminal::print_warning, HOME_DIR};
 use etcetera::base_strategy::BaseStrategy;
 use rust_i18n::t;
@@ -29,6 +32,7 @@ use crate::XDG_DIRS;
 use crate::WINDOWS_DIRS;
 
 pub fn run_git_pull(ctx: &ExecutionContext) -> Result<()> {
+    ctx.require_online()?;
     let mut repos = RepoStep::try_new()?;
     let config = ctx.config();
 
@@ -96,6 +100,10 @@ pub fn run_git_pull(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
+    if let Some(excluded) = config.git_repos_exclude() {
+        repos.exclude(excluded);
+    }
+
     // Warn the user about the bad patterns.
     //
     // NOTE: this should be executed **before** skipping the Git step or the
@@ -112,11 +120,22 @@ pub fn run_git_pull(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep(t!("No repositories to pull").to_string()).into());
     }
 
+    let manifests = Manifests::check_state(ctx, Step::GitRepos, || repos.remote_state())?;
+
     print_separator(t!("Git repositories"));
 
-    repos.pull_repos(ctx)
+    let pulled = repos.pull_repos(ctx);
+    if config.git_precommit_autoupdate() {
+        repos.precommit_autoupdate(ctx)?;
+    }
+    pulled?;
+
+    manifests.remember()
 }
 
+/// The file pre-commit reads its hooks from, at the root of a repository.
+const PRE_COMMIT_CONFIG: &str = ".pre-commit-config.yaml";
+
 #[cfg(windows)]
 static PATH_PREFIX: &str = "\\\\?\\";
 
@@ -248,6 +267,65 @@ impl RepoStep {
             .ok()
     }
 
+    /// The refs of the remotes of all the repositories, which change when there is something
+    /// to pull. `None` if the remote of a repository can't be reached.
+    fn remote_state(&self) -> Option<Vec<u8>> {
+        let mut repos: Vec<&PathBuf> = self.repos.iter().collect();
+        repos.sort();
+
+        let mut state = Vec::new();
+        for repo in repos {
+            if self.has_remotes(repo) != Some(true) {
+                continue;
+            }
+
+            let output = Command::new(&self.git)
+                .stdin(Stdio::null())
+                .current_dir(repo)
+                .args(["ls-remote", "--quiet"])
+                .output_checked()
+                .map_err(|e| debug!("Error listing the remote refs of {}: {e}", repo.display()))
+                .ok()?;
+            state.extend_from_slice(repo.to_string_lossy().as_bytes());
+            state.push(b'\0');
+            state.extend(output.stdout);
+        }
+
+        Some(state)
+    }
+
+    /// The value of the git configuration `key` in `repo`, if it is set.
+    fn config_value<P: AsRef<Path>>(&self, repo: P, key: &str) -> Option<String> {
+        Command::new(&self.git)
+            .stdin(Stdio::null())
+            .current_dir(repo.as_ref())
+            .args(["config", "--get", key])
+            .output_checked_utf8()
+            .ok()
+            .map(|output| output.stdout.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Check if git can verify signatures in `repo`, with the program or the allowed signers
+    /// file of the signature format it is configured to use.
+    fn can_verify_signatures<P: AsRef<Path>>(&self, repo: P) -> bool {
+        let repo = repo.as_ref();
+        match self.config_value(repo, "gpg.format").as_deref() {
+            Some("ssh") => self.config_value(repo, "gpg.ssh.allowedSignersFile").is_some(),
+            Some("x509") => which(
+                self.config_value(repo, "gpg.x509.program")
+                    .unwrap_or_else(|| String::from("gpgsm")),
+            )
+            .is_some(),
+            _ => which(
+                self.config_value(repo, "gpg.openpgp.program")
+                    .or_else(|| self.config_value(repo, "gpg.program"))
+                    .unwrap_or_else(|| String::from("gpg")),
+            )
+            .is_some(),
+        }
+    }
+
     /// Similar to `insert_if_repo`, with glob support.
     pub fn glob_insert(&mut self, pattern: &str) {
         if let Ok(glob) = glob_with(pattern, self.glob_match_options) {
@@ -283,6 +361,28 @@ impl RepoStep {
         }
     }
 
+    /// Remove the repos whose path matches one of the glob `patterns`.
+    pub fn exclude(&mut self, patterns: &[String]) {
+        let patterns: Vec<Pattern> = patterns
+            .iter()
+            .filter_map(|pattern| {
+                Pattern::new(pattern)
+                    .map_err(|e| error!("Bad glob pattern {pattern}: {e}"))
+                    .ok()
+            })
+            .collect();
+
+        self.repos.retain(|repo| {
+            let excluded = patterns
+                .iter()
+                .any(|pattern| pattern.matches_path_with(repo, self.glob_match_options));
+            if excluded {
+                debug!("Excluding {}", repo.display());
+            }
+            !excluded
+        });
+    }
+
     /// True if `self.repos` is empty.
     pub fn is_repos_empty(&self) -> bool {
         self.repos.is_empty()
@@ -298,65 +398,83 @@ impl RepoStep {
     }
 
     /// Try to pull a repo.
-    async fn pull_repo<P: AsRef<Path>>(&self, ctx: &ExecutionContext<'_>, repo: P) -> Result<()> {
-        let before_revision = get_head_revision(&self.git, &repo);
+    ///
+    /// Returns what should be printed about the repo, so that the output of repos pulled
+    /// concurrently doesn't get interleaved.
+    async fn pull_repo<P: AsRef<Path>>(&self, ctx: &ExecutionContext<'_>, repo: P) -> (String, Result<()>) {
+        let mut output = String::new();
+        let result = self.pull_repo_into(ctx, repo.as_ref(), &mut output).await;
+        (output, result)
+    }
+
+    async fn pull_repo_into(&self, ctx: &ExecutionContext<'_>, repo: &Path, output: &mut String) -> Result<()> {
+        let before_revision = get_head_revision(&self.git, repo);
 
         if ctx.config().verbose() {
-            println!("{} {}", style(t!("Pulling")).cyan().bold(), repo.as_ref().display());
+            writeln!(output, "{} {}", style(t!("Pulling")).cyan().bold(), repo.display())?;
         }
 
-        let mut command = AsyncCommand::new(&self.git);
+        let mut command = Command::new(&self.git);
 
         command
             .stdin(Stdio::null())
-            .current_dir(&repo)
+            .current_dir(repo)
             .args(["pull", "--ff-only"]);
 
+        let verify_signatures = ctx.config().git_verify_signatures();
+        if verify_signatures {
+            command.arg("--verify-signatures");
+        }
+
         if let Some(extra_arguments) = ctx.config().git_arguments() {
             command.args(extra_arguments.split_whitespace());
         }
 
-        let pull_output = command.output().await?;
-        let submodule_output = AsyncCommand::new(&self.git)
+        let pull_output = async_output(command).await?;
+        let mut pull_result = output_checked_utf8(pull_output);
+        if verify_signatures && pull_result.is_ok() {
+            pull_result = self.verify_head(repo, before_revision.as_deref()).await;
+        }
+        let mut command = Command::new(&self.git);
+        command
             .args(["submodule", "update", "--recursive"])
-            .current_dir(&repo)
-            .stdin(Stdio::null())
-            .output()
-            .await?;
-        let result = output_checked_utf8(pull_output)
+            .current_dir(repo)
+            .stdin(Stdio::null());
+        let submodule_output = async_output(command).await?;
+        let result = pull_result
             .and_then(|()| output_checked_utf8(submodule_output))
-            .wrap_err_with(|| format!("Failed to pull {}", repo.as_ref().display()));
+            .wrap_err_with(|| format!("Failed to pull {}", repo.display()));
 
         if result.is_err() {
-            println!(
+            writeln!(
+                output,
                 "{} {} {}",
                 style(t!("Failed")).red().bold(),
                 t!("pulling"),
-                repo.as_ref().display()
-            );
+                repo.display()
+            )?;
         } else {
-            let after_revision = get_head_revision(&self.git, repo.as_ref());
+            let after_revision = get_head_revision(&self.git, repo);
 
             match (&before_revision, &after_revision) {
                 (Some(before), Some(after)) if before != after => {
-                    println!("{} {}", style(t!("Changed")).yellow().bold(), repo.as_ref().display());
-
-                    Command::new(&self.git)
-                        .stdin(Stdio::null())
-                        .current_dir(&repo)
-                        .args([
-                            "--no-pager",
-                            "log",
-                            "--no-decorate",
-                            "--oneline",
-                            &format!("{before}..{after}"),
-                        ])
-                        .status_checked()?;
-                    println!();
+                    writeln!(output, "{} {}", style(t!("Changed")).yellow().bold(), repo.display())?;
+
+                    let mut command = Command::new(&self.git);
+                    command.stdin(Stdio::null()).current_dir(repo).args([
+                        "--no-pager",
+                        "log",
+                        "--no-decorate",
+                        "--oneline",
+                        &format!("{before}..{after}"),
+                    ]);
+                    let log_output = async_output(command).await?;
+                    writeln!(output, "{}", String::from_utf8_lossy(&log_output.stdout))?;
+                    output_checked_utf8(log_output)?;
                 }
                 _ => {
                     if ctx.config().verbose() {
-                        println!("{} {}", style(t!("Up-to-date")).green().bold(), repo.as_ref().display());
+                        writeln!(output, "{} {}", style(t!("Up-to-date")).green().bold(), repo.display())?;
                     }
                 }
             }
@@ -365,12 +483,133 @@ impl RepoStep {
         result
     }
 
+    /// Check the signature of the commit a pull moved `repo` to.
+    ///
+    /// `--verify-signatures` only applies to merges, so this also catches pulls that rebase.
+    /// If the signature can't be verified, the repository is reset to `before_revision`, so
+    /// that unverified commits aren't left checked out.
+    async fn verify_head(&self, repo: &Path, before_revision: Option<&str>) -> Result<()> {
+        let after_revision = get_head_revision(&self.git, repo);
+        if after_revision.as_deref() == before_revision {
+            return Ok(());
+        }
+
+        let mut command = Command::new(&self.git);
+        command
+            .stdin(Stdio::null())
+            .current_dir(repo)
+            .args(["verify-commit", "HEAD"]);
+        let verify_output = async_output(command).await?;
+        let Err(e) = output_checked_utf8(verify_output) else {
+            return Ok(());
+        };
+
+        if let Some(before) = before_revision {
+            let mut command = Command::new(&self.git);
+            command
+                .stdin(Stdio::null())
+                .current_dir(repo)
+                .args(["reset", "--keep", before]);
+            let reset_output = async_output(command).await?;
+            output_checked_utf8(reset_output)
+                .wrap_err_with(|| format!("Failed to reset {} to {before}", repo.display()))?;
+        }
+
+        Err(e.wrap_err(t!("The signature of the pulled commits can't be verified")))
+    }
+
+    /// Run `pre-commit autoupdate` in the repositories that have a `.pre-commit-config.yaml`,
+    /// reporting the ones whose hooks changed.
+    pub fn precommit_autoupdate(&self, ctx: &ExecutionContext) -> Result<()> {
+        let mut repos: Vec<&PathBuf> = self
+            .repos
+            .iter()
+            .filter(|repo| repo.join(PRE_COMMIT_CONFIG).is_file())
+            .collect();
+        if repos.is_empty() {
+            return Ok(());
+        }
+        repos.sort();
+
+        let pre_commit = match require("pre-commit") {
+            Ok(pre_commit) => pre_commit,
+            Err(_) => {
+                print_warning(t!("pre-commit is not installed, not updating the hooks"));
+                return Ok(());
+            }
+        };
+
+        println!("\n{}", t!("Updating the pre-commit hooks"));
+        let mut failed = Vec::new();
+        for repo in repos {
+            let config = repo.join(PRE_COMMIT_CONFIG);
+            let before = fs::read(&config).ok();
+            let output = ctx
+                .run_type()
+                .execute(&pre_commit)
+                .current_dir(repo)
+                .arg("autoupdate")
+                .output()?;
+            match output {
+                ExecutorOutput::Wet(output) if !output.status.success() => {
+                    debug!(
+                        "pre-commit autoupdate failed in {}: {}",
+                        repo.display(),
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                    println!("{} {}", style(t!("Failed")).red().bold(), repo.display());
+                    failed.push(repo.display().to_string());
+                }
+                ExecutorOutput::Wet(_) if fs::read(&config).ok() != before => {
+                    println!("{} {}", style(t!("Updated")).green().bold(), repo.display());
+                }
+                ExecutorOutput::Wet(_) => {
+                    if ctx.config().verbose() {
+                        println!("{} {}", style(t!("Up-to-date")).green().bold(), repo.display());
+                    }
+                }
+                ExecutorOutput::Dry => (),
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!(t!(
+                "Failed to update the pre-commit hooks of {repos}",
+                repos = failed.join(", ")
+            )))
+        }
+    }
+
     /// Pull the repositories specified in `self.repos`.
     ///
     /// # NOTE
     /// This function will create an async runtime and do the real job so the
     /// function itself is not async.
-    fn pull_repos(&self, ctx: &ExecutionContext) -> Result<()> {
+    pub fn pull_repos(&self, ctx: &ExecutionContext) -> Result<()> {
+        if ctx.config().dry_run_script() {
+            // The script should pull the repositories itself, so spell out the commands.
+            for repo in &self.repos {
+                let mut command = ctx.run_type().execute(&self.git);
+                command.current_dir(repo).args(["pull", "--ff-only"]);
+                if ctx.config().git_verify_signatures() {
+                    command.arg("--verify-signatures");
+                }
+                if let Some(extra_arguments) = ctx.config().git_arguments() {
+                    command.args(extra_arguments.split_whitespace());
+                }
+                command.status_checked()?;
+                ctx.run_type()
+                    .execute(&self.git)
+                    .current_dir(repo)
+                    .args(["submodule", "update", "--recursive"])
+                    .status_checked()?;
+            }
+
+            return Ok(());
+        }
+
         if ctx.run_type().dry() {
             self.repos
                 .iter()
@@ -402,6 +641,16 @@ impl RepoStep {
                 }
                 _ => true, // repo has remotes or command to check for remotes has failed. proceed to pull anyway.
             })
+            .filter(|repo| {
+                if ctx.config().git_verify_signatures() && !self.can_verify_signatures(repo) {
+                    print_warning(t!(
+                        "Skipping {repo} because git isn't set up to verify signatures in it",
+                        repo = repo.display()
+                    ));
+                    return false;
+                }
+                true
+            })
             .map(|repo| self.pull_repo(ctx, repo));
 
         let stream_of_futures = if let Some(limit) = ctx.config().git_concurrency_limit() {
@@ -410,10 +659,26 @@ impl RepoStep {
             futures_iterator.collect::<FuturesUnordered<_>>().boxed()
         };
 
+        // Print each repo's output as soon as it is done, and remember which ones failed.
         let basic_rt = runtime::Runtime::new()?;
-        let results = basic_rt.block_on(async { stream_of_futures.collect::<Vec<Result<()>>>().await });
-
-        let error = results.into_iter().find(std::result::Result::is_err);
-        error.unwrap_or(Ok(()))
+        let failed = basic_rt.block_on(async {
+            stream_of_futures
+                .filter_map(|(output, result)| async move {
+                    print!("{output}");
+                    result.err()
+                })
+                .collect::<Vec<_>>()
+                .await
+        });
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre!(t!(
+                "Failed to pull {count} repositories:\n{errors}",
+                count = failed.len(),
+                errors = failed.iter().map(|e| format!("  {e:#}")).collect::<Vec<_>>().join("\n")
+            )))
+        }
     }
 }
@@ -1,13 +1,76 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
+use console::style;
+use rust_i18n::t;
 
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
-use crate::terminal::print_separator;
+use crate::terminal::{print_info, print_separator};
 use crate::utils;
 use crate::utils::PathExt;
+use crate::HOME_DIR;
+
+/// Update the Go toolchain installed with `golang.org/dl` to the latest release.
+/// <https://pkg.go.dev/golang.org/dl>
+pub fn run_go_toolchain_update(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().go_update_toolchain() {
+        return Err(SkipStep(t!("Go toolchain update is not enabled").to_string()).into());
+    }
+    let go = utils::require("go")?;
+
+    // `golang.org/dl` downloads each version to `~/sdk/<version>`, any other Go comes from
+    // somewhere else, like a package manager, which should update it instead.
+    let goroot = Command::new(&go).args(["env", "GOROOT"]).output_checked_utf8()?;
+    let goroot = PathBuf::from(goroot.stdout.trim());
+    let current = match goroot.parent().zip(goroot.file_name()) {
+        Some((sdk, version)) if sdk == HOME_DIR.join("sdk") => version.to_string_lossy().into_owned(),
+        _ => return Err(SkipStep(t!("Go is not managed by golang.org/dl").to_string()).into()),
+    };
+
+    ctx.require_online()?;
+    let curl = utils::require("curl")?;
+    let latest = Command::new(curl)
+        .args(["--silent", "--fail", "--location", "https://go.dev/VERSION?m=text"])
+        .output_checked_utf8()?;
+    let latest = latest
+        .stdout
+        .lines()
+        .next()
+        .map(str::trim)
+        .filter(|version| version.starts_with("go"))
+        .ok_or_else(|| eyre!(t!("Failed to find the latest Go version")))?
+        .to_owned();
+
+    print_separator(t!("Go toolchain"));
+
+    if latest == current {
+        println!("{}", t!("Go is up to date ({version})", version = current));
+        return Ok(());
+    }
+
+    ctx.run_type()
+        .execute(&go)
+        .args(["install", &format!("golang.org/dl/{latest}@latest")])
+        .status_checked()?;
+    ctx.run_type()
+        .execute(gopath(&go)?.join("bin").join(&latest))
+        .arg("download")
+        .status_checked()?;
+    if ctx.run_type().dry() {
+        return Ok(());
+    }
+
+    println!("{} {current} -> {latest}", style(t!("Updated")).green().bold());
+    print_info(t!(
+        "Add {path} to your PATH to use it",
+        path = HOME_DIR.join("sdk").join(&latest).join("bin").display()
+    ));
+
+    Ok(())
+}
 
 /// <https://github.com/Gelio/go-global-update>
 pub fn run_go_global_update(ctx: &ExecutionContext) -> Result<()> {
@@ -29,17 +92,21 @@ pub fn run_go_gup(ctx: &ExecutionContext) -> Result<()> {
 
 /// Get the path of a Go binary.
 fn require_go_bin(name: &str) -> Result<PathBuf> {
-    utils::require(name).or_else(|_| {
+    utils::require(name).or_else(|not_installed| {
         let go = utils::require("go")?;
-        // TODO: Does this work? `go help gopath` says that:
-        // > The GOPATH environment variable lists places to look for Go code.
-        // > On Unix, the value is a colon-separated string.
-        // > On Windows, the value is a semicolon-separated string.
-        // > On Plan 9, the value is a list.
-        // Should we also fallback to the env variable?
-        let gopath_output = Command::new(go).args(["env", "GOPATH"]).output_checked_utf8()?;
-        let gopath = gopath_output.stdout.trim();
-
-        PathBuf::from(gopath).join("bin").join(name).require()
+        gopath(&go)?.join("bin").join(name).require().map_err(|_| not_installed)
     })
 }
+
+/// Get the `GOPATH` of `go`.
+fn gopath(go: &Path) -> Result<PathBuf> {
+    // TODO: Does this work? `go help gopath` says that:
+    // > The GOPATH environment variable lists places to look for Go code.
+    // > On Unix, the value is a colon-separated string.
+    // > On Windows, the value is a semicolon-separated string.
+    // > On Plan 9, the value is a list.
+    // Should we also fallback to the env variable?
+    let gopath_output = Command::new(go).args(["env", "GOPATH"]).output_checked_utf8()?;
+
+    Ok(PathBuf::from(gopath_output.stdout.trim()))
+}
@@ -1,21 +1,27 @@
+use std::collections::HashMap;
+use std::env;
 use std::fmt::Display;
 #[cfg(target_os = "linux")]
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use crate::utils::{get_require_sudo_string, require_option};
 use crate::HOME_DIR;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 #[cfg(target_os = "linux")]
 use nix::unistd::Uid;
 use rust_i18n::t;
 use semver::Version;
+use serde_json::Value;
 use tracing::debug;
 
+use crate::audit;
 use crate::command::CommandExt;
-use crate::terminal::{print_info, print_separator};
-use crate::utils::{require, PathExt};
+use crate::config::Step;
+use crate::manifests::Manifests;
+use crate::terminal::{print_info, print_separator, print_warning};
+use crate::utils::{count_updated_packages, require, which, PathExt};
 use crate::{error::SkipStep, execution_context::ExecutionContext};
 
 enum NPMVariant {
@@ -73,7 +79,6 @@ impl NPM {
         }
     }
 
-    #[cfg(target_os = "linux")]
     fn root(&self) -> Result<PathBuf> {
         let args = ["root", self.global_location_arg()];
         Command::new(&self.command)
@@ -82,6 +87,50 @@ impl NPM {
             .map(|s| PathBuf::from(s.stdout.trim()))
     }
 
+    /// The lockfile of the globally installed packages.
+    fn global_lockfile(&self) -> Result<PathBuf> {
+        let root = self.root()?;
+        Ok(match self.variant {
+            NPMVariant::Npm => root.join(".package-lock.json"),
+            // `pnpm root -g` points at the `node_modules` next to the lockfile.
+            NPMVariant::Pnpm => root.parent().unwrap_or(&root).join("pnpm-lock.yaml"),
+        })
+    }
+
+    /// The command auditing the globally installed packages.
+    fn audit_command(&self) -> Result<Command> {
+        let mut command = Command::new(&self.command);
+        match self.variant {
+            NPMVariant::Npm => {
+                command.args(["audit", "--json", self.global_location_arg()]);
+            }
+            // `pnpm audit` only reads the lockfile in the current directory.
+            NPMVariant::Pnpm => {
+                let lockfile = self.global_lockfile()?;
+                command
+                    .args(["audit", "--json"])
+                    .current_dir(lockfile.parent().unwrap());
+            }
+        }
+        Ok(command)
+    }
+
+    /// The versions of the globally installed packages, from `npm ls`.
+    fn installed(&self) -> Option<HashMap<String, String>> {
+        let output = Command::new(&self.command)
+            .args(["ls", self.global_location_arg(), "--depth=0", "--json"])
+            .output_checked_utf8()
+            .ok()?;
+        let list: Value = serde_json::from_str(&output.stdout).ok()?;
+        Some(
+            list["dependencies"]
+                .as_object()?
+                .iter()
+                .filter_map(|(name, package)| Some((name.clone(), package["version"].as_str()?.to_owned())))
+                .collect(),
+        )
+    }
+
     fn version(&self) -> Result<Version> {
         let version_str = Command::new(&self.command)
             .args(["--version"])
@@ -144,7 +193,6 @@ impl Yarn {
         matches!(version, Ok(ver) if ver.stdout.starts_with('1') || ver.stdout.starts_with('0'))
     }
 
-    #[cfg(target_os = "linux")]
     fn root(&self) -> Result<PathBuf> {
         let args = ["global", "dir"];
         Command::new(&self.command)
@@ -153,6 +201,13 @@ impl Yarn {
             .map(|s| PathBuf::from(s.stdout.trim()))
     }
 
+    /// The command auditing the globally installed packages.
+    fn audit_command(&self) -> Result<Command> {
+        let mut command = Command::new(&self.command);
+        command.args(["audit", "--json"]).current_dir(self.root()?);
+        Ok(command)
+    }
+
     fn upgrade(&self, ctx: &ExecutionContext, use_sudo: bool) -> Result<()> {
         let args = ["global", "upgrade"];
 
@@ -301,33 +356,168 @@ fn should_use_sudo_yarn(yarn: &Yarn, ctx: &ExecutionContext) -> Result<bool> {
 pub fn run_npm_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let npm = require("npm").map(|b| NPM::new(b, NPMVariant::Npm))?;
 
+    let local_project = if ctx.config().npm_update_local() {
+        env::current_dir()?.join("package.json").if_exists()
+    } else {
+        None
+    };
+    let manifests = Manifests::check_with(ctx, Step::Node, || {
+        let lockfile = npm
+            .global_lockfile()
+            .map_err(|e| debug!("Could not find the global lockfile: {e:#}"))
+            .ok()?;
+        let mut manifests = vec![lockfile];
+        manifests.extend(local_project.clone());
+        Some(manifests)
+    })?;
+
     print_separator(t!("Node Package Manager"));
 
+    // Compare the installed versions before and after, to tell how many packages got updated.
+    let installed = if ctx.run_type().dry() { None } else { npm.installed() };
+
     #[cfg(target_os = "linux")]
     {
-        npm.upgrade(ctx, should_use_sudo(&npm, ctx)?)
+        npm.upgrade(ctx, should_use_sudo(&npm, ctx)?)?;
     }
 
     #[cfg(not(target_os = "linux"))]
     {
-        npm.upgrade(ctx, false)
+        npm.upgrade(ctx, false)?;
+    }
+
+    if let Some(before) = installed {
+        if let Some(after) = npm.installed() {
+            ctx.record_updated_packages(count_updated_packages(&before, &after));
+        }
+    }
+
+    if let Some(package_json) = local_project {
+        update_local_project(ctx, package_json.parent().unwrap())?;
+    }
+
+    manifests.remember()?;
+    audit(ctx, "npm", || npm.audit_command())
+}
+
+/// Run the command built by `command`, the `audit` subcommand of the package manager `name`,
+/// when `node_audit` is set, and report how many vulnerabilities it found.
+///
+/// The command is only built here, as it can take running the package manager to find where
+/// the global packages are.
+fn audit(ctx: &ExecutionContext, name: &str, command: impl FnOnce() -> Result<Command>) -> Result<()> {
+    if !ctx.config().node_audit() || ctx.run_type().dry() {
+        return Ok(());
+    }
+
+    let mut command = command()?;
+
+    print_info(t!("Auditing the packages of {name}", name = name));
+    // Audits exit with an error when they find vulnerabilities, the output tells how many.
+    let output = command.output_checked_with_utf8(|_| Ok(()))?;
+    let Some(count) = count_vulnerabilities(&output.stdout) else {
+        print_warning(t!("Could not read the output of {name} audit", name = name));
+        return Ok(());
+    };
+
+    audit::record(name, count);
+    if count == 0 {
+        println!("{}", t!("No vulnerabilities found"));
+        Ok(())
+    } else if ctx.config().node_audit_fail() {
+        Err(eyre!(t!("{count} vulnerabilities found", count = count)))
+    } else {
+        print_warning(t!("{count} vulnerabilities found", count = count));
+        Ok(())
     }
 }
 
+/// Read the number of vulnerabilities from the JSON output of an audit.
+///
+/// npm and pnpm print a single document with the counts per severity under
+/// `metadata.vulnerabilities`, only npm 7 and above include a `total`. Yarn 1 prints one
+/// document per line, the counts are in the last one, of type `auditSummary`.
+fn count_vulnerabilities(output: &str) -> Option<u64> {
+    fn count(document: &Value) -> Option<u64> {
+        let vulnerabilities = match document.pointer("/metadata/vulnerabilities") {
+            Some(vulnerabilities) => vulnerabilities,
+            None if document["type"] == "auditSummary" => document.pointer("/data/vulnerabilities")?,
+            None => return None,
+        }
+        .as_object()?;
+
+        match vulnerabilities.get("total") {
+            Some(total) => total.as_u64(),
+            None => Some(vulnerabilities.values().filter_map(Value::as_u64).sum()),
+        }
+    }
+
+    if let Ok(document) = serde_json::from_str(output) {
+        return count(&document);
+    }
+    output
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .find_map(|document| count(&document))
+}
+
+/// Update the dependencies of the project in `dir` with the package manager its lockfile
+/// belongs to.
+fn update_local_project(ctx: &ExecutionContext, dir: &Path) -> Result<()> {
+    let (name, args): (&str, &[&str]) = if dir.join("pnpm-lock.yaml").exists() {
+        ("pnpm", &["update"])
+    } else if dir.join("yarn.lock").exists() {
+        ("yarn", &["upgrade"])
+    } else {
+        ("npm", &["update"])
+    };
+    let Some(binary) = which(name) else {
+        print_warning(t!(
+            "{name} is not installed, not updating the project in {dir}",
+            name = name,
+            dir = dir.display()
+        ));
+        return Ok(());
+    };
+
+    print_info(t!("Updating the project in {dir}", dir = dir.display()));
+    let mut command = ctx.run_type().execute(&binary);
+    command.current_dir(dir);
+    // Yarn 2 and above replaced `yarn upgrade` with `yarn up`.
+    if name == "yarn" && !Yarn::new(binary).has_global_subcmd() {
+        command.args(["up", "*"]);
+    } else {
+        command.args(args);
+    }
+    command.status_checked()
+}
+
 pub fn run_pnpm_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let pnpm = require("pnpm").map(|b| NPM::new(b, NPMVariant::Pnpm))?;
 
+    let manifests = Manifests::check_with(ctx, Step::Pnpm, || {
+        let lockfile = pnpm
+            .global_lockfile()
+            .map_err(|e| debug!("Could not find the global lockfile: {e:#}"))
+            .ok()?;
+        Some(vec![lockfile])
+    })?;
+
     print_separator(t!("Performant Node Package Manager"));
 
     #[cfg(target_os = "linux")]
     {
-        pnpm.upgrade(ctx, should_use_sudo(&pnpm, ctx)?)
+        pnpm.upgrade(ctx, should_use_sudo(&pnpm, ctx)?)?;
     }
 
     #[cfg(not(target_os = "linux"))]
     {
-        pnpm.upgrade(ctx, false)
+        pnpm.upgrade(ctx, false)?;
     }
+
+    manifests.remember()?;
+    audit(ctx, "pnpm", || pnpm.audit_command())
 }
 
 pub fn run_yarn_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -342,13 +532,15 @@ pub fn run_yarn_upgrade(ctx: &ExecutionContext) -> Result<()> {
 
     #[cfg(target_os = "linux")]
     {
-        yarn.upgrade(ctx, should_use_sudo_yarn(&yarn, ctx)?)
+        yarn.upgrade(ctx, should_use_sudo_yarn(&yarn, ctx)?)?;
     }
 
     #[cfg(not(target_os = "linux"))]
     {
-        yarn.upgrade(ctx, false)
+        yarn.upgrade(ctx, false)?;
     }
+
+    audit(ctx, "yarn", || yarn.audit_command())
 }
 
 pub fn deno_upgrade(ctx: &ExecutionContext) -> Result<()> {
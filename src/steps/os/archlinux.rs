@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::env::var_os;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
 use color_eyre::eyre;
 use color_eyre::eyre::{Context, Result};
@@ -10,8 +12,10 @@ use walkdir::WalkDir;
 use crate::command::CommandExt;
 use crate::error::TopgradeError;
 use crate::execution_context::ExecutionContext;
+use crate::explain;
 use crate::utils::require_option;
 use crate::utils::which;
+use crate::utils::{count_updated_packages, parse_package_versions};
 use crate::{config, output_changed_message, Step};
 
 fn get_execution_path() -> OsString {
@@ -366,10 +370,55 @@ pub fn get_arch_package_manager(ctx: &ExecutionContext) -> Option<Box<dyn ArchPa
     }
 }
 
+/// List the packages with a newer version in the sync databases, as of the last `pacman -Sy`.
+fn explain_arch_linux() -> Result<()> {
+    // `pacman -Qu` exits with 1 when there is nothing to upgrade.
+    let output = Command::new("pacman").arg("-Qu").output_checked_with(|output| {
+        if output.status.success() || output.status.code() == Some(1) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Lines look like `foo 1.0-1 -> 1.1-1`.
+    let packages: Vec<&str> = stdout.lines().filter(|line| !line.is_empty()).collect();
+
+    explain::print_upgradable("pacman", &packages);
+    Ok(())
+}
+
 pub fn upgrade_arch_linux(ctx: &ExecutionContext) -> Result<()> {
+    if ctx.run_type().explain() {
+        return explain_arch_linux();
+    }
+
     let package_manager =
         get_arch_package_manager(ctx).ok_or_else(|| eyre::Report::from(TopgradeError::FailedGettingPackageManager))?;
-    package_manager.upgrade(ctx)
+
+    // Compare the installed versions before and after, to tell how many packages got updated.
+    let installed = if ctx.run_type().dry() {
+        None
+    } else {
+        installed_arch_linux()
+    };
+    package_manager.upgrade(ctx)?;
+    if let Some(before) = installed {
+        if let Some(after) = installed_arch_linux() {
+            ctx.record_updated_packages(count_updated_packages(&before, &after));
+        }
+    }
+
+    Ok(())
+}
+
+/// The versions of the installed packages, according to pacman.
+fn installed_arch_linux() -> Option<HashMap<String, String>> {
+    Command::new("pacman")
+        .arg("-Q")
+        .output_checked_utf8()
+        .map(|output| parse_package_versions(&output.stdout))
+        .ok()
 }
 
 pub fn show_pacnew() {
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
 
 use color_eyre::eyre::Result;
 use ini::Ini;
@@ -7,16 +9,40 @@ use rust_i18n::t;
 use tracing::{debug, warn};
 
 use crate::command::CommandExt;
-use crate::error::{SkipStep, TopgradeError};
+use crate::error::{NotInstalled, SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
+use crate::executor::Executor;
+use crate::explain;
 use crate::steps::generic::is_wsl;
 use crate::steps::os::archlinux;
-use crate::terminal::{print_separator, prompt_yesno};
-use crate::utils::{get_require_sudo_string, require, require_option, which, PathExt};
+use crate::terminal::{print_separator, print_warning, prompt_yesno};
+use crate::utils::{
+    count_updated_packages, get_require_sudo_string, parse_package_versions, require, require_option, which, PathExt,
+};
 use crate::{Step, HOME_DIR};
 
 static OS_RELEASE_PATH: &str = "/etc/os-release";
 
+/// Packages `apt-get autoremove` removed during this run, see `Distribution::show_summary`.
+static APT_AUTOREMOVED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Packages apt kept back during this run, see `Distribution::show_summary`.
+static APT_HELD_BACK: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Makes apt install phased updates without waiting for them to be rolled out.
+const APT_INCLUDE_PHASED: &str = "APT::Get::Always-Include-Phased-Updates=true";
+
+/// Makes debconf take the default answer to its questions, see `apt_noninteractive`.
+const APT_NONINTERACTIVE_ENV: &str = "DEBIAN_FRONTEND=noninteractive";
+
+/// Makes dpkg keep the configuration files changed locally instead of asking what to do.
+const APT_KEEP_CONFIG_FILES: [&str; 4] = [
+    "-o",
+    "Dpkg::Options::=--force-confdef",
+    "-o",
+    "Dpkg::Options::=--force-confold",
+];
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Distribution {
@@ -135,7 +161,7 @@ impl Distribution {
     pub fn upgrade(self, ctx: &ExecutionContext) -> Result<()> {
         print_separator(t!("System update"));
 
-        match self {
+        let result = match self {
             Distribution::Alpine => upgrade_alpine_linux(ctx),
             Distribution::Chimera => upgrade_chimera_linux(ctx),
             Distribution::Wolfi => upgrade_wolfi_linux(ctx),
@@ -159,13 +185,36 @@ impl Distribution {
             Distribution::PCLinuxOS => upgrade_pclinuxos(ctx),
             Distribution::Nobara => upgrade_nobara(ctx),
             Distribution::NILRT => upgrade_nilrt(ctx),
+        };
+
+        // Left by Debian based distributions when an upgraded package needs a reboot.
+        if !ctx.run_type().dry() && Path::new("/var/run/reboot-required").exists() {
+            ctx.require_reboot();
         }
+
+        result
     }
 
     pub fn show_summary(self) {
         if let Distribution::Arch = self {
             archlinux::show_pacnew();
         }
+
+        let removed = APT_AUTOREMOVED.lock().unwrap();
+        if !removed.is_empty() {
+            println!(
+                "{}",
+                t!("Removed by apt autoremove: {packages}", packages = removed.join(", "))
+            );
+        }
+
+        let held_back = APT_HELD_BACK.lock().unwrap();
+        if !held_back.is_empty() {
+            println!(
+                "{}",
+                t!("Kept back by apt: {packages}", packages = held_back.join(", "))
+            );
+        }
     }
 
     pub fn redhat_based(self) -> bool {
@@ -323,23 +372,41 @@ fn upgrade_bedrock_strata(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// The global options of zypper, which come before its command.
+fn zypper_options(ctx: &ExecutionContext) -> &'static [&'static str] {
+    if ctx.config().yes(Step::System) && ctx.config().zypper_noninteractive() {
+        &["--non-interactive"]
+    } else {
+        &[]
+    }
+}
+
+/// The options of `zypper update` and `zypper dist-upgrade`.
+fn zypper_upgrade_options(ctx: &ExecutionContext) -> &'static [&'static str] {
+    match (ctx.config().yes(Step::System), ctx.config().zypper_noninteractive()) {
+        (true, true) => &["-y", "--auto-agree-with-licenses"],
+        (true, false) => &["-y"],
+        (false, _) => &[],
+    }
+}
+
 fn upgrade_suse(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), get_require_sudo_string())?;
     ctx.run_type()
         .execute(sudo)
-        .args(["zypper", "refresh"])
+        .arg("zypper")
+        .args(zypper_options(ctx))
+        .arg("refresh")
         .status_checked()?;
 
     let mut cmd = ctx.run_type().execute(sudo);
-    cmd.arg("zypper");
+    cmd.arg("zypper").args(zypper_options(ctx));
     cmd.arg(if ctx.config().suse_dup() {
         "dist-upgrade"
     } else {
         "update"
     });
-    if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
-    }
+    cmd.args(zypper_upgrade_options(ctx));
 
     cmd.status_checked()?;
 
@@ -350,14 +417,14 @@ fn upgrade_opensuse_tumbleweed(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), get_require_sudo_string())?;
     ctx.run_type()
         .execute(sudo)
-        .args(["zypper", "refresh"])
+        .arg("zypper")
+        .args(zypper_options(ctx))
+        .arg("refresh")
         .status_checked()?;
 
     let mut cmd = ctx.run_type().execute(sudo);
-    cmd.args(["zypper", "dist-upgrade"]);
-    if ctx.config().yes(Step::System) {
-        cmd.arg("-y");
-    }
+    cmd.arg("zypper").args(zypper_options(ctx)).arg("dist-upgrade");
+    cmd.args(zypper_upgrade_options(ctx));
 
     cmd.status_checked()?;
 
@@ -509,6 +576,23 @@ fn upgrade_gentoo(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// List the packages `apt-get dist-upgrade` would install or upgrade, based on the package
+/// lists from the last `apt-get update`.
+fn explain_debian() -> Result<()> {
+    let output = Command::new("apt-get")
+        .args(["--simulate", "dist-upgrade"])
+        .output_checked_utf8()?;
+    // Lines look like `Inst foo [1.0] (1.1 Debian:stable [amd64])`.
+    let packages: Vec<&str> = output
+        .stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("Inst "))
+        .collect();
+
+    explain::print_upgradable("apt", &packages);
+    Ok(())
+}
+
 fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     let apt = which("apt-fast")
         .or_else(|| {
@@ -530,6 +614,10 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     let is_mist = apt.ends_with("mist");
     let is_nala = apt.ends_with("nala");
 
+    if ctx.run_type().explain() {
+        return explain_debian();
+    }
+
     // MIST does not require `sudo`
     if is_mist {
         ctx.run_type().execute(&apt).arg("update").status_checked()?;
@@ -549,7 +637,15 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
             .status_checked_with_codes(&[0, 100])?;
     }
 
+    // Compare the installed versions before and after, to tell how many packages got updated.
+    let installed = if ctx.run_type().dry() { None } else { installed_debian() };
+
+    let noninteractive = ctx.config().yes(Step::System) && ctx.config().apt_noninteractive();
     let mut command = ctx.run_type().execute(sudo);
+    if noninteractive {
+        // `sudo` may not pass the variable on otherwise.
+        command.args(["env", APT_NONINTERACTIVE_ENV]);
+    }
     command.arg(&apt);
     if is_nala {
         command.arg("upgrade");
@@ -559,25 +655,118 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
+    if noninteractive {
+        command.args(APT_KEEP_CONFIG_FILES);
+    }
+    if ctx.config().apt_include_phased() {
+        command.args(["-o", APT_INCLUDE_PHASED]);
+    }
     if let Some(args) = ctx.config().apt_arguments() {
         command.args(args.split_whitespace());
     }
     command.status_checked()?;
 
-    if ctx.config().cleanup() {
+    if let Some(before) = installed {
+        if let Some(after) = installed_debian() {
+            ctx.record_updated_packages(count_updated_packages(&before, &after));
+        }
+    }
+
+    if !ctx.run_type().dry() {
+        APT_HELD_BACK
+            .lock()
+            .unwrap()
+            .extend(held_back_debian(ctx.config().apt_include_phased()));
+    }
+
+    if ctx.config().cleanup() || ctx.config().apt_clean() {
         ctx.run_type().execute(sudo).arg(&apt).arg("clean").status_checked()?;
+    }
+
+    if ctx.config().cleanup() || ctx.config().apt_autoremove() {
+        // Find out what is going to be removed first, for the summary.
+        let removed = if ctx.run_type().dry() {
+            Vec::new()
+        } else {
+            autoremovable_debian()
+        };
 
         let mut command = ctx.run_type().execute(sudo);
+        if noninteractive {
+            command.args(["env", APT_NONINTERACTIVE_ENV]);
+        }
         command.arg(&apt).arg("autoremove");
         if ctx.config().yes(Step::System) {
             command.arg("-y");
         }
         command.status_checked()?;
+
+        APT_AUTOREMOVED.lock().unwrap().extend(removed);
     }
 
     Ok(())
 }
 
+/// The versions of the installed packages, according to dpkg.
+fn installed_debian() -> Option<HashMap<String, String>> {
+    Command::new("dpkg-query")
+        .args(["--show", "--showformat=${Package}:${Architecture} ${Version}\n"])
+        .output_checked_utf8()
+        .map(|output| parse_package_versions(&output.stdout))
+        .ok()
+}
+
+/// List the packages `apt-get dist-upgrade` would still leave alone: held ones, phased updates
+/// not rolled out to this machine yet, and ones needing changes apt won't make on its own.
+fn held_back_debian(include_phased: bool) -> Vec<String> {
+    let mut command = Command::new("apt-get");
+    command.args(["--simulate", "dist-upgrade"]).env("LC_ALL", "C");
+    if include_phased {
+        command.args(["-o", APT_INCLUDE_PHASED]);
+    }
+
+    command
+        .output_checked_utf8()
+        .map(|output| parse_held_back(&output.stdout))
+        .unwrap_or_default()
+}
+
+/// Read the packages listed under the headings about kept back and deferred packages in the
+/// output of `apt-get dist-upgrade`. The packages are on indented lines below the heading.
+fn parse_held_back(output: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    let mut in_list = false;
+    for line in output.lines() {
+        if line.starts_with(' ') {
+            if in_list {
+                packages.extend(line.split_whitespace().map(String::from));
+            }
+        } else {
+            in_list = line.starts_with("The following packages have been kept back")
+                || line.starts_with("The following upgrades have been deferred due to phasing");
+        }
+    }
+    packages
+}
+
+/// List the packages `apt-get autoremove` would remove.
+fn autoremovable_debian() -> Vec<String> {
+    Command::new("apt-get")
+        .args(["--simulate", "autoremove"])
+        .output_checked_utf8()
+        .map(|output| {
+            // Lines look like `Remv foo [1.0]`.
+            output
+                .stdout
+                .lines()
+                .filter_map(|line| line.strip_prefix("Remv "))
+                .filter_map(|line| line.split_whitespace().next())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub fn run_deb_get(ctx: &ExecutionContext) -> Result<()> {
     let deb_get = require("deb-get")?;
 
@@ -845,7 +1034,25 @@ pub fn run_needrestart(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator(t!("Check for needed restarts"));
 
-    ctx.run_type().execute(sudo).arg(needrestart).status_checked()?;
+    ctx.run_type().execute(sudo).arg(&needrestart).status_checked()?;
+
+    // Batch mode reports the kernel status as `NEEDRESTART-KSTA: 3`, 2 and 3 meaning that a
+    // newer kernel than the running one is installed.
+    if !ctx.run_type().dry() {
+        let output = ctx
+            .run_type()
+            .execute(sudo)
+            .arg(&needrestart)
+            .args(["-b", "-k"])
+            .output_checked_utf8()?;
+        if output
+            .stdout
+            .lines()
+            .any(|line| matches!(line.strip_prefix("NEEDRESTART-KSTA: "), Some("2" | "3")))
+        {
+            ctx.require_reboot();
+        }
+    }
 
     Ok(())
 }
@@ -880,7 +1087,7 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
 pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
     let flatpak = require("flatpak")?;
     let sudo = require_option(ctx.sudo().as_ref(), get_require_sudo_string())?;
-    let cleanup = ctx.config().cleanup();
+    let remove_unused = ctx.config().cleanup() || ctx.config().flatpak_remove_unused();
     let yes = ctx.config().yes(Step::Flatpak);
     let run_type = ctx.run_type();
     print_separator("Flatpak User Packages");
@@ -891,15 +1098,18 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
     }
     run_type.execute(&flatpak).args(&update_args).status_checked()?;
 
-    if cleanup {
-        let mut cleanup_args = vec!["uninstall", "--user", "--unused"];
-        if yes {
-            cleanup_args.push("-y");
-        }
-        run_type.execute(&flatpak).args(&cleanup_args).status_checked()?;
+    if remove_unused {
+        let installation = crate::breaking_changes::data_dir().join("flatpak");
+        remove_unused_flatpaks(ctx, &installation, || run_type.execute(&flatpak), "--user")?;
     }
 
     print_separator(t!("Flatpak System Packages"));
+    let installation = Command::new(&flatpak)
+        .arg("--installations")
+        .output_checked_utf8()
+        .ok()
+        .and_then(|output| output.stdout.lines().next().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("/var/lib/flatpak"));
     if ctx.config().flatpak_use_sudo() || std::env::var("SSH_CLIENT").is_ok() {
         let mut update_args = vec!["update", "--system"];
         if yes {
@@ -910,16 +1120,17 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
             .arg(&flatpak)
             .args(&update_args)
             .status_checked()?;
-        if cleanup {
-            let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
-            if yes {
-                cleanup_args.push("-y");
-            }
-            run_type
-                .execute(sudo)
-                .arg(flatpak)
-                .args(&cleanup_args)
-                .status_checked()?;
+        if remove_unused {
+            remove_unused_flatpaks(
+                ctx,
+                &installation,
+                || {
+                    let mut command = run_type.execute(sudo);
+                    command.arg(&flatpak);
+                    command
+                },
+                "--system",
+            )?;
         }
     } else {
         let mut update_args = vec!["update", "--system"];
@@ -927,18 +1138,54 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
             update_args.push("-y");
         }
         run_type.execute(&flatpak).args(&update_args).status_checked()?;
-        if cleanup {
-            let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
-            if yes {
-                cleanup_args.push("-y");
-            }
-            run_type.execute(flatpak).args(&cleanup_args).status_checked()?;
+        if remove_unused {
+            remove_unused_flatpaks(ctx, &installation, || run_type.execute(&flatpak), "--system")?;
         }
     }
 
     Ok(())
 }
 
+/// Uninstall the runtimes and extensions no app of `installation` uses anymore, and print how
+/// much disk space this reclaimed.
+fn remove_unused_flatpaks(
+    ctx: &ExecutionContext,
+    installation: &Path,
+    flatpak: impl Fn() -> Executor,
+    installation_flag: &str,
+) -> Result<()> {
+    let mut cleanup_args = vec!["uninstall", installation_flag, "--unused"];
+    if ctx.config().yes(Step::Flatpak) {
+        cleanup_args.push("-y");
+    }
+
+    let size_before = (!ctx.run_type().dry()).then(|| disk_usage(installation)).flatten();
+    flatpak().args(&cleanup_args).status_checked()?;
+
+    if let Some((before, after)) = size_before.and_then(|before| Some((before, disk_usage(installation)?))) {
+        println!(
+            "{}",
+            t!(
+                "Reclaimed {size} MiB",
+                size = format!("{:.1}", before.saturating_sub(after) as f64 / (1024.0 * 1024.0))
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// The disk usage of `path` in bytes, counting hard links once like Flatpak's OSTree
+/// repository uses them.
+fn disk_usage(path: &Path) -> Option<u64> {
+    Command::new("du")
+        .arg("-sb")
+        .arg(path)
+        .output_checked_utf8()
+        .ok()
+        .and_then(|output| output.stdout.split_whitespace().next()?.parse().ok())
+}
+
 pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), get_require_sudo_string())?;
     let snap = require("snap")?;
@@ -1000,6 +1247,89 @@ pub fn run_distrobox_update(ctx: &ExecutionContext) -> Result<()> {
     .status_checked()
 }
 
+/// The names of the distrobox containers.
+fn list_distroboxes(distrobox: &Path) -> Result<Vec<String>> {
+    let output = Command::new(distrobox)
+        .args(["list", "--no-color"])
+        .output_checked_utf8()?;
+
+    Ok(output
+        .stdout
+        .lines()
+        // The first line holds the column names: ID | NAME | STATUS | IMAGE
+        .skip(1)
+        .filter_map(|line| line.split('|').nth(1))
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .collect())
+}
+
+/// Find the tool managing each of the `containers` to run Topgrade in, see
+/// `containers_to_topgrade`. The step tells whether it is a distrobox or a toolbx container.
+pub fn find_topgrade_containers(containers: &[String]) -> Vec<(Step, String)> {
+    let distroboxes = require("distrobox")
+        .and_then(|distrobox| list_distroboxes(&distrobox))
+        .unwrap_or_default();
+    let toolboxes = require("toolbox")
+        .and_then(|toolbx| crate::steps::toolbx::list_toolboxes(&toolbx))
+        .unwrap_or_default();
+    debug!("Distroboxes: {:?}, toolboxes: {:?}", distroboxes, toolboxes);
+
+    containers
+        .iter()
+        .filter_map(|container| {
+            if distroboxes.contains(container) {
+                Some((Step::Distrobox, container.clone()))
+            } else if toolboxes.contains(container) {
+                Some((Step::Toolbx, container.clone()))
+            } else {
+                print_warning(t!(
+                    "Container {container} was not found by distrobox or toolbx",
+                    container = container
+                ));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Run the Topgrade installed in a distrobox or toolbx container, see `find_topgrade_containers`.
+pub fn run_container_topgrade(ctx: &ExecutionContext, step: Step, container: &str) -> Result<()> {
+    let (tool, enter): (PathBuf, Vec<&str>) = if step == Step::Distrobox {
+        let mut enter = vec!["enter"];
+        if ctx.config().distrobox_root() {
+            enter.push("--root");
+        }
+        enter.extend([container, "--"]);
+        (require("distrobox")?, enter)
+    } else {
+        (require("toolbox")?, vec!["run", "-c", container])
+    };
+
+    Command::new(&tool)
+        .args(&enter)
+        .args(["sh", "-c", "command -v topgrade"])
+        .output_checked_utf8()
+        .map_err(|_| NotInstalled(t!("Topgrade is not installed in {container}", container = container).to_string()))?;
+
+    print_separator(t!("Topgrade in {container}", container = container));
+
+    let prefix = format!("TOPGRADE_PREFIX={container}");
+    let mut command = ctx.run_type().execute(&tool);
+    // The container shares the home and runtime directories holding the lock of this run.
+    command
+        .args(&enter)
+        .args(["env", &prefix, "topgrade", "--skip-notify", "--no-lock"]);
+    if ctx.config().verbose() {
+        command.arg("-v");
+    }
+    if ctx.config().yes(step) {
+        command.arg("--yes");
+    }
+
+    command.status_checked()
+}
+
 pub fn run_dkp_pacman_update(ctx: &ExecutionContext) -> Result<()> {
     let sudo = require_option(ctx.sudo().as_ref(), get_require_sudo_string())?;
     let dkp_pacman = require("dkp-pacman")?;
@@ -1325,4 +1655,25 @@ mod tests {
     fn test_cachyos() {
         test_template(include_str!("os_release/cachyos"), Distribution::Arch);
     }
+
+    #[test]
+    fn test_parse_held_back() {
+        let output = "Reading package lists...
+Building dependency tree...
+Calculating upgrade...
+The following packages have been kept back:
+  linux-generic linux-headers-generic
+The following upgrades have been deferred due to phasing:
+  gnome-shell
+The following packages will be upgraded:
+  curl libcurl4
+2 upgraded, 0 newly installed, 0 to remove and 3 not upgraded.
+Inst curl [8.5.0-2ubuntu10.1] (8.5.0-2ubuntu10.2 Ubuntu:24.04/noble-updates [amd64])
+";
+        assert_eq!(
+            parse_held_back(output),
+            vec!["linux-generic", "linux-headers-generic", "gnome-shell"]
+        );
+        assert!(parse_held_back("0 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.\n").is_empty());
+    }
 }
@@ -1,4 +1,5 @@
-use crate::command::CommandExt;
+use crate::command::{CommandExt, Utf8Output};
+use crate::config::SoftwareUpdates;
 use crate::execution_context::ExecutionContext;
 use crate::terminal::{print_separator, prompt_yesno};
 use crate::utils::{get_require_sudo_string, require_option};
@@ -43,10 +44,18 @@ pub fn run_mas(ctx: &ExecutionContext) -> Result<()> {
 pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
     print_separator(t!("macOS system update"));
 
+    let include_config_data = ctx.config().softwareupdate_include_config_data();
+    if ctx.config().dry_run() {
+        // Listing the updates doesn't change anything, so show what would be installed.
+        println!("{}", t!("Finding available software"));
+        let output = list_updates(include_config_data)?;
+        print!("{}{}", output.stdout, output.stderr);
+    }
+
     let should_ask = !(ctx.config().yes(Step::System) || ctx.config().dry_run());
     if should_ask {
         println!("{}", t!("Finding available software"));
-        if system_update_available()? {
+        if system_update_available(include_config_data)? {
             let answer = prompt_yesno(t!("A system update is available. Do you wish to install it?").as_ref())?;
             if !answer {
                 return Ok(());
@@ -59,7 +68,19 @@ pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
     }
 
     let mut command = ctx.run_type().execute("softwareupdate");
-    command.args(["--install", "--all"]);
+    command.arg("--install");
+    match ctx.config().softwareupdate_updates() {
+        SoftwareUpdates::All => command.arg("--all"),
+        SoftwareUpdates::Recommended => command.arg("--recommended"),
+    };
+
+    if include_config_data {
+        command.arg("--include-config-data");
+    }
+
+    if ctx.config().softwareupdate_restart() {
+        command.arg("--restart");
+    }
 
     if should_ask {
         command.arg("--no-scan");
@@ -68,12 +89,23 @@ pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
     command.status_checked()
 }
 
-fn system_update_available() -> Result<bool> {
-    let output = Command::new("softwareupdate").arg("--list").output_checked_utf8()?;
+fn list_updates(include_config_data: bool) -> Result<Utf8Output> {
+    let mut command = Command::new("softwareupdate");
+    command.arg("--list");
+    if include_config_data {
+        command.arg("--include-config-data");
+    }
+    let output = command.output_checked_utf8()?;
 
     debug!("{:?}", output);
 
-    Ok(!output.stderr.contains("No new software available"))
+    Ok(output)
+}
+
+fn system_update_available(include_config_data: bool) -> Result<bool> {
+    Ok(!list_updates(include_config_data)?
+        .stderr
+        .contains("No new software available"))
 }
 
 pub fn run_sparkle(ctx: &ExecutionContext) -> Result<()> {
@@ -3,6 +3,7 @@ use crate::{output_changed_message, Step, HOME_DIR};
 use color_eyre::eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
+use console::style;
 use home;
 use ini::Ini;
 #[cfg(target_os = "linux")]
@@ -28,7 +29,10 @@ use crate::execution_context::ExecutionContext;
 use crate::executor::Executor;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use crate::executor::RunType;
-use crate::terminal::print_separator;
+use crate::explain;
+use crate::terminal::{print_separator, print_warning};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::utils::{count_updated_packages, parse_package_versions};
 use crate::utils::{get_require_sudo_string, require, require_option, PathExt};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -274,6 +278,43 @@ pub fn upgrade_gnome_extensions(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+/// Rebuild the databases of `man` and `locate`, as enabled in `[maintenance]`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn run_maintenance(ctx: &ExecutionContext) -> Result<()> {
+    let tasks = [
+        (
+            ctx.config().maintenance_man_db(),
+            if cfg!(target_os = "macos") {
+                "/usr/libexec/makewhatis"
+            } else {
+                "mandb"
+            },
+        ),
+        (
+            ctx.config().maintenance_locate(),
+            if cfg!(target_os = "macos") {
+                "/usr/libexec/locate.updatedb"
+            } else {
+                "updatedb"
+            },
+        ),
+    ];
+    if tasks.iter().all(|(enabled, _)| !enabled) {
+        return Err(SkipStep(t!("No maintenance task is enabled").to_string()).into());
+    }
+
+    print_separator(t!("System maintenance"));
+
+    for (_, name) in tasks.into_iter().filter(|(enabled, _)| *enabled) {
+        match crate::utils::which(name) {
+            Some(binary) => ctx.execute_elevated(&binary, false)?.status_checked()?,
+            None => print_warning(t!("{name} is not installed", name = name)),
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 pub fn brew_linux_sudo_uid() -> Option<u32> {
     let linuxbrew_directory = "/home/linuxbrew/.linuxbrew";
@@ -333,8 +374,29 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
     print_separator(variant.step_title());
     let run_type = ctx.run_type();
 
+    if run_type.explain() {
+        let output = variant
+            .execute(RunType::Wet)
+            .args(["outdated", "--formula", "--quiet"])
+            .output_checked_utf8()?;
+        let packages: Vec<&str> = output.stdout.lines().collect();
+        explain::print_upgradable(variant.step_title(), &packages);
+        return Ok(());
+    }
+
     variant.execute(run_type).arg("update").status_checked()?;
 
+    // Compare the installed versions before and after, to tell how many formulae got updated.
+    let installed_formulae = || {
+        variant
+            .execute(RunType::Wet)
+            .args(["list", "--versions", "--formula"])
+            .output_checked_utf8()
+            .map(|output| parse_package_versions(&output.stdout))
+            .ok()
+    };
+    let installed = if run_type.dry() { None } else { installed_formulae() };
+
     let mut command = variant.execute(run_type);
     command.args(["upgrade", "--formula"]);
 
@@ -344,6 +406,12 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
 
     command.status_checked()?;
 
+    if let Some(before) = installed {
+        if let Some(after) = installed_formulae() {
+            ctx.record_updated_packages(count_updated_packages(&before, &after));
+        }
+    }
+
     if ctx.config().cleanup() {
         variant.execute(run_type).arg("cleanup").status_checked()?;
     }
@@ -355,6 +423,42 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
     Ok(())
 }
 
+pub fn run_brew_bundle(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    #[allow(unused_variables)]
+    let binary_name = require(variant.binary_name())?;
+
+    #[cfg(target_os = "macos")]
+    {
+        if variant.is_path() && !BrewVariant::is_macos_custom(binary_name) {
+            return Err(SkipStep(t!("Not a custom brew for macOS").to_string()).into());
+        }
+    }
+
+    let brewfile = require_option(
+        ctx.config().brew_bundle_file(),
+        t!("No Brewfile configured").to_string(),
+    )?;
+    let brewfile = PathBuf::from(shellexpand::tilde(brewfile).into_owned()).require()?;
+    let file_arg = format!("--file={}", brewfile.display());
+
+    print_separator(format!("{} - Bundle", variant.step_title()));
+    let run_type = ctx.run_type();
+
+    variant
+        .execute(run_type)
+        .args(["bundle", "install", &file_arg])
+        .status_checked()?;
+
+    if ctx.config().brew_bundle_cleanup() {
+        variant
+            .execute(run_type)
+            .args(["bundle", "cleanup", "--force", &file_arg])
+            .status_checked()?;
+    }
+
+    Ok(())
+}
+
 #[cfg(target_os = "macos")]
 pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
     let binary_name = require(variant.binary_name())?;
@@ -622,6 +726,63 @@ fn nix_profile_dir(nix: &Path) -> Result<Option<PathBuf>> {
     )
 }
 
+/// Update the inputs of the flakes in `nix_flake_dirs`.
+pub fn run_nix_flake(ctx: &ExecutionContext) -> Result<()> {
+    let dirs = ctx
+        .config()
+        .nix_flake_dirs()
+        .ok_or_else(|| SkipStep(t!("No flake directories configured").to_string()))?;
+    let nix = require("nix")?;
+
+    let dirs: Vec<&Path> = dirs
+        .iter()
+        .map(Path::new)
+        .filter(|dir| {
+            let has_flake = dir.join("flake.nix").is_file();
+            if !has_flake {
+                print_warning(t!("{dir} does not contain a flake.nix", dir = dir.display()));
+            }
+            has_flake
+        })
+        .collect();
+    if dirs.is_empty() {
+        return Err(SkipStep(t!("None of the flake directories contains a flake.nix").to_string()).into());
+    }
+
+    print_separator(t!("Nix flakes"));
+
+    // `nix flake update` can't tell what it would update without fetching the inputs, so dry
+    // runs only print the commands like everywhere else.
+    let mut failed = Vec::new();
+    for dir in dirs {
+        let result = ctx
+            .run_type()
+            .execute(&nix)
+            .current_dir(dir)
+            .args(nix_args())
+            .args(["--extra-experimental-features", "flakes", "flake", "update"])
+            .status_checked();
+
+        match result {
+            Ok(()) => println!("{} {}", style(t!("Updated")).green().bold(), dir.display()),
+            Err(e) => {
+                println!("{} {}", style(t!("Failed")).red().bold(), dir.display());
+                debug!("Failed to update the flake in {}: {e:?}", dir.display());
+                failed.push(dir.display().to_string());
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(t!(
+            "Failed to update the flakes in {dirs}",
+            dirs = failed.join(", ")
+        )))
+    }
+}
+
 /// Returns a directory from an environment variable, if and only if it is a directory which
 /// contains a flake.nix
 fn flake_dir(var: &'static str) -> Option<PathBuf> {
@@ -754,6 +915,12 @@ pub fn run_asdf(ctx: &ExecutionContext) -> Result<()> {
             .status_checked_with_codes(&[42])?;
     }
 
+    if !ctx.config().asdf_update_plugins() {
+        return Ok(());
+    }
+
+    // The Go rewrite (>= 0.16.0) dropped the hyphenated `plugin-update` command, but
+    // `plugin update` works with every version since 0.8.0
     ctx.run_type()
         .execute(&asdf)
         .args(["plugin", "update", "--all"])
@@ -765,10 +932,12 @@ pub fn run_mise(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("mise");
 
-    ctx.run_type()
-        .execute(&mise)
-        .args(["plugins", "update"])
-        .status_checked()?;
+    if ctx.config().mise_update_plugins() {
+        ctx.run_type()
+            .execute(&mise)
+            .args(["plugins", "update"])
+            .status_checked()?;
+    }
 
     ctx.run_type().execute(&mise).arg("upgrade").status_checked()
 }
@@ -802,25 +971,163 @@ pub fn run_pearl(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(pearl).arg("update").status_checked()
 }
 
-pub fn run_pyenv(ctx: &ExecutionContext) -> Result<()> {
-    let pyenv = require("pyenv")?;
-    print_separator("pyenv");
+/// A version manager of the pyenv family, which share their commands and layout.
+pub struct VersionManager {
+    name: &'static str,
+    /// The variable overriding where it is installed, `~/.{name}` by default.
+    root_variable: &'static str,
+    /// The arguments of `install` listing every version it can install.
+    list_args: &'static [&'static str],
+}
+
+pub const PYENV: VersionManager = VersionManager {
+    name: "pyenv",
+    root_variable: "PYENV_ROOT",
+    list_args: &["--list"],
+};
+
+pub const RBENV: VersionManager = VersionManager {
+    name: "rbenv",
+    root_variable: "RBENV_ROOT",
+    // `--list` only lists the latest version of each Ruby implementation.
+    list_args: &["--list-all"],
+};
+
+pub const NODENV: VersionManager = VersionManager {
+    name: "nodenv",
+    root_variable: "NODENV_ROOT",
+    list_args: &["--list"],
+};
+
+/// Parse a version like `3.12.1`, leaving out prereleases and other implementations, e.g.
+/// `3.13.0a1` or `pypy3.10-7.3.15`.
+fn parse_release(version: &str) -> Option<Version> {
+    Version::parse(version.trim())
+        .ok()
+        .filter(|version| version.pre.is_empty() && version.build.is_empty())
+}
+
+/// The newest patch release of each installed minor version, paired with the installed
+/// version it replaces, if it isn't installed yet.
+fn newer_patches(installed: &[Version], available: &[Version]) -> Vec<(Version, Version)> {
+    let mut upgrades: Vec<(Version, Version)> = Vec::new();
+    for version in installed {
+        let Some(latest) = available
+            .iter()
+            .filter(|available| available.major == version.major && available.minor == version.minor)
+            .max()
+        else {
+            continue;
+        };
+        if latest > version && !installed.contains(latest) && !upgrades.iter().any(|(_, upgrade)| upgrade == latest) {
+            upgrades.push((version.clone(), latest.clone()));
+        }
+    }
+    upgrades
+}
+
+/// Install the latest patch release of each minor version `manager` has installed, and
+/// make them global in place of the versions they replace if `set_global`.
+fn install_latest_patches(ctx: &ExecutionContext, manager: &VersionManager, binary: &Path) -> Result<()> {
+    let versions = |args: &[&str]| -> Result<Vec<Version>> {
+        let output = Command::new(binary).args(args).output_checked_utf8()?;
+        Ok(output.stdout.lines().filter_map(parse_release).collect())
+    };
+    let installed = versions(&["versions", "--bare"])?;
+    let available = versions(&[&["install"], manager.list_args].concat())?;
+
+    let upgrades = newer_patches(&installed, &available);
+    if upgrades.is_empty() {
+        println!("{}", t!("The installed versions are the latest patch releases"));
+        return Ok(());
+    }
+
+    for (old, new) in &upgrades {
+        ctx.run_type()
+            .execute(binary)
+            .args(["install", "--skip-existing", &new.to_string()])
+            .status_checked()?;
+        if !ctx.run_type().dry() {
+            println!("{} {old} -> {new}", manager.name);
+        }
+    }
+
+    if ctx.config().version_manager_set_global() {
+        let output = Command::new(binary).arg("global").output_checked_utf8()?;
+        let global: Vec<String> = output.stdout.split_whitespace().map(str::to_owned).collect();
+        let updated: Vec<String> = global
+            .iter()
+            .map(|version| {
+                upgrades
+                    .iter()
+                    .find(|(old, _)| old.to_string() == *version)
+                    .map_or_else(|| version.clone(), |(_, new)| new.to_string())
+            })
+            .collect();
+        if updated != global {
+            ctx.run_type()
+                .execute(binary)
+                .arg("global")
+                .args(&updated)
+                .status_checked()?;
+            if !ctx.run_type().dry() {
+                println!(
+                    "{}",
+                    t!(
+                        "Global {name} version: {versions}",
+                        name = manager.name,
+                        versions = updated.join(" ")
+                    )
+                );
+            }
+        }
+    }
 
-    let pyenv_dir = var("PYENV_ROOT").map_or_else(|_| HOME_DIR.join(".pyenv"), PathBuf::from);
+    Ok(())
+}
 
-    if !pyenv_dir.exists() {
-        return Err(SkipStep(t!("Pyenv is installed, but $PYENV_ROOT is not set correctly").to_string()).into());
+/// Update `manager` itself with its update plugin, then install the latest patch releases
+/// if `version_manager_latest_patch` is set.
+pub fn run_version_manager(ctx: &ExecutionContext, manager: &VersionManager) -> Result<()> {
+    let binary = require(manager.name)?;
+    let latest_patch = ctx.config().version_manager_latest_patch();
+
+    let root = var(manager.root_variable).map_or_else(|_| HOME_DIR.join(format!(".{}", manager.name)), PathBuf::from);
+    if !root.exists() {
+        return Err(SkipStep(
+            t!(
+                "{name} is installed, but ${variable} is not set correctly",
+                name = manager.name,
+                variable = manager.root_variable
+            )
+            .to_string(),
+        )
+        .into());
     }
 
-    if !pyenv_dir.join(".git").exists() {
-        return Err(SkipStep(t!("pyenv is not a git repository").to_string()).into());
+    let skip_update = if !root.join(".git").exists() {
+        Some(t!("{name} is not a git repository", name = manager.name))
+    } else if !root.join("plugins").join(format!("{}-update", manager.name)).exists() {
+        Some(t!("{name}-update plugin is not installed", name = manager.name))
+    } else {
+        None
+    };
+    if let (Some(reason), false) = (&skip_update, latest_patch) {
+        return Err(SkipStep(reason.to_string()).into());
     }
 
-    if !pyenv_dir.join("plugins").join("pyenv-update").exists() {
-        return Err(SkipStep(t!("pyenv-update plugin is not installed").to_string()).into());
+    print_separator(manager.name);
+
+    match skip_update {
+        Some(reason) => debug!("Not updating {}: {reason}", manager.name),
+        None => ctx.run_type().execute(&binary).arg("update").status_checked()?,
     }
 
-    ctx.run_type().execute(pyenv).arg("update").status_checked()
+    if latest_patch {
+        install_latest_patches(ctx, manager, &binary)?;
+    }
+
+    Ok(())
 }
 
 pub fn run_sdkman(ctx: &ExecutionContext) -> Result<()> {
@@ -922,3 +1229,41 @@ pub fn reboot() -> Result<()> {
 
     Command::new("sudo").arg("reboot").status_checked()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions
+            .iter()
+            .map(|version| Version::parse(version).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_release() {
+        assert_eq!(parse_release(" 3.12.1\n"), Some(Version::new(3, 12, 1)));
+        assert_eq!(parse_release("3.13.0-rc1"), None);
+        assert_eq!(parse_release("3.13.0a1"), None);
+        assert_eq!(parse_release("pypy3.10-7.3.15"), None);
+        assert_eq!(parse_release("3.12"), None);
+    }
+
+    #[test]
+    fn test_newer_patches() {
+        let installed = versions(&["3.11.4", "3.12.1", "3.12.7", "3.13.0"]);
+        let available = versions(&["3.11.4", "3.11.9", "3.12.1", "3.12.7", "3.12.8", "3.13.0", "3.14.0"]);
+        assert_eq!(
+            newer_patches(&installed, &available),
+            vec![
+                (Version::new(3, 11, 4), Version::new(3, 11, 9)),
+                (Version::new(3, 12, 1), Version::new(3, 12, 8)),
+            ]
+        );
+
+        // The latest patch releases are installed already.
+        let installed = versions(&["3.11.9", "3.12.8"]);
+        assert!(newer_patches(&installed, &available).is_empty());
+    }
+}
@@ -1,15 +1,16 @@
 use std::path::Path;
 use std::{ffi::OsStr, process::Command};
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use etcetera::base_strategy::BaseStrategy;
 use tracing::debug;
 
 use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
+use crate::executor::ExecutorOutput;
 use crate::terminal::{print_separator, print_warning};
 use crate::utils::{require, which};
-use crate::{error::SkipStep, steps::git::RepoStep};
+use crate::{error::is_skipped, error::SkipStep, steps::git::RepoStep};
 use crate::{powershell, Step};
 use rust_i18n::t;
 
@@ -40,6 +41,14 @@ pub fn run_chocolatey(ctx: &ExecutionContext) -> Result<()> {
 pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
     let winget = require("winget")?;
 
+    let exclude = ctx.config().winget_exclude();
+    if let Some(id) = exclude
+        .iter()
+        .find(|id| id.is_empty() || id.contains(char::is_whitespace))
+    {
+        return Err(eyre!(t!("Invalid winget package ID: {id}", id = id)));
+    }
+
     print_separator("winget");
 
     ctx.run_type()
@@ -47,27 +56,58 @@ pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
         .args(["source", "update"])
         .status_checked()?;
 
+    // `winget upgrade` has no way to leave packages out, but `--all` skips pinned ones. Adding
+    // a pin fails if the package is pinned already, those pins are left alone.
+    let mut pinned = Vec::new();
+    for id in exclude {
+        let added = match ctx
+            .run_type()
+            .execute(&winget)
+            .args(["pin", "add", "--id", id.as_str(), "--exact"])
+            .output()?
+        {
+            ExecutorOutput::Wet(output) => output.status.success(),
+            ExecutorOutput::Dry => true,
+        };
+        if added {
+            pinned.push(id);
+        } else {
+            debug!("Not pinning {id}, it is pinned already or not installed");
+        }
+    }
+
     let mut command = if ctx.config().winget_use_sudo() {
         match ctx.sudo() {
             Some(sudo) => {
                 let mut command = ctx.run_type().execute(sudo);
-                command.arg(winget);
+                command.arg(&winget);
                 command
             }
-            None => ctx.run_type().execute(winget),
+            None => ctx.run_type().execute(&winget),
         }
     } else {
-        ctx.run_type().execute(winget)
+        ctx.run_type().execute(&winget)
     };
 
     let mut args = vec!["upgrade", "--all"];
+    if let Some(source) = ctx.config().winget_source() {
+        args.extend(["--source", source.as_str()]);
+    }
     if ctx.config().winget_silent_install() {
         args.push("--silent");
     }
 
-    command.args(args).status_checked()?;
+    let result = command.args(args).status_checked();
 
-    Ok(())
+    // The pins added above are only meant for this run.
+    let unpinned = pinned.iter().try_for_each(|id| {
+        ctx.run_type()
+            .execute(&winget)
+            .args(["pin", "remove", "--id", id.as_str(), "--exact"])
+            .status_checked()
+    });
+
+    result.and(unpinned)
 }
 
 pub fn run_scoop(ctx: &ExecutionContext) -> Result<()> {
@@ -210,7 +250,7 @@ pub fn run_wsl_topgrade(ctx: &ExecutionContext) -> Result<()> {
         let result = upgrade_wsl_distribution(&wsl, &distribution, ctx);
         debug!("Upgrading {:?}: {:?}", distribution, result);
         if let Err(e) = result {
-            if e.is::<SkipStep>() {
+            if is_skipped(&e) {
                 continue;
             }
         }
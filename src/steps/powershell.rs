@@ -184,7 +184,18 @@ mod windows {
         // Pass the command string using the -Command flag
         powershell
             .build_command_internal(ctx, &["-Command", &command_str])?
-            .status_checked()
+            .status_checked()?;
+
+        if !ctx.run_type().dry() {
+            let reboot_required = powershell
+                .build_command_internal(ctx, &["-Command", "Get-WURebootStatus -Silent"])?
+                .output_checked_utf8()?;
+            if reboot_required.stdout.trim() == "True" {
+                ctx.require_reboot();
+            }
+        }
+
+        Ok(())
     }
 
     pub fn microsoft_store(powershell: &Powershell, ctx: &ExecutionContext) -> Result<()> {
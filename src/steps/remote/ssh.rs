@@ -1,27 +1,128 @@
+#[cfg(unix)]
+use std::fs;
+use std::process::Command;
+
 use color_eyre::eyre::Result;
 use rust_i18n::t;
+#[cfg(unix)]
+use tempfile::TempDir;
+#[cfg(unix)]
+use tracing::debug;
 
 use crate::{
-    command::CommandExt, error::SkipStep, execution_context::ExecutionContext, terminal::print_separator, utils,
+    command::CommandExt,
+    config::{RemoteTopgrade, Step},
+    error::SkipStep,
+    execution_context::ExecutionContext,
+    terminal::print_separator,
+    utils,
 };
 
-fn prepare_async_ssh_command(args: &mut Vec<&str>) {
-    args.insert(0, "ssh");
-    args.push("--keep");
+/// A directory of `ssh` control sockets, so that remotes on the same host share a connection
+/// for the rest of the run. See `ssh_control_master`.
+///
+/// The connections are closed and the directory removed when this is dropped.
+#[cfg(unix)]
+pub struct ControlMaster {
+    dir: TempDir,
 }
 
-pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
-    let ssh = utils::require("ssh")?;
+#[cfg(unix)]
+impl ControlMaster {
+    pub fn new() -> Result<Self> {
+        // Socket paths are limited to about 100 bytes, which the temporary directory on macOS
+        // can almost fill up by itself.
+        let dir = tempfile::Builder::new().prefix("topgrade-ssh-").tempdir_in("/tmp")?;
+        Ok(Self { dir })
+    }
 
-    let topgrade = ctx.config().remote_topgrade_path();
-    let mut args = vec!["-t", hostname];
+    /// The `ssh` options sharing connections through this directory.
+    fn ssh_options(&self) -> Vec<String> {
+        vec![
+            String::from("-o"),
+            String::from("ControlMaster=auto"),
+            String::from("-o"),
+            // `%C` is a hash of the host, port and user, which keeps the path short.
+            format!("ControlPath={}/%C", self.dir.path().display()),
+            String::from("-o"),
+            String::from("ControlPersist=yes"),
+        ]
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ControlMaster {
+    fn drop(&mut self) {
+        let Ok(sockets) = fs::read_dir(self.dir.path()) else {
+            return;
+        };
+        for socket in sockets.flatten() {
+            // The host is required but unused, since the socket is given directly.
+            let result = Command::new("ssh")
+                .arg("-o")
+                .arg(format!("ControlPath={}", socket.path().display()))
+                .args(["-O", "exit", "topgrade"])
+                .output_checked();
+            if let Err(e) = result {
+                debug!("Failed to close the ssh connection {}: {e:?}", socket.path().display());
+            }
+        }
+    }
+}
+
+fn prepare_async_ssh_command(args: &mut Vec<String>) {
+    args.insert(0, String::from("ssh"));
+    args.push(String::from("--keep"));
+}
+
+/// The arguments to `ssh` running Topgrade on `remote`.
+fn ssh_arguments(ctx: &ExecutionContext, remote: &RemoteTopgrade) -> Vec<String> {
+    let hostname = remote.host();
+    let mut args = remote.ssh_options();
+    args.extend([String::from("-t"), hostname.to_owned()]);
 
     if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-        args.extend(ssh_arguments.split_whitespace());
+        args.extend(ssh_arguments.split_whitespace().map(String::from));
     }
 
-    let env = format!("TOPGRADE_PREFIX={hostname}");
-    args.extend(["env", &env, "$SHELL", "-lc", topgrade]);
+    let mut topgrade = vec![ctx.config().remote_topgrade_path()];
+    topgrade.extend(forwarded_flags(ctx));
+    args.extend([
+        String::from("env"),
+        format!("TOPGRADE_PREFIX={hostname}"),
+        String::from("$SHELL"),
+        String::from("-lc"),
+        // `ssh` joins its arguments with spaces, the remote shell splits them up again.
+        shell_words::quote(&topgrade.join(" ")).into_owned(),
+    ]);
+
+    args
+}
+
+/// The flags of this run the remote Topgrade gets as well: `--dry-run` when dry running,
+/// and `--yes` when the remotes step is run with it.
+fn forwarded_flags(ctx: &ExecutionContext) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if ctx.run_type().dry() {
+        flags.push("--dry-run");
+    }
+    if ctx.config().yes(Step::Remotes) {
+        flags.push("--yes");
+    }
+    flags
+}
+
+/// Run Topgrade on `remote`, sharing connections through `control_master` if given.
+pub fn ssh_step(
+    ctx: &ExecutionContext,
+    remote: &RemoteTopgrade,
+    #[cfg(unix)] control_master: Option<&ControlMaster>,
+) -> Result<()> {
+    let ssh = utils::require("ssh")?;
+    ctx.require_online()?;
+
+    let hostname = remote.host();
+    let mut args = ssh_arguments(ctx, remote);
 
     if ctx.config().run_in_tmux() && !ctx.run_type().dry() {
         #[cfg(unix)]
@@ -38,18 +139,21 @@ pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
         ctx.run_type().execute("wt").args(&args).spawn()?;
         Err(SkipStep(String::from(t!("Remote Topgrade launched in an external terminal"))).into())
     } else {
-        let mut args = vec!["-t", hostname];
-
-        if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-            args.extend(ssh_arguments.split_whitespace());
-        }
-
-        let env = format!("TOPGRADE_PREFIX={hostname}");
-        args.extend(["env", &env, "$SHELL", "-lc", topgrade]);
-
         print_separator(format!("Remote ({hostname})"));
         println!("{}", t!("Connecting to {hostname}...", hostname = hostname));
 
-        ctx.run_type().execute(ssh).args(&args).status_checked()
+        // Only here, since the shared connection is closed at the end of the run, while remotes
+        // launched in tmux or another terminal may still be using it.
+        #[cfg(unix)]
+        if let Some(control_master) = control_master {
+            args.splice(0..0, control_master.ssh_options());
+        }
+
+        // When dry running, the remote Topgrade dry runs as well, so it is safe to connect.
+        if ctx.run_type().dry() {
+            Command::new(ssh).args(&args).status_checked()
+        } else {
+            ctx.run_type().execute(ssh).args(&args).status_checked()
+        }
     }
 }
@@ -179,6 +179,7 @@ pub fn topgrade_vagrant_box(ctx: &ExecutionContext, vagrant_box: &VagrantBox) ->
     let vagrant = Vagrant {
         path: utils::require("vagrant")?,
     };
+    ctx.require_online()?;
 
     let seperator = format!("Vagrant ({})", vagrant_box.smart_name());
     let mut _poweron = None;
@@ -210,6 +211,7 @@ pub fn topgrade_vagrant_box(ctx: &ExecutionContext, vagrant_box: &VagrantBox) ->
 
 pub fn upgrade_vagrant_boxes(ctx: &ExecutionContext) -> Result<()> {
     let vagrant = utils::require("vagrant")?;
+    ctx.require_online()?;
     print_separator(t!("Vagrant boxes"));
 
     let outdated = Command::new(&vagrant)
@@ -10,10 +10,11 @@ use etcetera::base_strategy::BaseStrategy;
 use crate::command::CommandExt;
 use crate::config::TmuxConfig;
 use crate::config::TmuxSessionMode;
-use crate::terminal::print_separator;
+use crate::error::SkipStep;
+use crate::terminal::{print_separator, print_warning};
 use crate::{
     execution_context::ExecutionContext,
-    utils::{which, PathExt},
+    utils::{require, which, PathExt},
 };
 use crate::{HOME_DIR, XDG_DIRS};
 
@@ -46,11 +47,59 @@ pub fn run_tpm(ctx: &ExecutionContext) -> Result<()> {
     }
     .require()?;
 
+    require("tmux")?;
+    let tmux = Tmux::new(Vec::new());
+    let running = tmux.is_running()?;
+    if !running && !ctx.config().tmux_force_plugin_update() {
+        return Err(SkipStep(t!("No tmux server is running").to_string()).into());
+    }
+
     print_separator("tmux plugins");
 
+    // A session of our own is only needed to start a server.
+    let _session = if running {
+        None
+    } else {
+        Some(ThrowawaySession::start(ctx, tmux)?)
+    };
+
     ctx.run_type().execute(tpm).arg("all").status_checked()
 }
 
+/// A detached tmux session running only so that the plugin manager can talk to a server,
+/// killed when dropped.
+struct ThrowawaySession<'a> {
+    ctx: &'a ExecutionContext<'a>,
+    tmux: Tmux,
+    name: String,
+}
+
+impl<'a> ThrowawaySession<'a> {
+    fn start(ctx: &'a ExecutionContext<'a>, tmux: Tmux) -> Result<Self> {
+        let name = format!("topgrade-tpm-{}", std::process::id());
+        ctx.run_type()
+            .execute(&tmux.tmux)
+            .args(["new-session", "-d", "-s", &name])
+            .status_checked()
+            .context("Failed to start a tmux session")?;
+        Ok(Self { ctx, tmux, name })
+    }
+}
+
+impl Drop for ThrowawaySession<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self
+            .ctx
+            .run_type()
+            .execute(&self.tmux.tmux)
+            .args(["kill-session", "-t", &self.name])
+            .status_checked()
+        {
+            print_warning(format!("{e:#}"));
+        }
+    }
+}
+
 struct Tmux {
     tmux: PathBuf,
     args: Option<Vec<String>>,
@@ -72,6 +121,16 @@ impl Tmux {
         command
     }
 
+    /// Tell whether a tmux server is running.
+    fn is_running(&self) -> Result<bool> {
+        Ok(self
+            .build()
+            .arg("list-sessions")
+            .output_checked_with(|_| Ok(()))?
+            .status
+            .success())
+    }
+
     fn has_session(&self, session_name: &str) -> Result<bool> {
         Ok(self
             .build()
@@ -8,7 +8,7 @@ use std::path::Path;
 use std::{path::PathBuf, process::Command};
 use tracing::debug;
 
-fn list_toolboxes(toolbx: &Path) -> Result<Vec<String>> {
+pub fn list_toolboxes(toolbx: &Path) -> Result<Vec<String>> {
     let output = Command::new(toolbx)
         .args(["list", "--containers"])
         .output_checked_utf8()?;
@@ -54,6 +54,8 @@ pub fn run_toolbx(ctx: &ExecutionContext) -> Result<()> {
             "system",
             "--no-self-update",
             "--skip-notify",
+            // The container shares the runtime directory holding the lock of this run.
+            "--no-lock",
         ];
         if ctx.config().yes(Step::Toolbx) {
             args.push("--yes");
@@ -4,8 +4,9 @@ use crate::HOME_DIR;
 use color_eyre::eyre::Result;
 use etcetera::base_strategy::BaseStrategy;
 
+use crate::config::NeovimPluginManager;
 use crate::executor::{Executor, ExecutorOutput};
-use crate::terminal::print_separator;
+use crate::terminal::{print_info, print_separator};
 use crate::{
     execution_context::ExecutionContext,
     utils::{require, PathExt},
@@ -27,17 +28,65 @@ pub fn vimrc() -> Result<PathBuf> {
         .or_else(|_| HOME_DIR.join(".vim/vimrc").require())
 }
 
-fn nvimrc() -> Result<PathBuf> {
+/// The directory Neovim reads its configuration from.
+fn nvim_config_dir() -> PathBuf {
     #[cfg(unix)]
-    let base_dir = crate::XDG_DIRS.config_dir();
+    return crate::XDG_DIRS.config_dir().join("nvim");
 
     #[cfg(windows)]
-    let base_dir = crate::WINDOWS_DIRS.cache_dir();
+    return crate::WINDOWS_DIRS.cache_dir().join("nvim");
+}
+
+/// The directory Neovim keeps its data in, `stdpath("data")`, where plugin managers install
+/// themselves.
+fn nvim_data_dir() -> PathBuf {
+    #[cfg(unix)]
+    return crate::XDG_DIRS.data_dir().join("nvim");
+
+    #[cfg(windows)]
+    return crate::WINDOWS_DIRS.cache_dir().join("nvim-data");
+}
 
-    base_dir
-        .join("nvim/init.vim")
+fn nvimrc() -> Result<PathBuf> {
+    let config_dir = nvim_config_dir();
+
+    config_dir
+        .join("init.vim")
         .require()
-        .or_else(|_| base_dir.join("nvim/init.lua").require())
+        .or_else(|_| config_dir.join("init.lua").require())
+}
+
+/// The plugin manager of the Neovim configuration, found from the files it leaves in the
+/// configuration directory or from where it is installed.
+fn detect_plugin_manager() -> Option<NeovimPluginManager> {
+    let config_dir = nvim_config_dir();
+    let data_dir = nvim_data_dir();
+
+    let managers = [
+        (
+            NeovimPluginManager::Lazy,
+            [config_dir.join("lazy-lock.json"), data_dir.join("lazy/lazy.nvim")],
+        ),
+        (
+            NeovimPluginManager::Packer,
+            [
+                config_dir.join("plugin/packer_compiled.lua"),
+                data_dir.join("site/pack/packer/start/packer.nvim"),
+            ],
+        ),
+        (
+            NeovimPluginManager::VimPlug,
+            [
+                config_dir.join("autoload/plug.vim"),
+                data_dir.join("site/autoload/plug.vim"),
+            ],
+        ),
+    ];
+
+    managers
+        .into_iter()
+        .find(|(_, paths)| paths.iter().any(|path| path.exists()))
+        .map(|(manager, _)| manager)
 }
 
 fn upgrade_script() -> Result<tempfile::NamedTempFile> {
@@ -130,16 +179,49 @@ pub fn upgrade_neovim(ctx: &ExecutionContext) -> Result<()> {
     let nvim = require("nvim")?;
     let nvimrc = nvimrc()?;
 
+    let manager = match ctx.config().neovim_plugin_manager() {
+        NeovimPluginManager::Auto => detect_plugin_manager().unwrap_or(NeovimPluginManager::Script),
+        manager => manager,
+    };
+    debug!("Upgrading Neovim plugins with {manager:?}");
+
     print_separator("Neovim");
-    upgrade(
-        ctx.run_type()
-            .execute(nvim)
-            .args(["-u"])
-            .arg(nvimrc)
-            .args(["--headless", "-V1", "-nS"])
-            .arg(upgrade_script()?.path()),
-        ctx,
-    )
+
+    let mut command = ctx.run_type().execute(nvim);
+    match manager {
+        NeovimPluginManager::Lazy => {
+            print_info(t!("Plugin manager: {manager}", manager = "lazy.nvim"));
+            command.args(["--headless", "+Lazy! sync", "+qa"]);
+        }
+        NeovimPluginManager::Packer => {
+            print_info(t!("Plugin manager: {manager}", manager = "packer.nvim"));
+            command.args([
+                "--headless",
+                "-c",
+                "autocmd User PackerComplete quitall",
+                "-c",
+                "PackerSync",
+            ]);
+        }
+        NeovimPluginManager::VimPlug => {
+            print_info(t!("Plugin manager: {manager}", manager = "vim-plug"));
+            let update = if ctx.config().force_vim_plug_update() {
+                "+PlugUpdate!"
+            } else {
+                "+PlugUpdate"
+            };
+            command.args(["--headless", "+PlugUpgrade", update, "+qa"]);
+        }
+        NeovimPluginManager::Auto | NeovimPluginManager::Script => {
+            command
+                .args(["-u"])
+                .arg(nvimrc)
+                .args(["--headless", "-V1", "-nS"])
+                .arg(upgrade_script()?.path());
+        }
+    }
+
+    upgrade(&mut command, ctx)
 }
 
 pub fn run_voom(ctx: &ExecutionContext) -> Result<()> {
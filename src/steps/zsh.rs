@@ -1,15 +1,18 @@
 use std::env;
+use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
 
 use color_eyre::eyre::Result;
+use rust_i18n::t;
 use tracing::debug;
 use walkdir::WalkDir;
 
 use crate::command::CommandExt;
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
 use crate::git::RepoStep;
-use crate::terminal::print_separator;
+use crate::terminal::{print_info, print_separator};
 use crate::utils::{require, PathExt};
 use crate::HOME_DIR;
 use crate::XDG_DIRS;
@@ -37,6 +40,39 @@ pub fn zshrc() -> PathBuf {
     zdotdir().join(".zshrc")
 }
 
+/// Remove the dumps `compinit` keeps, and start an interactive zsh so that the user's
+/// configuration writes them again with the completions installed since.
+///
+/// Prezto keeps its dump in its cache directory, the other frameworks next to `.zshrc`.
+pub fn run_compinit(ctx: &ExecutionContext) -> Result<()> {
+    if !ctx.config().rebuild_completions() {
+        return Err(SkipStep(t!("Rebuilding shell completions is not enabled").to_string()).into());
+    }
+    let zsh = require("zsh")?;
+
+    let dumps: Vec<PathBuf> = [zdotdir(), XDG_DIRS.cache_dir().join("prezto")]
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with(".zcompdump") || name.starts_with("zcompdump"))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    if dumps.is_empty() {
+        return Err(SkipStep(t!("No zsh completion dump found").to_string()).into());
+    }
+
+    print_separator(t!("zsh completions"));
+
+    ctx.run_type().execute("rm").arg("-f").args(&dumps).status_checked()?;
+    ctx.run_type().execute(zsh).args(["-i", "-c", "exit"]).status_checked()
+}
+
 pub fn run_antidote(ctx: &ExecutionContext) -> Result<()> {
     let zsh = require("zsh")?;
     let mut antidote = zdotdir().join(".antidote").require()?;
@@ -191,6 +227,19 @@ pub fn run_oh_my_zsh(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("oh-my-zsh");
 
+    ctx.run_type()
+        .execute("zsh")
+        .arg(oh_my_zsh.join("tools/upgrade.sh"))
+        // oh-my-zsh returns 80 when it is already updated and no changes pulled
+        // in this update.
+        // See this comment: https://github.com/r-darwish/topgrade/issues/569#issuecomment-736756731
+        // for more information.
+        .status_checked_with_codes(&[80])?;
+
+    if !ctx.config().omz_update_custom() {
+        return Ok(());
+    }
+
     let custom_dir = env::var::<_>("ZSH_CUSTOM")
         .or_else(|_| {
             Command::new("zsh")
@@ -218,13 +267,13 @@ pub fn run_oh_my_zsh(ctx: &ExecutionContext) -> Result<()> {
         custom_repos.insert_if_repo(entry.path());
     }
 
+    // Directories that aren't repositories of their own belong to oh-my-zsh, which
+    // was just updated.
     custom_repos.remove(&oh_my_zsh);
-    ctx.run_type()
-        .execute("zsh")
-        .arg(oh_my_zsh.join("tools/upgrade.sh"))
-        // oh-my-zsh returns 80 when it is already updated and no changes pulled
-        // in this update.
-        // See this comment: https://github.com/r-darwish/topgrade/issues/569#issuecomment-736756731
-        // for more information.
-        .status_checked_with_codes(&[80])
+    if custom_repos.is_repos_empty() {
+        return Ok(());
+    }
+
+    print_info(t!("Pulling the custom plugins and themes"));
+    custom_repos.pull_repos(ctx)
 }
@@ -1,13 +1,19 @@
 use std::ffi::OsStr;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum::AsRefStr;
+use tracing::debug;
 
 use crate::command::CommandExt;
+use crate::ctrlc;
 use crate::execution_context::ExecutionContext;
 use crate::executor::Executor;
 use crate::terminal::print_separator;
@@ -38,9 +44,9 @@ impl Sudo {
 
     /// Get the `sudo` binary for this platform.
     pub fn detect() -> Option<Self> {
-        which("doas")
-            .map(|p| (p, SudoKind::Doas))
-            .or_else(|| which("sudo").map(Self::determine_sudo_variant))
+        which("sudo")
+            .map(Self::determine_sudo_variant)
+            .or_else(|| which("doas").map(|p| (p, SudoKind::Doas)))
             .or_else(|| which("gsudo").map(|p| (p, SudoKind::Gsudo)))
             .or_else(|| which("pkexec").map(|p| (p, SudoKind::Pkexec)))
             .or_else(|| which("run0").map(|p| (p, SudoKind::Run0)))
@@ -113,6 +119,45 @@ impl Sudo {
         cmd.status_checked().wrap_err("Failed to elevate permissions")
     }
 
+    /// Keep the cached `sudo` credentials from expiring until the returned guard is dropped.
+    ///
+    /// Only `sudo` can refresh its credentials without running a command, so this returns
+    /// `None` for every other kind.
+    pub fn keepalive(&self) -> Option<SudoKeepalive> {
+        if !matches!(self.kind, SudoKind::Sudo) {
+            return None;
+        }
+
+        let path = self.path.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || loop {
+            match stopped.recv_timeout(SUDO_KEEPALIVE_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+
+            // Don't get in the way while the user decides what to do after Ctrl+C.
+            if ctrlc::interrupted() {
+                continue;
+            }
+
+            // `-n` makes `sudo` fail instead of asking for a password from the background.
+            let refreshed = Command::new(&path)
+                .args(["-n", "-v"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status_checked()
+                .is_ok();
+            debug!("Refreshed sudo credentials: {refreshed}");
+        });
+
+        Some(SudoKeepalive {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
     /// Execute a command with `sudo`.
     pub fn execute_elevated(&self, ctx: &ExecutionContext, command: &Path, interactive: bool) -> Executor {
         let mut cmd = ctx.run_type().execute(self);
@@ -122,7 +167,17 @@ impl Sudo {
         }
 
         if interactive {
-            cmd.arg("-i");
+            match self.kind {
+                // `doas` has no `-i`, and `doas -s` can't be given a command, so we run the
+                // command through a login shell ourselves.
+                // `$0` and `$@` are the command and arguments added after this.
+                SudoKind::Doas => {
+                    cmd.args(["sh", "-l", "-c", r#"exec "$0" "$@""#]);
+                }
+                _ => {
+                    cmd.arg("-i");
+                }
+            }
         }
 
         cmd.arg(command);
@@ -131,7 +186,27 @@ impl Sudo {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, AsRefStr)]
+/// How often [`Sudo::keepalive`] refreshes the credentials, well within `sudo`'s default
+/// 5 minute timeout.
+const SUDO_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Stops refreshing the `sudo` credentials when dropped, see [`Sudo::keepalive`].
+pub struct SudoKeepalive {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SudoKeepalive {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up, so this doesn't wait for the interval.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, AsRefStr)]
 #[serde(rename_all = "lowercase")]
 #[strum(serialize_all = "lowercase")]
 pub enum SudoKind {
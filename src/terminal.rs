@@ -1,5 +1,9 @@
+use std::cell::{Cell, RefCell};
 use std::cmp::{max, min};
 use std::env;
+use std::fmt;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::{self, Write};
 use std::process::Command;
 use std::sync::{LazyLock, Mutex};
@@ -8,7 +12,7 @@ use std::time::Duration;
 use chrono::{Local, Timelike};
 use color_eyre::eyre;
 use color_eyre::eyre::Context;
-use console::{style, Key, Term};
+use console::{style, Key, Style, Term};
 use notify_rust::{Notification, Timeout};
 use rust_i18n::t;
 use tracing::{debug, error};
@@ -16,10 +20,20 @@ use tracing::{debug, error};
 use which_crate::which;
 
 use crate::command::CommandExt;
-use crate::report::StepResult;
+use crate::config::Step;
+use crate::report::{StepReport, StepResult};
 
 static TERMINAL: LazyLock<Mutex<Terminal>> = LazyLock::new(|| Mutex::new(Terminal::new()));
 
+thread_local! {
+    /// Output of the step running on this thread, if it is being buffered.
+    static CAPTURED: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+
+    /// Position of the step running on this thread among the steps of the run, and how many
+    /// there are, shown in separators as `[N/M]`.
+    static PROGRESS: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
 #[cfg(unix)]
 pub fn shell() -> String {
     env::var("SHELL").unwrap_or_else(|_| "sh".to_string())
@@ -41,6 +55,15 @@ struct Terminal {
     set_title: bool,
     display_time: bool,
     desktop_notification: bool,
+    /// The character separators are drawn with, if not the default one.
+    separator_char: Option<char>,
+    /// The width of separators, 0 meaning the whole width of the terminal.
+    separator_width: usize,
+    separator_style: Style,
+    /// Whether someone is there to answer prompts, see `--no-interactive`.
+    interactive: bool,
+    /// Whether to answer yes to yes/no questions without asking, see `--yes`.
+    assume_yes: bool,
 }
 
 impl Terminal {
@@ -53,9 +76,22 @@ impl Terminal {
             set_title: true,
             display_time: true,
             desktop_notification: false,
+            separator_char: None,
+            separator_width: 80,
+            separator_style: Style::new().bold(),
+            interactive: true,
+            assume_yes: false,
         }
     }
 
+    fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    fn set_assume_yes(&mut self, assume_yes: bool) {
+        self.assume_yes = assume_yes;
+    }
+
     fn set_desktop_notifications(&mut self, desktop_notifications: bool) {
         self.desktop_notification = desktop_notifications;
     }
@@ -68,6 +104,14 @@ impl Terminal {
         self.display_time = display_time;
     }
 
+    fn set_separator(&mut self, character: Option<char>, width: usize, color: Option<&str>) {
+        self.separator_char = character;
+        self.separator_width = width;
+        if let Some(color) = color {
+            self.separator_style = Style::from_dotted_str(color).bold();
+        }
+    }
+
     fn notify_desktop<P: AsRef<str>>(&self, message: P, timeout: Option<Duration>) {
         debug!("Desktop notification: {}", message.as_ref());
         let mut notification = Notification::new();
@@ -82,14 +126,27 @@ impl Terminal {
         notification.show().ok();
     }
 
+    /// Write to the terminal, or to the buffer of the current step if its output is captured.
+    fn write_output(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        if write_captured(fmt::format(args).as_bytes()) {
+            Ok(())
+        } else {
+            self.term.write_fmt(args)
+        }
+    }
+
     fn print_separator<P: AsRef<str>>(&mut self, message: P) {
+        let message = match PROGRESS.with(Cell::get) {
+            Some((position, total)) => format!("[{position}/{total}] {}", message.as_ref()),
+            None => String::from(message.as_ref()),
+        };
+
         if self.set_title {
-            self.term
-                .set_title(format!("{}Topgrade - {}", self.prefix, message.as_ref()));
+            self.term.set_title(format!("{}Topgrade - {}", self.prefix, message));
         }
 
         if self.desktop_notification {
-            self.notify_desktop(message.as_ref(), Some(Duration::from_secs(5)));
+            self.notify_desktop(&message, Some(Duration::from_secs(5)));
         }
 
         let now = Local::now();
@@ -100,35 +157,39 @@ impl Terminal {
                 now.hour(),
                 now.minute(),
                 now.second(),
-                message.as_ref()
+                message
             )
         } else {
-            String::from(message.as_ref())
+            message
         };
 
         match self.width {
             Some(width) => {
-                self.term
-                    .write_fmt(format_args!(
-                        "{}\n",
-                        style(format_args!(
-                            "\n── {} {:─^border$}",
-                            message,
-                            "",
-                            border = max(
-                                2,
-                                min(80, width as usize)
-                                    .checked_sub(4)
-                                    .and_then(|e| e.checked_sub(message.len()))
-                                    .unwrap_or(0)
-                            )
-                        ))
-                        .bold()
-                    ))
+                let separator = self.separator_char.unwrap_or('─');
+                let width = match self.separator_width {
+                    0 => width as usize,
+                    separator_width => min(separator_width, width as usize),
+                };
+                let border = max(
+                    2,
+                    width
+                        .checked_sub(4)
+                        .and_then(|e| e.checked_sub(message.len()))
+                        .unwrap_or(0),
+                );
+                let line = format!(
+                    "\n{separator}{separator} {message} {}",
+                    separator.to_string().repeat(border)
+                );
+                self.write_output(format_args!("{}\n", self.separator_style.apply_to(line)))
                     .ok();
             }
             None => {
-                self.term.write_fmt(format_args!("―― {message} ――\n")).ok();
+                let separator = self.separator_char.unwrap_or('―');
+                self.write_output(format_args!(
+                    "{separator}{separator} {message} {separator}{separator}\n"
+                ))
+                .ok();
             }
         }
     }
@@ -137,50 +198,88 @@ impl Terminal {
     fn print_error<P: AsRef<str>, Q: AsRef<str>>(&mut self, key: Q, message: P) {
         let key = key.as_ref();
         let message = message.as_ref();
-        self.term
-            .write_fmt(format_args!(
-                "{} {}",
-                style(format!("{}", t!("{key} failed:", key = key))).red().bold(),
-                message
-            ))
-            .ok();
+        self.write_output(format_args!(
+            "{} {}",
+            style(format!("{}", t!("{key} failed:", key = key))).red().bold(),
+            message
+        ))
+        .ok();
     }
 
     #[allow(dead_code)]
     fn print_warning<P: AsRef<str>>(&mut self, message: P) {
         let message = message.as_ref();
-        self.term
-            .write_fmt(format_args!("{}\n", style(message).yellow().bold()))
+        self.write_output(format_args!("{}\n", style(message).yellow().bold()))
             .ok();
     }
 
+    fn print_line<P: AsRef<str>>(&mut self, message: P) {
+        self.write_output(format_args!("{}\n", message.as_ref())).ok();
+    }
+
     #[allow(dead_code)]
     fn print_info<P: AsRef<str>>(&mut self, message: P) {
         let message = message.as_ref();
-        self.term
-            .write_fmt(format_args!("{}\n", style(message).blue().bold()))
+        self.write_output(format_args!("{}\n", style(message).blue().bold()))
             .ok();
     }
 
-    fn print_result<P: AsRef<str>>(&mut self, key: P, result: &StepResult) {
-        let key = key.as_ref();
+    fn print_result(&mut self, step: &StepReport) {
+        let result = match &step.result {
+            StepResult::Success => format!("{}", style(t!("OK")).bold().green()),
+            StepResult::Failure(_) => format!("{}", style(t!("FAILED")).bold().red()),
+            StepResult::Ignored => format!("{}", style(t!("IGNORED")).bold().yellow()),
+            StepResult::Skipped(reason) => format!("{}: {}", style(t!("SKIPPED")).bold().blue(), reason),
+            StepResult::NotInstalled(reason) => {
+                format!("{}: {}", style(t!("NOT INSTALLED")).bold().dim(), reason)
+            }
+            StepResult::TimedOut => format!("{}", style(t!("TIMED OUT")).bold().red()),
+        };
 
-        self.term
-            .write_fmt(format_args!(
-                "{}: {}\n",
-                key,
-                match result {
-                    StepResult::Success => format!("{}", style(t!("OK")).bold().green()),
-                    StepResult::Failure => format!("{}", style(t!("FAILED")).bold().red()),
-                    StepResult::Ignored => format!("{}", style(t!("IGNORED")).bold().yellow()),
-                    StepResult::Skipped(reason) => format!("{}: {}", style(t!("SKIPPED")).bold().blue(), reason),
-                }
-            ))
-            .ok();
+        let mut line = format!("{}: {}", step.key, result);
+        if step.attempts > 1 {
+            line.push_str(&format!(" ({})", t!("{attempts} attempts", attempts = step.attempts)));
+        }
+        if let Some(count) = step.updated_packages {
+            line.push_str(&format!(" ({})", t!("{count} packages updated", count = count)));
+        }
+        if self.display_time {
+            line.push_str(&format!(
+                " {}",
+                style(format!("[{}]", format_duration(step.duration))).dim()
+            ));
+        }
+
+        self.write_output(format_args!("{line}\n")).ok();
+    }
+
+    fn print_total_time(&mut self, elapsed: Duration) {
+        self.write_output(format_args!(
+            "{}\n",
+            style(t!("Total time: {time}", time = format_duration(elapsed))).bold()
+        ))
+        .ok();
     }
 
     #[allow(dead_code)]
     fn prompt_yesno(&mut self, question: &str) -> Result<bool, io::Error> {
+        // Takes precedence over `--no-interactive`, which only stands for the safe default.
+        if self.assume_yes {
+            debug!("Answering yes to {question:?}");
+            self.term
+                .write_line(&format!(
+                    "{} y",
+                    style(format!("{question} {}", t!("(Y)es/(N)o"))).yellow().bold()
+                ))
+                .ok();
+            return Ok(true);
+        }
+
+        if !self.interactive {
+            debug!("Answering no to {question:?} in non-interactive mode");
+            return Ok(false);
+        }
+
         self.term
             .write_fmt(format_args!(
                 "{}",
@@ -198,7 +297,7 @@ impl Terminal {
     }
     #[allow(unused_variables)]
     fn should_retry(&mut self, interrupted: bool, step_name: &str) -> eyre::Result<bool> {
-        if self.width.is_none() {
+        if self.width.is_none() || !self.interactive {
             return Ok(false);
         }
 
@@ -247,6 +346,77 @@ impl Terminal {
         answer
     }
 
+    /// Let the user toggle `steps` in a checklist, all of them checked at first.
+    ///
+    /// Returns `None` when the user aborts with Ctrl-C.
+    fn select_steps(&mut self, steps: &[Step]) -> io::Result<Option<Vec<Step>>> {
+        if self.width.is_none() || !self.interactive || steps.is_empty() {
+            return Ok(Some(steps.to_vec()));
+        }
+
+        let mut checked = vec![true; steps.len()];
+        let mut cursor = 0;
+        // Leave room for the instructions and the line the cursor ends up on.
+        let height = (self.term.size().0 as usize).saturating_sub(2).clamp(1, steps.len());
+        let mut top = 0;
+
+        self.term.write_line(&format!(
+            "{}",
+            style(t!("Select the steps to run (Space: toggle, Enter: confirm)"))
+                .yellow()
+                .bold()
+        ))?;
+        self.term.hide_cursor()?;
+
+        let selected = loop {
+            if cursor < top {
+                top = cursor;
+            } else if cursor >= top + height {
+                top = cursor + 1 - height;
+            }
+
+            for index in top..top + height {
+                let line = format!(
+                    "{} [{}] {}",
+                    if index == cursor { ">" } else { " " },
+                    if checked[index] { "x" } else { " " },
+                    steps[index].as_ref()
+                );
+                if index == cursor {
+                    self.term.write_line(&format!("{}", style(line).bold()))?;
+                } else {
+                    self.term.write_line(&line)?;
+                }
+            }
+
+            let key = self.term.read_key()?;
+            self.term.clear_last_lines(height)?;
+            match key {
+                Key::ArrowUp | Key::Char('k') => cursor = cursor.checked_sub(1).unwrap_or(steps.len() - 1),
+                Key::ArrowDown | Key::Char('j') => cursor = (cursor + 1) % steps.len(),
+                Key::Home => cursor = 0,
+                Key::End => cursor = steps.len() - 1,
+                Key::Char(' ') => checked[cursor] = !checked[cursor],
+                Key::Enter => {
+                    break Some(
+                        steps
+                            .iter()
+                            .zip(&checked)
+                            .filter(|(_, checked)| **checked)
+                            .map(|(step, _)| *step)
+                            .collect(),
+                    )
+                }
+                Key::CtrlC => break None,
+                _ => (),
+            }
+        };
+
+        self.term.clear_last_lines(1)?;
+        self.term.show_cursor()?;
+        Ok(selected)
+    }
+
     fn get_char(&self) -> Result<Key, io::Error> {
         self.term.read_key()
     }
@@ -276,13 +446,149 @@ pub fn print_warning<P: AsRef<str>>(message: P) {
     TERMINAL.lock().unwrap().print_warning(message);
 }
 
+/// Print a line of output of the current step, which is buffered with the rest of its output
+/// when it runs alongside other steps, unlike `println!`.
+pub fn print_line<P: AsRef<str>>(message: P) {
+    TERMINAL.lock().unwrap().print_line(message);
+}
+
 #[allow(dead_code)]
 pub fn print_info<P: AsRef<str>>(message: P) {
     TERMINAL.lock().unwrap().print_info(message);
 }
 
-pub fn print_result<P: AsRef<str>>(key: P, result: &StepResult) {
-    TERMINAL.lock().unwrap().print_result(key, result);
+pub fn print_result(step: &StepReport) {
+    TERMINAL.lock().unwrap().print_result(step);
+}
+
+/// Print the wall-clock time of the whole run at the bottom of the summary.
+pub fn print_total_time(elapsed: Duration) {
+    TERMINAL.lock().unwrap().print_total_time(elapsed);
+}
+
+/// Format a duration for the summary, e.g. `4.2s`, `3m 07s` or `1h 02m 03s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}h {:02}m {:02}s", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+/// Run `f`, buffering what it prints through this module and the output of the commands it runs
+/// instead of writing it to the terminal. Returns the result of `f` along with the buffered output.
+pub fn capture_output<R>(f: impl FnOnce() -> R) -> (R, Vec<u8>) {
+    CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let output = CAPTURED.with(|captured| captured.take()).unwrap_or_default();
+
+    (result, output)
+}
+
+/// Set the position of the step running on this thread, see `PROGRESS`.
+pub fn set_progress(progress: Option<(usize, usize)>) {
+    PROGRESS.with(|cell| cell.set(progress));
+}
+
+/// Tells whether the output of the current thread is being buffered by `capture_output`.
+pub fn is_capturing() -> bool {
+    CAPTURED.with(|captured| captured.borrow().is_some())
+}
+
+/// Append `bytes` to the buffered output of the current thread. Returns `false` if the output
+/// is not being buffered.
+pub fn write_captured(bytes: &[u8]) -> bool {
+    CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(buffer) => {
+            buffer.extend_from_slice(bytes);
+            true
+        }
+        None => false,
+    })
+}
+
+/// The `[key]` tag in front of the output lines of a step, in a color picked from its name.
+pub fn step_tag(key: &str) -> String {
+    const COLORS: [console::Color; 5] = [
+        console::Color::Cyan,
+        console::Color::Magenta,
+        console::Color::Yellow,
+        console::Color::Blue,
+        console::Color::Green,
+    ];
+
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    let color = COLORS[(hasher.finish() % COLORS.len() as u64) as usize];
+    style(format!("[{key}]")).fg(color).to_string()
+}
+
+/// Print the buffered output of a step all at once, prefixing every line with the step name,
+/// colored like `step_tag` when `colored` is set.
+pub fn print_captured(key: &str, output: &[u8], colored: bool) {
+    let _terminal = TERMINAL.lock().unwrap();
+    let mut stdout = io::stdout().lock();
+    let prefix = if colored {
+        step_tag(key)
+    } else {
+        style(format!("[{key}]")).dim().to_string()
+    };
+    for line in String::from_utf8_lossy(output).lines() {
+        writeln!(stdout, "{prefix} {line}").ok();
+    }
+    stdout.flush().ok();
+}
+
+/// Point stdout at stderr, so that everything Topgrade and the commands it runs print ends up
+/// on stderr, and return a handle to the original stdout.
+#[cfg(unix)]
+pub fn take_stdout() -> io::Result<File> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    use nix::unistd::{dup, dup2};
+
+    io::stdout().flush()?;
+    let stdout = dup(io::stdout().as_raw_fd())?;
+    dup2(io::stderr().as_raw_fd(), io::stdout().as_raw_fd())?;
+
+    // SAFETY: `stdout` was just duplicated and nothing else owns it.
+    Ok(unsafe { File::from_raw_fd(stdout) })
+}
+
+/// Point stdout at stderr, so that everything Topgrade and the commands it runs print ends up
+/// on stderr, and return a handle to the original stdout.
+#[cfg(windows)]
+pub fn take_stdout() -> io::Result<File> {
+    use std::os::windows::io::FromRawHandle;
+
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::processenv::{GetStdHandle, SetStdHandle};
+    use winapi::um::winbase::{STD_ERROR_HANDLE, STD_OUTPUT_HANDLE};
+
+    io::stdout().flush()?;
+
+    // SAFETY: we only swap the standard handles of our own process, and the original stdout
+    // handle is no longer owned by anything else once it has been replaced.
+    unsafe {
+        let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
+        if stdout == INVALID_HANDLE_VALUE || SetStdHandle(STD_OUTPUT_HANDLE, GetStdHandle(STD_ERROR_HANDLE)) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(File::from_raw_handle(stdout.cast()))
+    }
+}
+
+/// Look at the terminal right away, its size and whether it supports colors, so that it is still
+/// known once stdout no longer points at it.
+#[cfg(unix)]
+pub fn probe_terminal() {
+    LazyLock::force(&TERMINAL);
+    console::colors_enabled();
+    console::colors_enabled_stderr();
 }
 
 /// Tells whether the terminal is dumb.
@@ -290,6 +596,13 @@ pub fn is_dumb() -> bool {
     TERMINAL.lock().unwrap().width.is_none()
 }
 
+/// Let the user pick which of `steps` to run, see `--interactive-select`.
+///
+/// Returns `None` when the user aborts with Ctrl-C.
+pub fn select_steps(steps: &[Step]) -> io::Result<Option<Vec<Step>>> {
+    TERMINAL.lock().unwrap().select_steps(steps)
+}
+
 pub fn get_key() -> Result<Key, io::Error> {
     TERMINAL.lock().unwrap().get_char()
 }
@@ -298,6 +611,11 @@ pub fn set_title(set_title: bool) {
     TERMINAL.lock().unwrap().set_title(set_title);
 }
 
+/// Change how separators look, see `separator_char`, `separator_width` and `separator_color`.
+pub fn set_separator(character: Option<char>, width: usize, color: Option<&str>) {
+    TERMINAL.lock().unwrap().set_separator(character, width, color);
+}
+
 pub fn set_desktop_notifications(desktop_notifications: bool) {
     TERMINAL
         .lock()
@@ -314,6 +632,27 @@ pub fn notify_desktop<P: AsRef<str>>(message: P, timeout: Option<Duration>) {
     TERMINAL.lock().unwrap().notify_desktop(message, timeout);
 }
 
+/// Set whether someone is there to answer prompts. When not, prompts are skipped and take
+/// their safe default: no retry, "no" to yes/no questions, and every step selected.
+pub fn set_interactive(interactive: bool) {
+    TERMINAL.lock().unwrap().set_interactive(interactive);
+}
+
+/// Set whether yes/no questions are answered with yes without asking.
+pub fn set_assume_yes(assume_yes: bool) {
+    TERMINAL.lock().unwrap().set_assume_yes(assume_yes);
+}
+
+/// Tell whether yes/no questions are answered with yes without asking.
+pub fn assumes_yes() -> bool {
+    TERMINAL.lock().unwrap().assume_yes
+}
+
+/// Tell whether someone is there to answer prompts.
+pub fn is_interactive() -> bool {
+    TERMINAL.lock().unwrap().interactive
+}
+
 pub fn display_time(display_time: bool) {
     TERMINAL.lock().unwrap().display_time(display_time);
 }
@@ -0,0 +1,77 @@
+//! Recording when each step ran, to look at in a trace viewer, see `--trace-out`.
+
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{Context, Result};
+use rust_i18n::t;
+use serde::Serialize;
+
+use crate::config::Step;
+use crate::terminal::print_info;
+
+/// A complete event of the Chrome trace event format, which chrome://tracing and Perfetto
+/// open.
+#[derive(Debug, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    /// `X` for a complete event, that has both a start and a duration.
+    ph: &'static str,
+    /// Start of the step since the recording started, in microseconds.
+    ts: u128,
+    /// In microseconds.
+    dur: u128,
+    pid: u32,
+    /// The steps run by `run_queued` get their own row, the others share row 0.
+    tid: usize,
+    args: TraceArgs,
+}
+
+#[derive(Debug, Serialize)]
+struct TraceArgs {
+    step: Step,
+    outcome: &'static str,
+}
+
+/// When the recording started, and the steps run so far, if `--trace-out` was given.
+static RECORDING: Mutex<Option<(Instant, Vec<TraceEvent>)>> = Mutex::new(None);
+
+/// Start recording when each step runs.
+pub fn start_recording() {
+    *RECORDING.lock().unwrap() = Some((Instant::now(), Vec::new()));
+}
+
+/// Record that the step `name` ran from `started` for `duration`. `id` tells apart steps
+/// running at the same time, see `runner::run_once`.
+pub fn record(step: Step, name: &str, id: usize, started: Instant, duration: Duration, outcome: &'static str) {
+    if let Some((start, events)) = RECORDING.lock().unwrap().as_mut() {
+        events.push(TraceEvent {
+            name: name.to_owned(),
+            cat: "step",
+            ph: "X",
+            ts: started.saturating_duration_since(*start).as_micros(),
+            dur: duration.as_micros(),
+            pid: process::id(),
+            tid: id,
+            args: TraceArgs { step, outcome },
+        });
+    }
+}
+
+/// Write the recorded events to `path`.
+pub fn save(path: &Path) -> Result<()> {
+    let events = RECORDING
+        .lock()
+        .unwrap()
+        .take()
+        .map(|(_, events)| events)
+        .unwrap_or_default();
+    fs::write(path, serde_json::to_string_pretty(&events)?)
+        .with_context(|| format!("Failed to write the trace to {}", path.display()))?;
+    print_info(t!("Saved the trace to {path}", path = path.display()));
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::env;
 use std::ffi::OsStr;
 use std::fmt::Debug;
@@ -16,7 +17,7 @@ use tracing_subscriber::{registry, EnvFilter};
 
 use crate::command::CommandExt;
 use crate::config::DEFAULT_LOG_LEVEL;
-use crate::error::SkipStep;
+use crate::error::{NotInstalled, SkipStep};
 
 pub trait PathExt
 where
@@ -97,7 +98,7 @@ pub fn require<T: AsRef<OsStr> + Debug>(binary_name: T) -> Result<PathBuf> {
             Ok(path)
         }
         Err(e) => match e {
-            which_crate::Error::CannotFindBinaryPath => Err(SkipStep(format!(
+            which_crate::Error::CannotFindBinaryPath => Err(NotInstalled(format!(
                 "{}",
                 t!(
                     "Cannot find {binary_name} in PATH",
@@ -121,7 +122,7 @@ pub fn require_one<T: AsRef<OsStr> + Debug>(binary_names: impl IntoIterator<Item
         }
     }
 
-    Err(SkipStep(format!(
+    Err(NotInstalled(format!(
         "{}",
         t!(
             "Cannot find any of {binary_names} in PATH",
@@ -151,6 +152,24 @@ pub fn string_prepend_str(string: &mut String, s: &str) {
     *string = new_string;
 }
 
+/// Parse a list of installed packages with one package per line, its name followed by its
+/// version, as printed by `pacman -Q` or `brew list --versions`.
+pub fn parse_package_versions(list: &str) -> HashMap<String, String> {
+    list.lines()
+        .filter_map(|line| line.trim().split_once(char::is_whitespace))
+        .map(|(name, version)| (name.to_owned(), version.trim().to_owned()))
+        .collect()
+}
+
+/// How many of the packages installed `before` have another version `after`. Packages
+/// installed or removed in between don't count.
+pub fn count_updated_packages(before: &HashMap<String, String>, after: &HashMap<String, String>) -> u64 {
+    after
+        .iter()
+        .filter(|(name, version)| before.get(*name).is_some_and(|old| old != *version))
+        .count() as u64
+}
+
 #[cfg(target_family = "unix")]
 pub fn hostname() -> Result<String> {
     match nix::unistd::gethostname() {
@@ -170,6 +189,9 @@ pub fn hostname() -> Result<String> {
 }
 
 pub mod merge_strategies {
+    use std::hash::Hash;
+
+    use indexmap::IndexMap;
     use merge::Merge;
 
     use crate::config::Commands;
@@ -212,9 +234,20 @@ pub mod merge_strategies {
     }
 
     pub fn commands_merge_opt(left: &mut Option<Commands>, right: Option<Commands>) {
+        index_map_merge_opt(left, right);
+    }
+
+    /// Extends left with the entries of right, entries of left win on conflicting keys, like
+    /// the values already set win with the other strategies
+    pub fn index_map_merge_opt<K, V>(left: &mut Option<IndexMap<K, V>>, right: Option<IndexMap<K, V>>)
+    where
+        K: Hash + Eq,
+    {
         if let Some(ref mut left_inner) = left {
             if let Some(right_inner) = right {
-                left_inner.extend(right_inner);
+                for (key, value) in right_inner {
+                    left_inner.entry(key).or_insert(value);
+                }
             }
         } else {
             *left = right;
@@ -317,3 +350,26 @@ macro_rules! output_changed_message {
         )
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_versions() {
+        let versions = parse_package_versions("bash 5.2.037-1\n  git   2.48.1 2.47.0\n\nnot-a-package\n");
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions["bash"], "5.2.037-1");
+        assert_eq!(versions["git"], "2.48.1 2.47.0");
+        assert!(!versions.contains_key("not-a-package"));
+    }
+
+    #[test]
+    fn test_count_updated_packages() {
+        let before = parse_package_versions("bash 5.2.037-1\ngit 2.47.0\nvim 9.1\nremoved 1.0\n");
+        let after = parse_package_versions("bash 5.2.037-2\ngit 2.48.1\nvim 9.1\ninstalled 1.0\n");
+        assert_eq!(count_updated_packages(&before, &after), 2);
+        assert_eq!(count_updated_packages(&before, &before), 0);
+        assert_eq!(count_updated_packages(&HashMap::new(), &after), 0);
+    }
+}